@@ -0,0 +1,95 @@
+//! Cost of enriching a mutation call with a large response, against the previous
+//! approach of converting the whole response with `serde_json::to_value` and
+//! cloning the call's value out of it.
+//!
+//! Run with `cargo bench --bench enrichment`.
+
+use apollo_router::graphql;
+use serde_json::json;
+use serde_json_bytes::Value as BytesValue;
+use starstuff::plugins::{
+    kurrent_mapper::MutationCall,
+    mutation_plugin::{EnrichmentOptions, calls_to_persist},
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+/// Counts allocations so the two approaches can be compared by allocated bytes,
+/// not only by time.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const ITERATIONS: u32 = 200;
+
+fn large_response() -> BytesValue {
+    let payments: Vec<_> = (0..5_000)
+        .map(|i| {
+            json!({
+                "id": format!("payment-{i}"),
+                "amount": i * 100,
+                "status": "SCHEDULED",
+                "dueDate": "2026-01-01"
+            })
+        })
+        .collect();
+    serde_json_bytes::to_value(json!({
+        "recordLoanRequested": { "loanId": "loan-1", "schedule": payments }
+    }))
+    .unwrap()
+}
+
+fn call() -> MutationCall {
+    MutationCall {
+        field_name: "recordLoanRequested".to_string(),
+        ..Default::default()
+    }
+}
+
+fn measure(name: &str, mut run: impl FnMut()) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    let started = Instant::now();
+    for _ in 0..ITERATIONS {
+        run();
+    }
+    let elapsed = started.elapsed() / ITERATIONS;
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / ITERATIONS as usize;
+    let bytes = (ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes) / ITERATIONS as usize;
+    println!("{name:<24} {elapsed:>12?}/iter {allocations:>8} allocs/iter {bytes:>10} bytes/iter");
+}
+
+fn main() {
+    let data = large_response();
+    let response = graphql::Response::builder().data(data.clone()).build();
+    let options = EnrichmentOptions::default();
+    let calls = vec![call()];
+
+    measure("to_value + clone", || {
+        let converted = serde_json::to_value(&data).unwrap();
+        black_box(converted.get("recordLoanRequested").cloned());
+    });
+    measure("calls_to_persist", || {
+        black_box(calls_to_persist(&calls, &response, &options));
+    });
+}
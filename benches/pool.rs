@@ -0,0 +1,65 @@
+//! Append throughput of one KurrentDB client against a pool of them, under
+//! concurrent load. Needs a running KurrentDB (`docker compose up kurrentdb`);
+//! `KURRENTDB_CONNECTION_STRING` points it elsewhere.
+//!
+//! Run with `cargo bench --bench pool`.
+
+use futures::stream::{self, StreamExt};
+use starstuff::plugins::kurrent_mapper::{KurrentServiceBuilder, MutationCall, MutationSink};
+use std::{sync::Arc, time::Instant};
+
+const APPENDS: usize = 4_000;
+const CONCURRENCY: usize = 128;
+/// Appends are spread over this many streams, so per-stream ordering does not
+/// serialize them.
+const STREAMS: usize = 32;
+
+fn call(i: usize) -> MutationCall {
+    MutationCall {
+        field_name: format!("benchAppend{}", i % STREAMS),
+        loan_id: Some(format!("loan-{i}")),
+        ..Default::default()
+    }
+}
+
+async fn measure(connection_string: &str, pool_size: usize) {
+    let service = Arc::new(
+        KurrentServiceBuilder::new()
+            .connection_string(connection_string)
+            .stream_prefix(format!("bench-pool{pool_size}-"))
+            .connection_pool_size(pool_size)
+            .build()
+            .await
+            .expect("KurrentDB is reachable"),
+    );
+
+    let started = Instant::now();
+    stream::iter(0..APPENDS)
+        .for_each_concurrent(CONCURRENCY, |i| {
+            let service = service.clone();
+            async move {
+                service
+                    .persist_and_wait(vec![call(i)])
+                    .await
+                    .expect("append succeeded");
+            }
+        })
+        .await;
+    let elapsed = started.elapsed();
+    println!(
+        "pool of {pool_size:<2} {elapsed:>12?} {:>10.0} appends/s",
+        APPENDS as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let connection_string = std::env::var("KURRENTDB_CONNECTION_STRING")
+        .unwrap_or_else(|_| "kurrentdb://localhost:2113?tls=false".to_string());
+    let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+
+    runtime.block_on(async {
+        for pool_size in [1, 2, 4, 8] {
+            measure(&connection_string, pool_size).await;
+        }
+    });
+}
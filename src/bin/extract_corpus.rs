@@ -0,0 +1,37 @@
+//! Prints how each mutation of a directory of operation fixtures would be
+//! persisted (stream, event type, extracted id) as one JSON line, so the effect
+//! of a configuration change can be reviewed as a diff of two runs.
+//!
+//! Usage: `extract_corpus <supergraph.graphql> <plugin-config.json> <fixtures-dir>`
+//!
+//! The configuration is the JSON form of the `starstuff.mutation_plugin` block.
+//! Each `<name>.graphql` operation is run with the variables of `<name>.json`,
+//! if present.
+
+use starstuff::plugins::{corpus::extract_corpus, mutation_plugin::PluginConfig};
+use std::{fs, path::Path};
+use tower::BoxError;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let (Some(supergraph), Some(config), Some(fixtures)) = (args.next(), args.next(), args.next())
+    else {
+        eprintln!("usage: extract_corpus <supergraph.graphql> <plugin-config.json> <fixtures-dir>");
+        std::process::exit(2);
+    };
+
+    if let Err(error) = run(&supergraph, &config, &fixtures) {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
+
+fn run(supergraph: &str, config: &str, fixtures: &str) -> Result<(), BoxError> {
+    let supergraph_sdl = fs::read_to_string(supergraph)?;
+    let config: PluginConfig = serde_json::from_str(&fs::read_to_string(config)?)?;
+
+    for entry in extract_corpus(Path::new(fixtures), &config, &supergraph_sdl)? {
+        println!("{}", serde_json::to_string(&entry)?);
+    }
+    Ok(())
+}
@@ -0,0 +1,44 @@
+//! Reads a mutation stream from the start and prints each persisted `MutationCall`
+//! as one JSON line, e.g. to rebuild a downstream read model.
+//!
+//! Usage: `replay <connection_string> <stream>`
+
+use kurrentdb::{Client, ClientSettings};
+use starstuff::plugins::kurrent_mapper::{ReplayHandler, ReplayedEvent, replay};
+use tower::BoxError;
+
+struct PrintCalls;
+
+#[async_trait::async_trait]
+impl ReplayHandler for PrintCalls {
+    async fn handle(&mut self, event: ReplayedEvent) -> Result<(), BoxError> {
+        println!("{}", serde_json::to_string(&event.call)?);
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let (Some(connection_string), Some(stream)) = (args.next(), args.next()) else {
+        eprintln!("usage: replay <connection_string> <stream>");
+        std::process::exit(2);
+    };
+
+    if let Err(error) = run(&connection_string, &stream).await {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
+
+async fn run(connection_string: &str, stream: &str) -> Result<(), BoxError> {
+    let settings: ClientSettings = connection_string.parse()?;
+    let client = Client::new(settings)?;
+
+    let summary = replay(&client, stream, &mut PrintCalls).await?;
+    eprintln!(
+        "replayed {} event(s), skipped {}",
+        summary.replayed, summary.skipped
+    );
+    Ok(())
+}
@@ -1,4 +1,5 @@
-mod plugins;
+// Linking the library registers its plugins with the router.
+use starstuff as _;
 
 fn main() {
     if let Err(error) = apollo_router::main() {
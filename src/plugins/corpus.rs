@@ -0,0 +1,143 @@
+use serde::Serialize;
+use serde_json_bytes::{ByteString, Map as BytesMap, Value as BytesValue};
+use std::{fs, path::Path};
+use tower::BoxError;
+
+use crate::plugins::kurrent_mapper::{event_type, stream_name};
+use crate::plugins::mutation_plugin::{PluginConfig, extract_mutations};
+
+/// How one mutation of a corpus fixture would be persisted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CorpusEntry {
+    /// File name of the fixture, without `.graphql`.
+    pub fixture: String,
+    pub field_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    pub stream: String,
+    pub event_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loan_id: Option<String>,
+}
+
+/// Extracts the mutations of every `<name>.graphql` operation in `dir`, with the
+/// variables of `<name>.json` if there is one, and names them as `config` would.
+/// Fixtures are read in file name order, so the result can be snapshotted.
+pub fn extract_corpus(
+    dir: &Path,
+    config: &PluginConfig,
+    supergraph_sdl: &str,
+) -> Result<Vec<CorpusEntry>, BoxError> {
+    let options = config.extraction_options(supergraph_sdl);
+    let mut operations = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "graphql")
+        {
+            operations.push(path);
+        }
+    }
+    operations.sort();
+
+    let mut entries = Vec::new();
+    for path in operations {
+        let fixture = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let query = fs::read_to_string(&path)?;
+        let variables = read_variables(&path.with_extension("json"))?;
+        for call in extract_mutations(&query, &variables, &options) {
+            entries.push(CorpusEntry {
+                fixture: fixture.clone(),
+                stream: stream_name(&call, &config.kurrent),
+                event_type: event_type(&call, &config.kurrent),
+                field_name: call.field_name,
+                alias: call.alias,
+                loan_id: call.loan_id,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+fn read_variables(path: &Path) -> Result<BytesMap<ByteString, BytesValue>, BoxError> {
+    if !path.exists() {
+        return Ok(BytesMap::new());
+    }
+    match serde_json::from_str(&fs::read_to_string(path)?)? {
+        BytesValue::Object(variables) => Ok(variables),
+        _ => Err(format!("{} must hold a JSON object of variables", path.display()).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entries(config: serde_json::Value) -> Vec<CorpusEntry> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/corpus");
+        let config: PluginConfig = serde_json::from_value(config).unwrap();
+        extract_corpus(
+            &dir,
+            &config,
+            include_str!("../../supergraph-schema.graphql"),
+        )
+        .unwrap()
+    }
+
+    fn entry(
+        fixture: &str,
+        field_name: &str,
+        alias: Option<&str>,
+        stream: &str,
+        event_type: &str,
+        loan_id: &str,
+    ) -> CorpusEntry {
+        CorpusEntry {
+            fixture: fixture.to_string(),
+            field_name: field_name.to_string(),
+            alias: alias.map(str::to_string),
+            stream: stream.to_string(),
+            event_type: event_type.to_string(),
+            loan_id: Some(loan_id.to_string()),
+        }
+    }
+
+    #[test]
+    fn corpus_fixtures_extract_with_their_variables() {
+        assert_eq!(
+            vec![
+                entry(
+                    "check_credit",
+                    "recordCreditChecked",
+                    None,
+                    "graphql-mutation-recordCreditChecked",
+                    "GraphQL.CheckCredit",
+                    "loan-1",
+                ),
+                entry(
+                    "manual_decision",
+                    "recordLoanManuallyApproved",
+                    Some("approval"),
+                    "graphql-mutation-recordLoanManuallyApproved",
+                    "GraphQL.recordLoanManuallyApproved",
+                    "loan-2",
+                ),
+            ],
+            entries(json!({}))
+        );
+    }
+
+    #[test]
+    fn config_changes_show_in_the_corpus() {
+        let entries = entries(json!({ "name_source": "alias" }));
+
+        assert_eq!("graphql-mutation-approval", entries[1].stream);
+        assert_eq!("GraphQL.approval", entries[1].event_type);
+    }
+}
@@ -0,0 +1,92 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::plugins::field_pattern::FieldPatterns;
+use crate::plugins::kurrent_mapper::MutationCall;
+
+/// Adds to a call what its response reveals. Enrichers run in registration order
+/// on every call with a response value (the call's own field of `data`), after
+/// `skip_when` and before `responseData` is attached.
+pub trait Enricher: fmt::Debug + Send + Sync {
+    fn enrich(&self, call: &mut MutationCall, response: &Value);
+}
+
+/// The built-in enricher: takes the aggregate id (`loan_id`) from the response,
+/// either the whole scalar response or a configured path within it.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseIdEnricher {
+    /// Mutations whose scalar response is the id of the aggregate they created.
+    pub id_from_scalar_response: FieldPatterns,
+    /// Dotted path to the aggregate id within the response, per mutation field.
+    pub id_from_response: HashMap<String, String>,
+}
+
+impl Enricher for ResponseIdEnricher {
+    fn enrich(&self, call: &mut MutationCall, response: &Value) {
+        if let Some(path) = self.id_from_response.get(&call.field_name) {
+            match value_at_path(response, path).and_then(id_string) {
+                Some(id) => {
+                    tracing::debug!(loan_id = %id, mutation = %call.field_name, path = %path, "Extracted loanId from response path");
+                    call.loan_id = Some(id);
+                }
+                None => {
+                    tracing::debug!(mutation = %call.field_name, path = %path, "No id at the configured response path");
+                }
+            }
+        }
+
+        if self.id_from_scalar_response.matches(&call.field_name)
+            && let Some(loan_id) = response.as_str()
+        {
+            call.loan_id = Some(loan_id.to_string());
+            tracing::debug!(loan_id = %loan_id, mutation = %call.field_name, "Extracted loanId from response");
+        }
+    }
+}
+
+pub(crate) fn value_at_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(value, |current, segment| current.get(segment))
+}
+
+/// Ids are strings, but numeric ids are accepted and stringified.
+fn id_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(id) => Some(id.clone()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(field_name: &str) -> MutationCall {
+        MutationCall {
+            field_name: field_name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ids_come_from_the_configured_path_or_the_scalar_response() {
+        let enricher = ResponseIdEnricher {
+            id_from_scalar_response: FieldPatterns::compile(&["recordLoanRequested"]).unwrap(),
+            id_from_response: HashMap::from([("createLoan".to_string(), "loan.id".to_string())]),
+        };
+
+        let mut created = call("createLoan");
+        enricher.enrich(&mut created, &json!({ "loan": { "id": 42 } }));
+        let mut requested = call("recordLoanRequested");
+        enricher.enrich(&mut requested, &json!("loan-1"));
+        let mut other = call("recordCreditChecked");
+        enricher.enrich(&mut other, &json!("loan-2"));
+
+        assert_eq!(Some("42".to_string()), created.loan_id);
+        assert_eq!(Some("loan-1".to_string()), requested.loan_id);
+        assert_eq!(None, other.loan_id);
+    }
+}
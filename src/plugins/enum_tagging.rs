@@ -0,0 +1,163 @@
+use apollo_parser::{
+    Parser,
+    cst::{Definition, OperationDefinition, Type},
+};
+use serde_json::{Map, Value};
+use serde_json_bytes::{ByteString, Map as BytesMap, Value as BytesValue};
+use std::collections::{HashMap, HashSet};
+
+/// Key of the object enum values are wrapped in, e.g. `{ "__enum": "ACTIVE" }`,
+/// so consumers can tell an enum apart from a string.
+pub const ENUM_TAG: &str = "__enum";
+
+pub fn tag_enum(value: &str) -> Value {
+    let mut tagged = Map::new();
+    tagged.insert(ENUM_TAG.to_string(), Value::String(value.to_string()));
+    Value::Object(tagged)
+}
+
+/// Enum and input object types of the supergraph. Enum values arriving through
+/// variables are plain JSON strings, so their declared types are needed to find them.
+#[derive(Debug, Default)]
+pub struct EnumTypes {
+    enums: HashSet<String>,
+    /// Input object type name to its field names and their named types.
+    input_fields: HashMap<String, HashMap<String, String>>,
+}
+
+impl EnumTypes {
+    pub fn from_sdl(sdl: &str) -> Self {
+        let ast = Parser::new(sdl).parse();
+        let mut types = Self::default();
+
+        for def in ast.document().definitions() {
+            match def {
+                Definition::EnumTypeDefinition(enum_type) => {
+                    if let Some(name) = enum_type.name() {
+                        types.enums.insert(name.text().to_string());
+                    }
+                }
+                Definition::InputObjectTypeDefinition(input) => {
+                    let Some(name) = input.name() else { continue };
+                    let fields = input
+                        .input_fields_definition()
+                        .into_iter()
+                        .flat_map(|fields| fields.input_value_definitions())
+                        .filter_map(|field| {
+                            Some((field.name()?.text().to_string(), named_type(&field.ty()?)?))
+                        })
+                        .collect();
+                    types.input_fields.insert(name.text().to_string(), fields);
+                }
+                _ => {}
+            }
+        }
+
+        types
+    }
+
+    /// Wraps every enum value in `value`, which is declared as `type_name`.
+    pub fn tag_value(&self, type_name: &str, value: Value) -> Value {
+        match value {
+            Value::Array(items) => Value::Array(
+                items
+                    .into_iter()
+                    .map(|item| self.tag_value(type_name, item))
+                    .collect(),
+            ),
+            Value::String(enum_value) if self.enums.contains(type_name) => tag_enum(&enum_value),
+            Value::Object(fields) => match self.input_fields.get(type_name) {
+                Some(field_types) => Value::Object(
+                    fields
+                        .into_iter()
+                        .map(|(name, field)| {
+                            let field = match field_types.get(&name) {
+                                Some(field_type) => self.tag_value(field_type, field),
+                                None => field,
+                            };
+                            (name, field)
+                        })
+                        .collect(),
+                ),
+                None => Value::Object(fields),
+            },
+            other => other,
+        }
+    }
+
+    /// Copy of `variables` with enum values tagged according to the types the
+    /// operation declares for them.
+    pub fn tag_variables(
+        &self,
+        operation: &OperationDefinition,
+        variables: &BytesMap<ByteString, BytesValue>,
+    ) -> BytesMap<ByteString, BytesValue> {
+        let mut tagged = variables.clone();
+        let definitions = operation
+            .variable_definitions()
+            .into_iter()
+            .flat_map(|definitions| definitions.variable_definitions());
+
+        for definition in definitions {
+            let Some(name) = definition.variable().and_then(|v| v.name()) else {
+                continue;
+            };
+            let Some(type_name) = definition.ty().as_ref().and_then(named_type) else {
+                continue;
+            };
+            if let Some(value) = tagged.get_mut(name.text().as_str())
+                && let Ok(json) = serde_json::to_value(&*value)
+            {
+                *value = BytesValue::from(self.tag_value(&type_name, json));
+            }
+        }
+
+        tagged
+    }
+}
+
+/// Innermost named type of `ty`, e.g. `LoanStatus` for `[LoanStatus!]!`.
+pub(crate) fn named_type(ty: &Type) -> Option<String> {
+    match ty {
+        Type::NamedType(named) => Some(named.name()?.text().to_string()),
+        Type::ListType(list) => named_type(&list.ty()?),
+        Type::NonNullType(non_null) => match non_null.named_type() {
+            Some(named) => Some(named.name()?.text().to_string()),
+            None => named_type(&non_null.list_type()?.ty()?),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SDL: &str = r#"
+        enum LoanStatus { ACTIVE CLOSED }
+        input StatusInput {
+          loanId: String!
+          status: LoanStatus!
+          history: [LoanStatus!]
+        }
+    "#;
+
+    #[test]
+    fn tags_enum_values_nested_in_input_objects_and_lists() {
+        let types = EnumTypes::from_sdl(SDL);
+
+        let tagged = types.tag_value(
+            "StatusInput",
+            json!({ "loanId": "ACTIVE", "status": "ACTIVE", "history": ["CLOSED"] }),
+        );
+
+        assert_eq!(
+            json!({
+                "loanId": "ACTIVE",
+                "status": { "__enum": "ACTIVE" },
+                "history": [{ "__enum": "CLOSED" }]
+            }),
+            tagged
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use regex::Regex;
+use serde::Deserialize;
+
+/// Mutation field names matched by glob (`create*`, `record*Requested`) or, when
+/// wrapped in slashes, regular expression (`/^record\w+Requested$/`) patterns.
+/// Patterns are compiled when the configuration is loaded.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "Vec<String>")]
+pub struct FieldPatterns {
+    patterns: Vec<Regex>,
+}
+
+impl FieldPatterns {
+    pub fn compile<S: AsRef<str>>(patterns: &[S]) -> Result<Self, regex::Error> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| compile_pattern(pattern.as_ref()))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { patterns })
+    }
+
+    pub fn matches(&self, field_name: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.is_match(field_name))
+    }
+}
+
+impl TryFrom<Vec<String>> for FieldPatterns {
+    type Error = regex::Error;
+
+    fn try_from(patterns: Vec<String>) -> Result<Self, Self::Error> {
+        Self::compile(&patterns)
+    }
+}
+
+fn compile_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    if let Some(regex) = pattern
+        .strip_prefix('/')
+        .and_then(|rest| rest.strip_suffix('/'))
+    {
+        return Regex::new(regex);
+    }
+
+    let glob = regex::escape(pattern)
+        .replace(r"\*", ".*")
+        .replace(r"\?", ".");
+    Regex::new(&format!("^{glob}$"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_and_regexes_match_field_names() {
+        let patterns =
+            FieldPatterns::compile(&["create*", "record*Requested", "/^open(Account|Case)$/"])
+                .unwrap();
+
+        assert!(patterns.matches("createOrder"));
+        assert!(patterns.matches("recordLoanRequested"));
+        assert!(patterns.matches("recordCreditCheckRequested"));
+        assert!(patterns.matches("openCase"));
+        assert!(!patterns.matches("recordCreditChecked"));
+        assert!(!patterns.matches("recreateOrder"));
+        assert!(FieldPatterns::compile(&["/(/"]).is_err());
+    }
+}
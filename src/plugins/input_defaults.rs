@@ -0,0 +1,268 @@
+use apollo_parser::{
+    Parser,
+    cst::{self, CstNode, Definition},
+};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+use crate::plugins::enum_tagging::{named_type, tag_enum};
+use crate::plugins::kurrent_mapper::{MutationArg, OperationType};
+
+#[derive(Debug)]
+struct InputField {
+    name: String,
+    type_name: String,
+    default: Option<Value>,
+}
+
+/// Default values the supergraph declares for input object fields. GraphQL input
+/// coercion fills in absent fields with them, so they are applied to persisted
+/// arguments too, to record what the subgraph actually received.
+#[derive(Debug, Default)]
+pub struct InputDefaults {
+    /// Root field to its arguments' named types.
+    arguments: HashMap<(OperationType, String), HashMap<String, String>>,
+    input_fields: HashMap<String, Vec<InputField>>,
+}
+
+impl InputDefaults {
+    /// `tag_enums` renders enum defaults like enum literals under `tag_enums`.
+    pub fn from_sdl(sdl: &str, tag_enums: bool) -> Self {
+        let ast = Parser::new(sdl).parse();
+        let doc = ast.document();
+        let mut defaults = Self::default();
+
+        let mut roots = HashMap::from([
+            ("Mutation".to_string(), OperationType::Mutation),
+            ("Query".to_string(), OperationType::Query),
+        ]);
+        for def in doc.definitions() {
+            let Definition::SchemaDefinition(schema) = def else {
+                continue;
+            };
+            roots.clear();
+            for root in schema.root_operation_type_definitions() {
+                let operation_type = match root.operation_type() {
+                    Some(op) if op.mutation_token().is_some() => OperationType::Mutation,
+                    Some(op) if op.query_token().is_some() => OperationType::Query,
+                    _ => continue,
+                };
+                if let Some(name) = root.named_type().and_then(|named| named.name()) {
+                    roots.insert(name.text().to_string(), operation_type);
+                }
+            }
+        }
+
+        for def in doc.definitions() {
+            let (name, fields) = match &def {
+                Definition::ObjectTypeDefinition(object) => {
+                    (object.name(), object.fields_definition())
+                }
+                Definition::ObjectTypeExtension(object) => {
+                    (object.name(), object.fields_definition())
+                }
+                Definition::InputObjectTypeDefinition(input) => {
+                    let Some(name) = input.name() else { continue };
+                    let fields = input
+                        .input_fields_definition()
+                        .into_iter()
+                        .flat_map(|fields| fields.input_value_definitions())
+                        .filter_map(|field| input_field(&field, tag_enums));
+                    defaults
+                        .input_fields
+                        .entry(name.text().to_string())
+                        .or_default()
+                        .extend(fields);
+                    continue;
+                }
+                _ => continue,
+            };
+            let Some(&operation_type) = name.and_then(|name| roots.get(name.text().as_str()))
+            else {
+                continue;
+            };
+            for field in fields
+                .into_iter()
+                .flat_map(|fields| fields.field_definitions())
+            {
+                let Some(field_name) = field.name() else {
+                    continue;
+                };
+                let arguments = field
+                    .arguments_definition()
+                    .into_iter()
+                    .flat_map(|arguments| arguments.input_value_definitions())
+                    .filter_map(|argument| {
+                        Some((
+                            argument.name()?.text().to_string(),
+                            named_type(&argument.ty()?)?,
+                        ))
+                    })
+                    .collect();
+                defaults
+                    .arguments
+                    .insert((operation_type, field_name.text().to_string()), arguments);
+            }
+        }
+
+        defaults
+    }
+
+    /// Adds the declared default of every absent input object field in `arguments`,
+    /// recursively. Fields given explicitly, including as `null`, are kept.
+    pub fn apply(
+        &self,
+        operation_type: OperationType,
+        field_name: &str,
+        arguments: &mut [MutationArg],
+    ) {
+        let Some(types) = self
+            .arguments
+            .get(&(operation_type, field_name.to_string()))
+        else {
+            return;
+        };
+        for argument in arguments {
+            if let Some(type_name) = types.get(&argument.name) {
+                self.apply_to_value(type_name, &mut argument.value);
+            }
+        }
+    }
+
+    fn apply_to_value(&self, type_name: &str, value: &mut Value) {
+        match value {
+            Value::Array(items) => items
+                .iter_mut()
+                .for_each(|item| self.apply_to_value(type_name, item)),
+            Value::Object(object) => {
+                let Some(fields) = self.input_fields.get(type_name) else {
+                    return;
+                };
+                for field in fields {
+                    match object.get_mut(&field.name) {
+                        Some(value) => self.apply_to_value(&field.type_name, value),
+                        None => {
+                            if let Some(default) = &field.default {
+                                object.insert(field.name.clone(), default.clone());
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn input_field(field: &cst::InputValueDefinition, tag_enums: bool) -> Option<InputField> {
+    Some(InputField {
+        name: field.name()?.text().to_string(),
+        type_name: named_type(&field.ty()?)?,
+        default: field
+            .default_value()
+            .and_then(|default| default.value())
+            .and_then(|value| literal_to_json(&value, tag_enums)),
+    })
+}
+
+/// JSON form of a constant (variable-free) literal, such as a default value.
+fn literal_to_json(value: &cst::Value, tag_enums: bool) -> Option<Value> {
+    let text = || value.syntax().text().to_string();
+    match value {
+        cst::Value::StringValue(_)
+        | cst::Value::IntValue(_)
+        | cst::Value::FloatValue(_)
+        | cst::Value::BooleanValue(_) => serde_json::from_str(&text()).ok(),
+        cst::Value::NullValue(_) => Some(Value::Null),
+        cst::Value::EnumValue(_) if tag_enums => Some(tag_enum(&text())),
+        cst::Value::EnumValue(_) => Some(Value::String(text())),
+        cst::Value::Variable(_) => None,
+        cst::Value::ListValue(list) => list
+            .values()
+            .map(|item| literal_to_json(&item, tag_enums))
+            .collect::<Option<Vec<_>>>()
+            .map(Value::Array),
+        cst::Value::ObjectValue(object) => object
+            .object_fields()
+            .map(|field| {
+                Some((
+                    field.name()?.text().to_string(),
+                    literal_to_json(&field.value()?, tag_enums)?,
+                ))
+            })
+            .collect::<Option<Map<_, _>>>()
+            .map(Value::Object),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SDL: &str = r#"
+        enum Currency { USD EUR }
+        input AddressInput {
+          City: String!
+          Country: String = "US"
+        }
+        input LoanInput {
+          loanId: ID!
+          Currency: Currency = USD
+          Term: Int = 36
+          Address: AddressInput
+          CoApplicants: [AddressInput!]
+        }
+        type Mutation {
+          recordLoanRequested(input: LoanInput!): ID!
+        }
+    "#;
+
+    fn apply(defaults: &InputDefaults, input: Value) -> Value {
+        let mut arguments = vec![MutationArg {
+            name: "input".to_string(),
+            value: input,
+        }];
+        defaults.apply(
+            OperationType::Mutation,
+            "recordLoanRequested",
+            &mut arguments,
+        );
+        arguments.remove(0).value
+    }
+
+    #[test]
+    fn absent_fields_get_their_defaults_and_explicit_nulls_are_kept() {
+        let defaults = InputDefaults::from_sdl(SDL, false);
+
+        let input = apply(
+            &defaults,
+            json!({
+                "loanId": "loan-1",
+                "Term": null,
+                "Address": { "City": "Boston" },
+                "CoApplicants": [{ "City": "Lyon", "Country": "FR" }]
+            }),
+        );
+
+        assert_eq!(
+            json!({
+                "loanId": "loan-1",
+                "Currency": "USD",
+                "Term": null,
+                "Address": { "City": "Boston", "Country": "US" },
+                "CoApplicants": [{ "City": "Lyon", "Country": "FR" }]
+            }),
+            input
+        );
+    }
+
+    #[test]
+    fn enum_defaults_are_tagged_like_enum_literals() {
+        let defaults = InputDefaults::from_sdl(SDL, true);
+
+        let input = apply(&defaults, json!({ "loanId": "loan-1" }));
+
+        assert_eq!(json!({ "__enum": "USD" }), input["Currency"]);
+    }
+}
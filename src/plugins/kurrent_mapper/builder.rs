@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use super::{
+    error::KurrentError,
+    mapper::{KurrentConfig, KurrentService},
+    naming::{CaseTransform, EventTypeCase, StreamNaming},
+};
+
+/// Builds a [`KurrentService`] outside the router, e.g. in an integration test
+/// harness or a replay tool. Unset options keep their `router.yaml` defaults.
+#[derive(Debug, Clone, Default)]
+pub struct KurrentServiceBuilder {
+    config: KurrentConfig,
+}
+
+impl KurrentServiceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn connection_string(mut self, connection_string: impl Into<String>) -> Self {
+        self.config.connection_string = connection_string.into();
+        self
+    }
+
+    pub fn stream_prefix(mut self, stream_prefix: impl Into<String>) -> Self {
+        self.config.stream_prefix = stream_prefix.into();
+        self
+    }
+
+    /// Switches to `{category}-{loan_id}` streams.
+    pub fn category(mut self, category: impl Into<String>) -> Self {
+        self.config.stream_naming = StreamNaming::Category;
+        self.config.category = Some(category.into());
+        self
+    }
+
+    pub fn stream_override(
+        mut self,
+        field_name: impl Into<String>,
+        stream: impl Into<String>,
+    ) -> Self {
+        self.config
+            .stream_overrides
+            .insert(field_name.into(), stream.into());
+        self
+    }
+
+    pub fn event_type_case(mut self, case: EventTypeCase) -> Self {
+        self.config.event_type_case = case;
+        self
+    }
+
+    pub fn case_transform(mut self, case: CaseTransform) -> Self {
+        self.config.case_transform = case;
+        self
+    }
+
+    pub fn projection_safe(mut self, projection_safe: bool) -> Self {
+        self.config.projection_safe = projection_safe;
+        self
+    }
+
+    pub fn health_check_interval(mut self, interval: Duration) -> Self {
+        self.config.health_check_interval_ms = interval.as_millis() as u64;
+        self
+    }
+
+    pub fn connection_pool_size(mut self, size: usize) -> Self {
+        self.config.connection_pool_size = size;
+        self
+    }
+
+    pub fn reconnect_after_failures(mut self, failures: u32) -> Self {
+        self.config.reconnect_after_failures = failures;
+        self
+    }
+
+    pub fn append_timeout(mut self, timeout: Duration) -> Self {
+        self.config.append_timeout_ms = timeout.as_millis() as u64;
+        self
+    }
+
+    pub fn dead_letter_stream(mut self, stream: impl Into<String>) -> Self {
+        self.config.dead_letter_stream = stream.into();
+        self
+    }
+
+    pub fn max_event_bytes(mut self, max_event_bytes: usize) -> Self {
+        self.config.max_event_bytes = Some(max_event_bytes);
+        self
+    }
+
+    pub fn max_append_batch(mut self, max_append_batch: usize) -> Self {
+        self.config.max_append_batch = Some(max_append_batch);
+        self
+    }
+
+    pub fn config(&self) -> &KurrentConfig {
+        &self.config
+    }
+
+    /// Validates the configuration and connects, exactly like the plugin does.
+    pub async fn build(self) -> Result<KurrentService, KurrentError> {
+        KurrentService::new(self.config).await
+    }
+}
+
+impl From<KurrentConfig> for KurrentServiceBuilder {
+    fn from(config: KurrentConfig) -> Self {
+        Self { config }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::kurrent_mapper::{MutationCall, naming};
+
+    #[test]
+    fn builder_sets_naming_independently_of_plugin_config() {
+        let builder = KurrentServiceBuilder::new()
+            .connection_string("kurrentdb://localhost:2113?tls=false")
+            .category("loan")
+            .stream_override("recordLoanRequested", "loans")
+            .append_timeout(Duration::from_secs(2));
+        let call = |field_name: &str| MutationCall {
+            field_name: field_name.to_string(),
+            loan_id: Some("123".to_string()),
+            ..Default::default()
+        };
+
+        let config = builder.config();
+
+        assert_eq!(
+            "kurrentdb://localhost:2113?tls=false",
+            config.connection_string
+        );
+        assert_eq!(2_000, config.append_timeout_ms);
+        assert_eq!(5_000, config.health_check_interval_ms);
+        assert_eq!(
+            "loan-123",
+            naming::stream_name(&call("recordCreditChecked"), config)
+        );
+        assert_eq!(
+            "loans",
+            naming::stream_name(&call("recordLoanRequested"), config)
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_cooldown_ms() -> u64 {
+    30_000
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitOpenAction {
+    /// Keep the batch in the write-ahead log, to be replayed on the next startup.
+    /// Batches are dropped when `wal_path` is unset.
+    #[default]
+    Wal,
+    /// Write each call to the dead-letter stream, for setups where it stays writable
+    /// while the target streams do not.
+    DeadLetter,
+    /// Drop the batch, acknowledging it in the write-ahead log.
+    Drop,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failed batches (retryable append errors) that open the circuit.
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// How long the circuit stays open before one batch is let through as a probe.
+    #[serde(default = "default_cooldown_ms")]
+    pub cooldown_ms: u64,
+    /// What happens to batches while the circuit is open.
+    #[serde(default)]
+    pub on_open: CircuitOpenAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    /// The cooldown elapsed and a probe batch is in flight; further batches are
+    /// refused until it completes.
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct Breaker {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Instant,
+}
+
+/// Stops appends to an unreachable KurrentDB after `failure_threshold` consecutive
+/// failures. The `kurrent_circuit_breaker_open` metric is 1 while the circuit is
+/// open or half-open and 0 once it closed again.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    breaker: Mutex<Breaker>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: &CircuitBreakerConfig) -> Self {
+        Self {
+            failure_threshold: config.failure_threshold.max(1),
+            cooldown: Duration::from_millis(config.cooldown_ms),
+            breaker: Mutex::new(Breaker {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                opened_at: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.lock().state
+    }
+
+    /// Whether a batch may be appended now. Once the cooldown has elapsed, a single
+    /// batch is let through to probe whether KurrentDB recovered.
+    pub fn allow(&self) -> bool {
+        self.allow_at(Instant::now())
+    }
+
+    fn allow_at(&self, now: Instant) -> bool {
+        let mut breaker = self.lock();
+        match breaker.state {
+            CircuitState::Closed => true,
+            CircuitState::Open if now.duration_since(breaker.opened_at) >= self.cooldown => {
+                tracing::info!("Circuit breaker half-open, probing KurrentDB");
+                breaker.state = CircuitState::HalfOpen;
+                true
+            }
+            CircuitState::Open | CircuitState::HalfOpen => false,
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut breaker = self.lock();
+        breaker.consecutive_failures = 0;
+        if breaker.state != CircuitState::Closed {
+            breaker.state = CircuitState::Closed;
+            tracing::info!(
+                counter.kurrent_circuit_breaker_open = -1i64,
+                "Circuit breaker closed, KurrentDB is accepting appends again"
+            );
+        }
+    }
+
+    pub fn record_failure(&self) {
+        self.record_failure_at(Instant::now());
+    }
+
+    fn record_failure_at(&self, now: Instant) {
+        let mut breaker = self.lock();
+        breaker.consecutive_failures = breaker.consecutive_failures.saturating_add(1);
+        let trips = match breaker.state {
+            CircuitState::Closed => breaker.consecutive_failures >= self.failure_threshold,
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+        if !trips {
+            return;
+        }
+
+        if breaker.state == CircuitState::Closed {
+            tracing::warn!(
+                counter.kurrent_circuit_breaker_open = 1i64,
+                failures = breaker.consecutive_failures,
+                cooldown_ms = self.cooldown.as_millis() as u64,
+                "Circuit breaker opened, not appending to KurrentDB until the cooldown elapses"
+            );
+        } else {
+            tracing::warn!("Circuit breaker probe failed, reopening");
+        }
+        breaker.state = CircuitState::Open;
+        breaker.opened_at = now;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Breaker> {
+        self.breaker
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(&CircuitBreakerConfig {
+            failure_threshold: 3,
+            cooldown_ms: 1_000,
+            on_open: CircuitOpenAction::Drop,
+        })
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures_only() {
+        let breaker = breaker();
+        let now = Instant::now();
+
+        breaker.record_failure_at(now);
+        breaker.record_failure_at(now);
+        breaker.record_success();
+        breaker.record_failure_at(now);
+        breaker.record_failure_at(now);
+        assert_eq!(CircuitState::Closed, breaker.state());
+        assert!(breaker.allow_at(now));
+
+        breaker.record_failure_at(now);
+        assert_eq!(CircuitState::Open, breaker.state());
+        assert!(!breaker.allow_at(now + Duration::from_millis(999)));
+    }
+
+    #[test]
+    fn half_opens_after_the_cooldown_for_a_single_probe() {
+        let breaker = breaker();
+        let opened = Instant::now();
+        (0..3).for_each(|_| breaker.record_failure_at(opened));
+
+        let after_cooldown = opened + Duration::from_secs(1);
+        assert!(breaker.allow_at(after_cooldown));
+        assert_eq!(CircuitState::HalfOpen, breaker.state());
+        assert!(!breaker.allow_at(after_cooldown));
+
+        breaker.record_failure_at(after_cooldown);
+        assert_eq!(CircuitState::Open, breaker.state());
+        assert!(!breaker.allow_at(after_cooldown + Duration::from_millis(500)));
+
+        assert!(breaker.allow_at(after_cooldown + Duration::from_secs(1)));
+        breaker.record_success();
+        assert_eq!(CircuitState::Closed, breaker.state());
+        assert!(breaker.allow_at(after_cooldown + Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,157 @@
+use super::mapper::MutationCall;
+use std::{future::Future, time::Duration};
+use tokio::{sync::mpsc, task, time::Instant};
+
+/// A batch handed to the sink, with its write-ahead log entry if it has one.
+#[derive(Debug)]
+pub struct PendingBatch {
+    pub calls: Vec<MutationCall>,
+    pub wal_id: Option<u64>,
+}
+
+/// Sends batches to a background worker that holds them for up to `interval`, or
+/// until `max_batch_size` calls are buffered, and then flushes them together.
+/// Dropping the last handle flushes whatever is still buffered.
+#[derive(Clone)]
+pub struct Coalescer {
+    sender: mpsc::UnboundedSender<PendingBatch>,
+}
+
+impl Coalescer {
+    pub fn spawn<F, Fut>(interval: Duration, max_batch_size: usize, flush: F) -> Self
+    where
+        F: Fn(Vec<PendingBatch>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        task::spawn(run(receiver, interval, max_batch_size.max(1), flush));
+        Self { sender }
+    }
+
+    pub fn push(&self, batch: PendingBatch) {
+        if self.sender.send(batch).is_err() {
+            tracing::error!("Coalescing worker stopped, dropping mutation batch");
+        }
+    }
+}
+
+async fn run<F, Fut>(
+    mut receiver: mpsc::UnboundedReceiver<PendingBatch>,
+    interval: Duration,
+    max_batch_size: usize,
+    flush: F,
+) where
+    F: Fn(Vec<PendingBatch>) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut buffer = Vec::new();
+    let mut buffered_calls = 0;
+    // Set by the first batch of a window, so a quiet period still flushes it.
+    let mut deadline = None;
+
+    loop {
+        let received = match deadline {
+            Some(deadline) => tokio::select! {
+                batch = receiver.recv() => Some(batch),
+                _ = tokio::time::sleep_until(deadline) => None,
+            },
+            None => Some(receiver.recv().await),
+        };
+
+        match received {
+            Some(Some(batch)) => {
+                buffered_calls += batch.calls.len();
+                buffer.push(batch);
+                deadline.get_or_insert_with(|| Instant::now() + interval);
+                if buffered_calls < max_batch_size {
+                    continue;
+                }
+            }
+            Some(None) => {
+                if !buffer.is_empty() {
+                    flush(buffer).await;
+                }
+                return;
+            }
+            None => {}
+        }
+
+        flush(std::mem::take(&mut buffer)).await;
+        buffered_calls = 0;
+        deadline = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn batch(field_name: &str) -> PendingBatch {
+        PendingBatch {
+            calls: vec![MutationCall {
+                field_name: field_name.to_string(),
+                ..Default::default()
+            }],
+            wal_id: None,
+        }
+    }
+
+    fn recording(
+        interval: Duration,
+        max_batch_size: usize,
+    ) -> (Coalescer, Arc<Mutex<Vec<Vec<String>>>>) {
+        let flushed = Arc::new(Mutex::new(Vec::new()));
+        let recorder = flushed.clone();
+        let coalescer = Coalescer::spawn(interval, max_batch_size, move |batches| {
+            let fields = batches
+                .iter()
+                .flat_map(|batch| batch.calls.iter().map(|call| call.field_name.clone()))
+                .collect();
+            recorder.lock().unwrap().push(fields);
+            async {}
+        });
+        (coalescer, flushed)
+    }
+
+    #[tokio::test]
+    async fn timer_flushes_buffered_batches_without_further_pushes() {
+        let (coalescer, flushed) = recording(Duration::from_millis(20), 100);
+
+        coalescer.push(batch("recordLoanRequested"));
+        coalescer.push(batch("recordCreditChecked"));
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert!(flushed.lock().unwrap().is_empty());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(
+            vec![vec![
+                "recordLoanRequested".to_string(),
+                "recordCreditChecked".to_string()
+            ]],
+            *flushed.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn reaching_max_batch_size_flushes_before_the_interval() {
+        let (coalescer, flushed) = recording(Duration::from_secs(60), 2);
+
+        coalescer.push(batch("recordLoanRequested"));
+        coalescer.push(batch("recordCreditChecked"));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        assert_eq!(1, flushed.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn dropping_the_coalescer_flushes_what_is_buffered() {
+        let (coalescer, flushed) = recording(Duration::from_secs(60), 100);
+
+        coalescer.push(batch("recordLoanRequested"));
+        drop(coalescer);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        assert_eq!(1, flushed.lock().unwrap().len());
+    }
+}
@@ -0,0 +1,248 @@
+use futures::{
+    FutureExt,
+    future::{self, BoxFuture},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    error::KurrentError,
+    mapper::{KurrentConfig, KurrentService, MutationCall, MutationSink},
+};
+
+/// A sink written to in addition to the primary KurrentDB connection, e.g. a second
+/// cluster during a migration or a local backup file.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkDefinition {
+    Kurrent(Box<KurrentConfig>),
+    /// Appends every call as a JSON line.
+    File {
+        path: PathBuf,
+    },
+}
+
+impl SinkDefinition {
+    /// Label used in logs when this sink misbehaves.
+    pub fn name(&self) -> String {
+        match self {
+            SinkDefinition::Kurrent(config) => format!("kurrent:{}", config.connection_string),
+            SinkDefinition::File { path } => format!("file:{}", path.display()),
+        }
+    }
+
+    pub async fn build(&self) -> Result<Arc<dyn MutationSink>, KurrentError> {
+        match self {
+            SinkDefinition::Kurrent(config) => Ok(Arc::new(
+                KurrentService::new(config.as_ref().clone()).await?,
+            )),
+            SinkDefinition::File { path } => Ok(Arc::new(FileSink::open(path.clone())?)),
+        }
+    }
+}
+
+/// Hands every batch to each of its sinks. A sink that panics is logged and
+/// skipped, so it cannot keep the batch from the others. Health is that of the
+/// first (primary) sink; the rest are best effort.
+pub struct CompositeSink {
+    sinks: Vec<(String, Arc<dyn MutationSink>)>,
+}
+
+impl CompositeSink {
+    pub fn new(sinks: Vec<(String, Arc<dyn MutationSink>)>) -> Self {
+        Self { sinks }
+    }
+}
+
+impl MutationSink for CompositeSink {
+    fn persist_mutations(&self, calls: Vec<MutationCall>) {
+        for (name, sink) in &self.sinks {
+            let calls = calls.clone();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| sink.persist_mutations(calls)));
+            if result.is_err() {
+                tracing::error!(sink = %name, "Mutation sink panicked, continuing with the remaining sinks");
+            }
+        }
+    }
+
+    /// Waits for every sink, but only the primary sink's outcome is returned.
+    fn persist_and_wait(
+        &self,
+        calls: Vec<MutationCall>,
+    ) -> BoxFuture<'static, Result<(), KurrentError>> {
+        let persisting: Vec<_> = self
+            .sinks
+            .iter()
+            .map(|(name, sink)| {
+                let name = name.clone();
+                let calls = calls.clone();
+                let persisted = panic::catch_unwind(AssertUnwindSafe(|| sink.persist_and_wait(calls)));
+                async move {
+                    let result = match persisted {
+                        Ok(persisting) => AssertUnwindSafe(persisting).catch_unwind().await,
+                        Err(panicked) => Err(panicked),
+                    };
+                    result.unwrap_or_else(|_| {
+                        tracing::error!(sink = %name, "Mutation sink panicked, continuing with the remaining sinks");
+                        Ok(())
+                    })
+                }
+            })
+            .collect();
+
+        Box::pin(async move {
+            future::join_all(persisting)
+                .await
+                .into_iter()
+                .next()
+                .unwrap_or(Ok(()))
+        })
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.sinks.first().is_none_or(|(_, sink)| sink.is_healthy())
+    }
+
+    fn has_capacity(&self) -> bool {
+        self.sinks
+            .first()
+            .is_none_or(|(_, sink)| sink.has_capacity())
+    }
+}
+
+/// Writes calls as JSON lines to a local file.
+pub struct FileSink {
+    path: PathBuf,
+    file: Mutex<BufWriter<File>>,
+}
+
+impl FileSink {
+    pub fn open(path: PathBuf) -> Result<Self, KurrentError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|err| {
+                KurrentError::config(format!("cannot open sink file {}: {err}", path.display()))
+            })?;
+        Ok(Self {
+            path,
+            file: Mutex::new(BufWriter::new(file)),
+        })
+    }
+}
+
+impl MutationSink for FileSink {
+    fn persist_mutations(&self, calls: Vec<MutationCall>) {
+        let mut file = self
+            .file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let result = calls
+            .iter()
+            .try_for_each(|call| {
+                serde_json::to_writer(&mut *file, call)?;
+                file.write_all(b"\n").map_err(serde_json::Error::io)
+            })
+            .and_then(|()| file.flush().map_err(serde_json::Error::io));
+        if let Err(error) = result {
+            tracing::error!(error = %error, path = %self.path.display(), "Failed to write mutations to sink file");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingSink {
+        calls: Mutex<Vec<MutationCall>>,
+        healthy: bool,
+    }
+
+    impl MutationSink for RecordingSink {
+        fn persist_mutations(&self, calls: Vec<MutationCall>) {
+            self.calls.lock().unwrap().extend(calls);
+        }
+
+        fn is_healthy(&self) -> bool {
+            self.healthy
+        }
+    }
+
+    struct PanickingSink;
+
+    impl MutationSink for PanickingSink {
+        fn persist_mutations(&self, _calls: Vec<MutationCall>) {
+            panic!("sink failed");
+        }
+    }
+
+    fn call() -> MutationCall {
+        MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn a_failing_sink_does_not_stop_the_others() {
+        let primary = Arc::new(RecordingSink {
+            healthy: true,
+            ..Default::default()
+        });
+        let mirror = Arc::new(RecordingSink::default());
+        let composite = CompositeSink::new(vec![
+            ("primary".to_string(), primary.clone()),
+            ("broken".to_string(), Arc::new(PanickingSink)),
+            ("mirror".to_string(), mirror.clone()),
+        ]);
+
+        composite.persist_mutations(vec![call()]);
+
+        assert_eq!(1, primary.calls.lock().unwrap().len());
+        assert_eq!(1, mirror.calls.lock().unwrap().len());
+        assert!(composite.is_healthy());
+    }
+
+    #[test]
+    fn file_sink_appends_calls_as_json_lines() {
+        let path =
+            std::env::temp_dir().join(format!("starstuff-sink-{}.jsonl", uuid::Uuid::new_v4()));
+        let sink = FileSink::open(path.clone()).unwrap();
+
+        sink.persist_mutations(vec![call(), call()]);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<MutationCall> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, lines.len());
+        assert_eq!("recordCreditChecked", lines[0].field_name);
+    }
+
+    #[test]
+    fn sink_definitions_are_tagged_by_type() {
+        let definitions: Vec<SinkDefinition> = serde_json::from_value(serde_json::json!([
+            { "type": "kurrent", "connection_string": "esdb://backup:2113?tls=false" },
+            { "type": "file", "path": "/var/log/mutations.jsonl" }
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            "kurrent:esdb://backup:2113?tls=false",
+            definitions[0].name()
+        );
+        assert_eq!("file:/var/log/mutations.jsonl", definitions[1].name());
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    fmt,
+    future::Future,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tower::BoxError;
+
+/// Errors that may mean the underlying connection is stale and worth rebuilding.
+pub trait ConnectionFailure {
+    fn is_connection_error(&self) -> bool;
+}
+
+impl ConnectionFailure for kurrentdb::Error {
+    fn is_connection_error(&self) -> bool {
+        matches!(
+            self,
+            kurrentdb::Error::GrpcConnectionError(_)
+                | kurrentdb::Error::ConnectionClosed
+                | kurrentdb::Error::NotLeaderException(_)
+        )
+    }
+}
+
+/// Outcome of an operation bounded by [`with_deadline`].
+#[derive(Debug)]
+pub enum Deadline<E> {
+    /// The operation did not finish in time.
+    Elapsed(Duration),
+    Failed(E),
+}
+
+/// A hung call is treated like a lost connection, so it counts toward a rebuild.
+impl<E: ConnectionFailure> ConnectionFailure for Deadline<E> {
+    fn is_connection_error(&self) -> bool {
+        match self {
+            Deadline::Elapsed(_) => true,
+            Deadline::Failed(error) => error.is_connection_error(),
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for Deadline<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Deadline::Elapsed(timeout) => write!(f, "timed out after {}ms", timeout.as_millis()),
+            Deadline::Failed(error) => error.fmt(f),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for Deadline<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Deadline::Elapsed(_) => None,
+            Deadline::Failed(error) => Some(error),
+        }
+    }
+}
+
+pub async fn with_deadline<T, E>(
+    timeout: Duration,
+    operation: impl Future<Output = Result<T, E>>,
+) -> Result<T, Deadline<E>> {
+    match tokio::time::timeout(timeout, operation).await {
+        Ok(result) => result.map_err(Deadline::Failed),
+        Err(_) => Err(Deadline::Elapsed(timeout)),
+    }
+}
+
+type Connect<C> = Box<dyn Fn() -> Result<C, BoxError> + Send + Sync>;
+
+/// Holds the current client and rebuilds it after `threshold` consecutive
+/// connection failures. Rebuilds are serialized and tagged with a generation, so a
+/// burst of concurrent failures against the same stale client reconnects only once.
+/// Operations hold the client they started with, so replacing it never affects
+/// calls already in flight.
+pub struct ReconnectingClient<C> {
+    current: RwLock<Arc<C>>,
+    generation: AtomicU64,
+    consecutive_failures: AtomicU32,
+    threshold: u32,
+    /// Serializes rebuilds; holds the function they connect with.
+    connect: Mutex<Connect<C>>,
+}
+
+impl<C> ReconnectingClient<C> {
+    pub fn new(
+        client: C,
+        threshold: u32,
+        connect: impl Fn() -> Result<C, BoxError> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(client)),
+            generation: AtomicU64::new(0),
+            consecutive_failures: AtomicU32::new(0),
+            threshold: threshold.max(1),
+            connect: Mutex::new(Box::new(connect)),
+        }
+    }
+
+    pub fn current(&self) -> Arc<C> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Runs `op` against the current client, feeding the outcome into the
+    /// reconnect bookkeeping.
+    pub async fn run<T, E, F, Fut>(&self, op: F) -> Result<T, E>
+    where
+        E: ConnectionFailure,
+        F: FnOnce(Arc<C>) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let generation = self.generation.load(Ordering::Acquire);
+        let result = op(self.current()).await;
+
+        match &result {
+            Ok(_) => self.consecutive_failures.store(0, Ordering::Relaxed),
+            Err(error) if error.is_connection_error() => self.record_connection_failure(generation),
+            Err(_) => {}
+        }
+
+        result
+    }
+
+    fn record_connection_failure(&self, generation: u64) {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures < self.threshold {
+            return;
+        }
+
+        let connect = self.connect.lock().unwrap();
+        if self.generation.load(Ordering::Acquire) != generation {
+            // Another task already replaced the client this failure was observed on.
+            return;
+        }
+
+        match connect() {
+            Ok(client) => {
+                self.swap(client);
+                tracing::info!(
+                    failures,
+                    "Rebuilt KurrentDB client after repeated connection errors"
+                );
+            }
+            Err(error) => {
+                tracing::warn!(error = %error, failures, "Failed to rebuild KurrentDB client");
+            }
+        }
+    }
+
+    /// Connects with `connect` and, if that succeeds, makes the new client current
+    /// and `connect` the function later rebuilds use, e.g. after rotated credentials.
+    /// On failure the current client is kept.
+    pub fn reconnect_with(
+        &self,
+        connect: impl Fn() -> Result<C, BoxError> + Send + Sync + 'static,
+    ) -> Result<(), BoxError> {
+        let mut current_connect = self.connect.lock().unwrap();
+        let client = connect()?;
+        self.swap(client);
+        *current_connect = Box::new(connect);
+        Ok(())
+    }
+
+    fn swap(&self, client: C) {
+        *self.current.write().unwrap() = Arc::new(client);
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[derive(Debug)]
+    enum FakeError {
+        ConnectionLost,
+        Rejected,
+    }
+
+    impl ConnectionFailure for FakeError {
+        fn is_connection_error(&self) -> bool {
+            matches!(self, FakeError::ConnectionLost)
+        }
+    }
+
+    struct FakeClient {
+        stale: bool,
+        hung: bool,
+    }
+
+    impl FakeClient {
+        async fn append(&self) -> Result<(), FakeError> {
+            if self.hung {
+                std::future::pending::<()>().await;
+            }
+            if self.stale {
+                Err(FakeError::ConnectionLost)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn reconnecting(threshold: u32, connects: Arc<AtomicUsize>) -> ReconnectingClient<FakeClient> {
+        ReconnectingClient::new(
+            FakeClient {
+                stale: true,
+                hung: false,
+            },
+            threshold,
+            move || {
+                connects.fetch_add(1, Ordering::SeqCst);
+                Ok(FakeClient {
+                    stale: false,
+                    hung: false,
+                })
+            },
+        )
+    }
+
+    async fn append(client: &ReconnectingClient<FakeClient>) -> Result<(), FakeError> {
+        client.run(|c| async move { c.append().await }).await
+    }
+
+    #[tokio::test]
+    async fn rebuilds_client_after_repeated_connection_errors() {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let client = reconnecting(2, connects.clone());
+
+        assert!(append(&client).await.is_err());
+        assert_eq!(0, connects.load(Ordering::SeqCst));
+
+        assert!(append(&client).await.is_err());
+        assert_eq!(1, connects.load(Ordering::SeqCst));
+
+        assert!(append(&client).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn concurrent_failures_on_the_same_client_reconnect_once() {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let client = reconnecting(1, connects.clone());
+
+        let stale = client.current();
+        let generation = client.generation.load(Ordering::Acquire);
+        for _ in 0..5 {
+            assert!(stale.append().await.is_err());
+            client.record_connection_failure(generation);
+        }
+
+        assert_eq!(1, connects.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn non_connection_errors_do_not_trigger_a_rebuild() {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let client = reconnecting(1, connects.clone());
+
+        let result: Result<(), FakeError> =
+            client.run(|_| async { Err(FakeError::Rejected) }).await;
+
+        assert!(result.is_err());
+        assert_eq!(0, connects.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn reconnecting_with_new_settings_leaves_in_flight_calls_on_the_old_client() {
+        let client = ReconnectingClient::new("old-credentials", 1, || Ok("old-credentials"));
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+
+        let in_flight = client.run(|c| async move {
+            released.await.unwrap();
+            Ok::<_, FakeError>(*c)
+        });
+        let swap = async {
+            client.reconnect_with(|| Ok("new-credentials")).unwrap();
+            release.send(()).unwrap();
+        };
+        let (used, ()) = tokio::join!(in_flight, swap);
+
+        assert_eq!("old-credentials", used.unwrap());
+        assert_eq!("new-credentials", *client.current());
+
+        let failed: Result<(), FakeError> = client
+            .run(|_| async { Err(FakeError::ConnectionLost) })
+            .await;
+        assert!(failed.is_err());
+        assert_eq!("new-credentials", *client.current());
+    }
+
+    #[tokio::test]
+    async fn timed_out_operations_count_as_connection_failures() {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let connects_in_rebuild = connects.clone();
+        let client = ReconnectingClient::new(
+            FakeClient {
+                stale: false,
+                hung: true,
+            },
+            1,
+            move || {
+                connects_in_rebuild.fetch_add(1, Ordering::SeqCst);
+                Ok(FakeClient {
+                    stale: false,
+                    hung: false,
+                })
+            },
+        );
+        let timeout = Duration::from_millis(10);
+
+        let result = client
+            .run(|c| async move { with_deadline(timeout, c.append()).await })
+            .await;
+
+        assert!(matches!(result, Err(Deadline::Elapsed(elapsed)) if elapsed == timeout));
+        assert_eq!(1, connects.load(Ordering::SeqCst));
+        assert!(
+            client
+                .run(|c| async move { with_deadline(timeout, c.append()).await })
+                .await
+                .is_ok()
+        );
+    }
+}
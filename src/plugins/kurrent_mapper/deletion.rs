@@ -0,0 +1,262 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::{
+    error::KurrentError,
+    mapper::{KurrentConfig, MutationCall, OperationType},
+    naming::{self, StreamNaming},
+    routing,
+};
+
+/// What happens to an aggregate's stream once its deletion event is appended. Only
+/// per-aggregate streams, whose name includes the aggregate id, are ever deleted.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamDeletion {
+    /// Keep the stream; the deletion event alone marks the aggregate as deleted.
+    #[default]
+    None,
+    /// Soft-delete the stream: its events, the deletion event included, are no
+    /// longer readable from it, and a later append starts it afresh.
+    Soft,
+    /// Tombstone the stream, which can never be written to again. Requires
+    /// `allow_hard_delete`.
+    Hard,
+}
+
+/// A mutation field that deletes an aggregate, e.g. `closeLoan`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DeleteMutation {
+    /// Aggregate name, e.g. `Loan`, written as the `GraphQL.LoanDeleted` event type.
+    pub aggregate: String,
+    #[serde(default)]
+    pub stream_deletion: StreamDeletion,
+}
+
+impl DeleteMutation {
+    pub fn event_type(&self) -> String {
+        format!("GraphQL.{}Deleted", self.aggregate)
+    }
+}
+
+/// The deletion `call` performs, if its field is one of `delete_mutations`.
+/// Queries and calls captured at the subgraph layer never delete.
+pub fn delete_mutation<'a>(
+    call: &MutationCall,
+    delete_mutations: &'a HashMap<String, DeleteMutation>,
+) -> Option<&'a DeleteMutation> {
+    if call.operation_type != OperationType::Mutation || call.subgraph.is_some() {
+        return None;
+    }
+    delete_mutations.get(&call.field_name)
+}
+
+/// The stream deletion to run once `call`'s event is appended to `stream_name`.
+/// `None` unless `stream_name` is exactly the call's own aggregate stream: a call
+/// without an aggregate id, one that fell back to a shared stream, or one a
+/// routing rule applies to only gets its event.
+pub fn stream_deletion(
+    call: &MutationCall,
+    stream_name: &str,
+    config: &KurrentConfig,
+) -> Option<StreamDeletion> {
+    let deletion = delete_mutation(call, &config.delete_mutations)?.stream_deletion;
+    if deletion == StreamDeletion::None {
+        return None;
+    }
+    let own_stream = routing::matching_rule(call, &config.routing_rules)
+        .is_none()
+        .then(|| naming::aggregate_stream_name(call, config))
+        .flatten();
+    if own_stream.as_deref() == Some(stream_name) {
+        return Some(deletion);
+    }
+    tracing::warn!(mutation = %call.field_name, stream = %stream_name, "Not deleting a stream that is not the deleted aggregate's own");
+    None
+}
+
+/// Splits the calls of one stream after every call that deletes it, as
+/// `(calls, deletion)` segments to append in order, each followed by its deletion.
+pub fn deletion_segments(
+    deletions: &[Option<StreamDeletion>],
+) -> Vec<(usize, Option<StreamDeletion>)> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for (index, deletion) in deletions.iter().enumerate() {
+        if deletion.is_some() {
+            segments.push((index + 1 - start, *deletion));
+            start = index + 1;
+        }
+    }
+    if start < deletions.len() {
+        segments.push((deletions.len() - start, None));
+    }
+    segments
+}
+
+/// Rejects delete mutations that delete their stream unless the stream is per
+/// aggregate: a `stream_overrides` template with `{loan_id}`, or `category` stream
+/// naming, and the field is neither sharded nor named by a routing rule.
+/// Tombstoning additionally requires `allow_hard_delete`.
+pub fn validate_delete_mutations(config: &KurrentConfig) -> Result<(), KurrentError> {
+    for (field, mutation) in &config.delete_mutations {
+        if mutation.stream_deletion == StreamDeletion::None {
+            continue;
+        }
+        let per_aggregate = match config.stream_overrides.get(field) {
+            Some(template) => template.contains("{loan_id}"),
+            None => config.stream_naming == StreamNaming::Category,
+        };
+        let rerouted = config.shards.contains_key(field)
+            || config
+                .routing_rules
+                .iter()
+                .any(|rule| rule.field.as_ref() == Some(field));
+        if !per_aggregate || rerouted {
+            return Err(KurrentError::config(format!(
+                "delete_mutations.{field} deletes its stream, which must be per aggregate: use stream_naming `category` or a stream_overrides template with `{{loan_id}}`, without shards or routing rules for the field"
+            )));
+        }
+        if mutation.stream_deletion == StreamDeletion::Hard && !config.allow_hard_delete {
+            return Err(KurrentError::config(format!(
+                "delete_mutations.{field} tombstones its stream, which requires allow_hard_delete: true"
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::kurrent_mapper::{KurrentConfig, MutationArg, event_type, stream_name};
+    use serde_json::json;
+
+    fn config(stream_deletion: &str, allow_hard_delete: bool) -> KurrentConfig {
+        serde_json::from_value(json!({
+            "stream_naming": "category",
+            "category": "loan",
+            "delete_mutations": {
+                "recordLoanManuallyDenied": { "aggregate": "Loan", "stream_deletion": stream_deletion }
+            },
+            "allow_hard_delete": allow_hard_delete
+        }))
+        .unwrap()
+    }
+
+    fn call(field_name: &str) -> MutationCall {
+        MutationCall {
+            operation_name: Some("DenyLoan".to_string()),
+            field_name: field_name.to_string(),
+            loan_id: Some("loan-1".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn delete_mutations_write_a_deleted_event_to_their_aggregate_stream() {
+        let config = config("hard", true);
+        let denied = call("recordLoanManuallyDenied");
+        let stream = stream_name(&denied, &config);
+
+        assert_eq!("GraphQL.LoanDeleted", event_type(&denied, &config));
+        assert_eq!("loan-loan-1", stream);
+        assert_eq!(
+            Some(StreamDeletion::Hard),
+            stream_deletion(&denied, &stream, &config)
+        );
+        let approved = call("recordLoanManuallyApproved");
+        assert!(delete_mutation(&approved, &config.delete_mutations).is_none());
+        assert_eq!("GraphQL.DenyLoan", event_type(&approved, &config));
+    }
+
+    #[test]
+    fn shared_streams_are_never_deleted() {
+        let mut config = config("soft", false);
+        config.stream_naming = StreamNaming::Prefix;
+
+        let error = validate_delete_mutations(&config).unwrap_err();
+
+        assert_eq!("config", error.kind());
+        assert!(error.to_string().contains("per aggregate"));
+        config.stream_overrides.insert(
+            "recordLoanManuallyDenied".to_string(),
+            "denials-{loan_id}".to_string(),
+        );
+        assert!(validate_delete_mutations(&config).is_ok());
+
+        let mut anonymous = call("recordLoanManuallyDenied");
+        anonymous.loan_id = None;
+        assert_eq!(
+            None,
+            stream_deletion(
+                &anonymous,
+                "graphql-mutation-recordLoanManuallyDenied",
+                &config
+            )
+        );
+    }
+
+    #[test]
+    fn only_the_aggregates_own_stream_is_deleted() {
+        let mut config = config("soft", false);
+        let mut denied = call("recordLoanManuallyDenied");
+        denied.loan_id = Some("1".to_string());
+
+        assert_eq!(
+            Some(StreamDeletion::Soft),
+            stream_deletion(&denied, "loan-1", &config)
+        );
+        for shared in [
+            "loan-12",
+            "loans-2024-1",
+            "graphql-mutation-recordLoanManuallyDenied-1",
+        ] {
+            assert_eq!(None, stream_deletion(&denied, shared, &config));
+        }
+
+        config.routing_rules = serde_json::from_value(json!([
+            { "argument": "input.region", "equals": "EU", "stream": "loan-1" }
+        ]))
+        .unwrap();
+        denied.arguments = vec![MutationArg {
+            name: "input".to_string(),
+            value: json!({ "region": "EU" }),
+        }];
+        assert_eq!(None, stream_deletion(&denied, "loan-1", &config));
+
+        config.routing_rules.clear();
+        config.shards = serde_json::from_value(json!({
+            "recordLoanManuallyDenied": { "count": 4 }
+        }))
+        .unwrap();
+        let error = validate_delete_mutations(&config).unwrap_err();
+        assert!(error.to_string().contains("shards"));
+        assert_eq!(None, stream_deletion(&denied, "loan-1", &config));
+    }
+
+    #[test]
+    fn hard_deletes_must_be_allowed_explicitly() {
+        let error = validate_delete_mutations(&config("hard", false)).unwrap_err();
+
+        assert_eq!("config", error.kind());
+        assert!(error.to_string().contains("allow_hard_delete"));
+        assert!(validate_delete_mutations(&config("soft", false)).is_ok());
+    }
+
+    #[test]
+    fn streams_are_split_after_each_deleting_call() {
+        let soft = Some(StreamDeletion::Soft);
+        let hard = Some(StreamDeletion::Hard);
+
+        assert_eq!(
+            vec![(2, soft), (1, hard), (2, None)],
+            deletion_segments(&[None, soft, hard, None, None])
+        );
+        assert_eq!(vec![(1, soft)], deletion_segments(&[soft]));
+        assert!(deletion_segments(&[]).is_empty());
+    }
+}
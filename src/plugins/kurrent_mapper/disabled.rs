@@ -0,0 +1,16 @@
+use super::mapper::{MutationCall, MutationSink};
+
+/// Stands in for every sink while the plugin is configured with `enabled: false`:
+/// mutations are still detected, logged and counted, then dropped here.
+#[derive(Debug, Default)]
+pub struct DisabledSink;
+
+impl MutationSink for DisabledSink {
+    fn persist_mutations(&self, calls: Vec<MutationCall>) {
+        tracing::debug!(
+            monotonic_counter.kurrent_mutations_not_persisted_total = calls.len() as u64,
+            count = calls.len(),
+            "Persistence is disabled, dropping mutation(s)"
+        );
+    }
+}
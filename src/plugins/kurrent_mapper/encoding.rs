@@ -0,0 +1,224 @@
+use prost::Message;
+use prost_types::{ListValue, Struct, value::Kind};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+
+use super::error::KurrentError;
+
+/// Event metadata key naming the codec of a non-JSON payload. KurrentDB itself
+/// only distinguishes JSON from binary.
+pub const CONTENT_TYPE_METADATA_KEY: &str = "content_type";
+
+/// Codec used for the payload of mutation events.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EventEncoding {
+    /// A JSON event, as KurrentDB projections expect.
+    #[default]
+    Json,
+    /// A `google.protobuf.Struct` holding the JSON payload. Numbers are doubles,
+    /// so integers beyond 2^53 should be persisted as strings (`large_integers`).
+    Protobuf,
+    /// MessagePack with named fields.
+    Msgpack,
+}
+
+impl EventEncoding {
+    pub fn codec(self) -> &'static dyn EventCodec {
+        match self {
+            EventEncoding::Json => &JsonCodec,
+            EventEncoding::Protobuf => &ProtobufCodec,
+            EventEncoding::Msgpack => &MsgpackCodec,
+        }
+    }
+
+    /// The encoding an event was written with, from its metadata. Events without a
+    /// content type are JSON.
+    pub fn of_metadata(metadata: &Map<String, Value>) -> Result<Self, KurrentError> {
+        let Some(content_type) = metadata.get(CONTENT_TYPE_METADATA_KEY) else {
+            return Ok(EventEncoding::Json);
+        };
+        [
+            EventEncoding::Json,
+            EventEncoding::Protobuf,
+            EventEncoding::Msgpack,
+        ]
+        .into_iter()
+        .find(|encoding| content_type.as_str() == Some(encoding.codec().content_type()))
+        .ok_or_else(|| KurrentError::Encode(format!("unknown content type {content_type}")))
+    }
+}
+
+/// Turns an event payload into bytes and back.
+pub trait EventCodec: Send + Sync {
+    fn content_type(&self) -> &'static str;
+    fn encode(&self, payload: &Value) -> Result<Vec<u8>, KurrentError>;
+    fn decode(&self, data: &[u8]) -> Result<Value, KurrentError>;
+}
+
+pub struct JsonCodec;
+
+impl EventCodec for JsonCodec {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn encode(&self, payload: &Value) -> Result<Vec<u8>, KurrentError> {
+        Ok(serde_json::to_vec(payload)?)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Value, KurrentError> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+pub struct ProtobufCodec;
+
+impl EventCodec for ProtobufCodec {
+    fn content_type(&self) -> &'static str {
+        "application/x-protobuf"
+    }
+
+    fn encode(&self, payload: &Value) -> Result<Vec<u8>, KurrentError> {
+        let Value::Object(fields) = payload else {
+            return Err(KurrentError::Encode(
+                "protobuf payloads must be objects".to_string(),
+            ));
+        };
+        Ok(to_struct(fields).encode_to_vec())
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Value, KurrentError> {
+        let message =
+            Struct::decode(data).map_err(|error| KurrentError::Encode(error.to_string()))?;
+        Ok(from_struct(message))
+    }
+}
+
+pub struct MsgpackCodec;
+
+impl EventCodec for MsgpackCodec {
+    fn content_type(&self) -> &'static str {
+        "application/msgpack"
+    }
+
+    fn encode(&self, payload: &Value) -> Result<Vec<u8>, KurrentError> {
+        rmp_serde::to_vec_named(payload).map_err(|error| KurrentError::Encode(error.to_string()))
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Value, KurrentError> {
+        rmp_serde::from_slice(data).map_err(|error| KurrentError::Encode(error.to_string()))
+    }
+}
+
+fn to_struct(fields: &Map<String, Value>) -> Struct {
+    Struct {
+        fields: fields
+            .iter()
+            .map(|(name, value)| (name.clone(), to_protobuf(value)))
+            .collect(),
+    }
+}
+
+fn to_protobuf(value: &Value) -> prost_types::Value {
+    let kind = match value {
+        Value::Null => Kind::NullValue(0),
+        Value::Bool(b) => Kind::BoolValue(*b),
+        Value::Number(n) => Kind::NumberValue(n.as_f64().unwrap_or_default()),
+        Value::String(s) => Kind::StringValue(s.clone()),
+        Value::Array(items) => Kind::ListValue(ListValue {
+            values: items.iter().map(to_protobuf).collect(),
+        }),
+        Value::Object(fields) => Kind::StructValue(to_struct(fields)),
+    };
+    prost_types::Value { kind: Some(kind) }
+}
+
+fn from_struct(message: Struct) -> Value {
+    Value::Object(
+        message
+            .fields
+            .into_iter()
+            .map(|(name, value)| (name, from_protobuf(value)))
+            .collect(),
+    )
+}
+
+fn from_protobuf(value: prost_types::Value) -> Value {
+    match value.kind {
+        None | Some(Kind::NullValue(_)) => Value::Null,
+        Some(Kind::BoolValue(b)) => Value::Bool(b),
+        Some(Kind::NumberValue(n)) => number(n),
+        Some(Kind::StringValue(s)) => Value::String(s),
+        Some(Kind::ListValue(list)) => {
+            Value::Array(list.values.into_iter().map(from_protobuf).collect())
+        }
+        Some(Kind::StructValue(message)) => from_struct(message),
+    }
+}
+
+/// Integral doubles become integers again, so ids and counts round-trip unchanged.
+fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() <= i64::MAX as f64 {
+        Value::from(n as i64)
+    } else {
+        Number::from_f64(n).map_or(Value::Null, Value::Number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn payload() -> Value {
+        json!({
+            "operation_name": "CheckCredit",
+            "field_name": "recordCreditChecked",
+            "loan_id": "loan-123",
+            "alias": null,
+            "arguments": { "input": { "Score": 720, "Ratio": 0.35, "Approved": true } },
+            "selected_fields": [{ "response_key": "Score", "field_name": "Score" }],
+            "sequence": 0
+        })
+    }
+
+    fn round_trip(encoding: EventEncoding) -> Value {
+        let codec = encoding.codec();
+        codec.decode(&codec.encode(&payload()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn json_round_trips() {
+        assert_eq!(payload(), round_trip(EventEncoding::Json));
+    }
+
+    #[test]
+    fn protobuf_round_trips() {
+        assert_eq!(payload(), round_trip(EventEncoding::Protobuf));
+    }
+
+    #[test]
+    fn msgpack_round_trips() {
+        assert_eq!(payload(), round_trip(EventEncoding::Msgpack));
+    }
+
+    #[test]
+    fn encoding_is_read_back_from_the_metadata() {
+        let mut metadata = Map::new();
+        assert_eq!(
+            EventEncoding::Json,
+            EventEncoding::of_metadata(&metadata).unwrap()
+        );
+
+        metadata.insert(
+            CONTENT_TYPE_METADATA_KEY.to_string(),
+            json!("application/msgpack"),
+        );
+        assert_eq!(
+            EventEncoding::Msgpack,
+            EventEncoding::of_metadata(&metadata).unwrap()
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use super::{error::KurrentError, mapper::KurrentConfig};
+
+const PLACEHOLDER_START: &str = "${env.";
+
+/// Replaces `${env.NAME}` placeholders in the options deployments template per
+/// environment: `connection_string`, `stream_prefix`, `query_stream_prefix` and
+/// the `stream_overrides` values. Resolved once, when the service starts.
+pub fn resolve_env_placeholders(config: &mut KurrentConfig) -> Result<(), KurrentError> {
+    resolve_with(config, |name| std::env::var(name).ok())
+}
+
+fn resolve_with(
+    config: &mut KurrentConfig,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<(), KurrentError> {
+    config.connection_string = expand(&config.connection_string, "connection_string", &lookup)?;
+    config.stream_prefix = expand(&config.stream_prefix, "stream_prefix", &lookup)?;
+    config.query_stream_prefix =
+        expand(&config.query_stream_prefix, "query_stream_prefix", &lookup)?;
+    for (field, template) in config.stream_overrides.iter_mut() {
+        *template = expand(template, &format!("stream_overrides.{field}"), &lookup)?;
+    }
+    Ok(())
+}
+
+fn expand(
+    value: &str,
+    option: &str,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<String, KurrentError> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find(PLACEHOLDER_START) {
+        expanded.push_str(&rest[..start]);
+        let name_start = start + PLACEHOLDER_START.len();
+        let Some(name_len) = rest[name_start..].find('}') else {
+            return Err(KurrentError::config(format!(
+                "{option} has an unterminated `{PLACEHOLDER_START}` placeholder"
+            )));
+        };
+        let name = &rest[name_start..name_start + name_len];
+        let resolved = lookup(name).ok_or_else(|| {
+            KurrentError::config(format!(
+                "{option} references environment variable {name}, which is not set"
+            ))
+        })?;
+        expanded.push_str(&resolved);
+        rest = &rest[name_start + name_len + 1..];
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn lookup(name: &str) -> Option<String> {
+        (name == "DEPLOY_ENV").then(|| "staging".to_string())
+    }
+
+    #[test]
+    fn set_variables_are_substituted() {
+        let mut config: KurrentConfig = serde_json::from_value(json!({
+            "connection_string": "kurrentdb://kurrent-${env.DEPLOY_ENV}:2113?tls=false",
+            "stream_prefix": "${env.DEPLOY_ENV}-mutation-",
+            "stream_overrides": { "recordLoanRequested": "${env.DEPLOY_ENV}-loans-{loan_id}" }
+        }))
+        .unwrap();
+
+        resolve_with(&mut config, lookup).unwrap();
+
+        assert_eq!(
+            "kurrentdb://kurrent-staging:2113?tls=false",
+            config.connection_string
+        );
+        assert_eq!("staging-mutation-", config.stream_prefix);
+        assert_eq!(
+            "staging-loans-{loan_id}",
+            config.stream_overrides["recordLoanRequested"]
+        );
+    }
+
+    #[test]
+    fn unset_variables_are_a_configuration_error() {
+        let mut config: KurrentConfig =
+            serde_json::from_value(json!({ "stream_prefix": "${env.UNSET_PREFIX}-" })).unwrap();
+
+        let error = resolve_with(&mut config, lookup).unwrap_err();
+
+        assert_eq!("config", error.kind());
+        assert!(error.to_string().contains("stream_prefix"));
+        assert!(error.to_string().contains("UNSET_PREFIX"));
+    }
+}
@@ -0,0 +1,131 @@
+use std::{error::Error, fmt, io};
+
+use super::connection::{ConnectionFailure, Deadline};
+
+/// Failure modes of the persistence side, so callers can classify them (retry,
+/// dead-letter, metric labels) instead of matching on messages.
+#[derive(Debug)]
+pub enum KurrentError {
+    /// Invalid configuration, detected at startup.
+    Config(String),
+    /// The KurrentDB client could not be created.
+    Connect(io::Error),
+    /// A call or its metadata could not be serialized into an event.
+    Serialize(serde_json::Error),
+    /// A payload could not be encoded or decoded with its `event_encoding` codec.
+    Encode(String),
+    /// An append was not acknowledged. `retryable` is set for connection errors and
+    /// timeouts, where trying again may succeed.
+    Append {
+        stream: String,
+        retryable: bool,
+        source: Deadline<kurrentdb::Error>,
+    },
+    /// A stream could not be read back.
+    Read(kurrentdb::Error),
+    /// The write-ahead log could not be opened or written.
+    Wal(io::Error),
+    /// The circuit breaker is open, so the batch was not appended.
+    CircuitOpen,
+}
+
+impl KurrentError {
+    pub fn config(message: impl Into<String>) -> Self {
+        KurrentError::Config(message.into())
+    }
+
+    pub fn append(stream: &str, source: Deadline<kurrentdb::Error>) -> Self {
+        KurrentError::Append {
+            stream: stream.to_string(),
+            retryable: source.is_connection_error(),
+            source,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            KurrentError::Append {
+                retryable: true,
+                ..
+            }
+        )
+    }
+
+    /// Short label for logs and metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            KurrentError::Config(_) => "config",
+            KurrentError::Connect(_) => "connect",
+            KurrentError::Serialize(_) => "serialize",
+            KurrentError::Encode(_) => "encode",
+            KurrentError::Append { .. } => "append",
+            KurrentError::Read(_) => "read",
+            KurrentError::Wal(_) => "wal",
+            KurrentError::CircuitOpen => "circuit_open",
+        }
+    }
+}
+
+impl fmt::Display for KurrentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KurrentError::Config(message) => f.write_str(message),
+            KurrentError::Connect(error) => write!(f, "cannot create KurrentDB client: {error}"),
+            KurrentError::Serialize(error) => write!(f, "cannot serialize event: {error}"),
+            KurrentError::Encode(message) => write!(f, "cannot encode event: {message}"),
+            KurrentError::Append { stream, source, .. } => {
+                write!(f, "append to {stream} failed: {source}")
+            }
+            KurrentError::Read(error) => write!(f, "cannot read stream: {error}"),
+            KurrentError::Wal(error) => write!(f, "write-ahead log failed: {error}"),
+            KurrentError::CircuitOpen => f.write_str("circuit breaker open, batch not appended"),
+        }
+    }
+}
+
+impl Error for KurrentError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            KurrentError::Config(_) | KurrentError::Encode(_) | KurrentError::CircuitOpen => None,
+            KurrentError::Connect(error) => Some(error),
+            KurrentError::Serialize(error) => Some(error),
+            KurrentError::Append { source, .. } => Some(source),
+            KurrentError::Read(error) => Some(error),
+            KurrentError::Wal(error) => Some(error),
+        }
+    }
+}
+
+impl From<serde_json::Error> for KurrentError {
+    fn from(error: serde_json::Error) -> Self {
+        KurrentError::Serialize(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn only_connection_failures_and_timeouts_are_retryable() {
+        let timed_out = KurrentError::append(
+            "graphql-mutation-recordCreditChecked",
+            Deadline::Elapsed(Duration::from_millis(50)),
+        );
+        let rejected = KurrentError::append(
+            "graphql-mutation-recordCreditChecked",
+            Deadline::Failed(kurrentdb::Error::ResourceNotFound),
+        );
+
+        assert!(timed_out.is_retryable());
+        assert!(!rejected.is_retryable());
+        assert!(!KurrentError::config("bad category").is_retryable());
+        assert_eq!("append", rejected.kind());
+        assert_eq!(
+            "append to graphql-mutation-recordCreditChecked failed: timed out after 50ms",
+            timed_out.to_string()
+        );
+    }
+}
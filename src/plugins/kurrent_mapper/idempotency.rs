@@ -0,0 +1,80 @@
+use lru::LruCache;
+use std::{
+    num::NonZeroUsize,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Bounded record of recently persisted idempotency keys. A key recorded within
+/// `window` is reported as a duplicate; the least recently used keys are evicted
+/// once `capacity` is reached. Keys are recorded once their call is appended, so a
+/// retry of a call that failed to persist is let through.
+pub struct IdempotencyCache {
+    seen: Mutex<LruCache<String, Instant>>,
+    window: Duration,
+}
+
+impl IdempotencyCache {
+    pub fn new(capacity: usize, window: Duration) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            seen: Mutex::new(LruCache::new(capacity)),
+            window,
+        }
+    }
+
+    /// Whether `key` was recorded within the window.
+    pub fn is_duplicate(&self, key: &str) -> bool {
+        self.is_duplicate_at(key, Instant::now())
+    }
+
+    /// Records `key` as persisted.
+    pub fn record(&self, key: &str) {
+        self.record_at(key, Instant::now());
+    }
+
+    fn is_duplicate_at(&self, key: &str, now: Instant) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        seen.get(key)
+            .is_some_and(|at| now.saturating_duration_since(*at) < self.window)
+    }
+
+    fn record_at(&self, key: &str, now: Instant) {
+        self.seen.lock().unwrap().put(key.to_string(), now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_key_within_window_is_a_duplicate() {
+        let cache = IdempotencyCache::new(16, Duration::from_secs(60));
+        let now = Instant::now();
+
+        assert!(!cache.is_duplicate_at("req-1", now));
+        cache.record_at("req-1", now);
+        assert!(cache.is_duplicate_at("req-1", now + Duration::from_secs(30)));
+        assert!(!cache.is_duplicate_at("req-2", now + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn key_is_accepted_again_once_the_window_elapsed() {
+        let cache = IdempotencyCache::new(16, Duration::from_secs(60));
+        let now = Instant::now();
+
+        cache.record_at("req-1", now);
+        assert!(!cache.is_duplicate_at("req-1", now + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn least_recently_used_keys_are_evicted_at_capacity() {
+        let cache = IdempotencyCache::new(1, Duration::from_secs(60));
+        let now = Instant::now();
+
+        cache.record_at("req-1", now);
+        cache.record_at("req-2", now);
+        assert!(!cache.is_duplicate_at("req-1", now));
+    }
+}
@@ -0,0 +1,169 @@
+use kurrentdb::{Client, EventData};
+use serde::Serialize;
+use serde_json::Value;
+use std::{sync::Arc, time::Duration};
+use uuid::Uuid;
+
+use super::{
+    connection::{ReconnectingClient, with_deadline},
+    mapper::KurrentConfig,
+    scan_header::{fnv1a, now_ms},
+};
+
+pub const STARTED_EVENT_TYPE: &str = "Connector.Started";
+pub const STOPPED_EVENT_TYPE: &str = "Connector.Stopped";
+
+/// Payload of the lifecycle events written to the control stream.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LifecycleEvent {
+    pub version: &'static str,
+    pub config_hash: String,
+    pub hostname: String,
+    pub timestamp_ms: u64,
+}
+
+impl LifecycleEvent {
+    fn now(config_hash: &str) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            config_hash: config_hash.to_string(),
+            hostname: hostname(),
+            timestamp_ms: now_ms(),
+        }
+    }
+}
+
+/// FNV-1a of the serialized configuration, so restarts with a changed
+/// configuration stand out in the control stream.
+pub fn config_hash(config: &KurrentConfig) -> String {
+    let serialized = serde_json::to_value(config)
+        .map(|value| canonical_json(&value))
+        .unwrap_or_default();
+    format!("{:08x}", fnv1a(&serialized))
+}
+
+/// JSON with object keys sorted, so map iteration order does not change the hash.
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(fields) => {
+            let mut fields: Vec<_> = fields.iter().collect();
+            fields.sort_by_key(|(key, _)| *key);
+            let fields: Vec<_> = fields
+                .into_iter()
+                .map(|(key, value)| {
+                    format!("{}:{}", Value::from(key.as_str()), canonical_json(value))
+                })
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<_> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        scalar => scalar.to_string(),
+    }
+}
+
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Writes `Connector.Started` when created and `Connector.Stopped` when the last
+/// clone of the service holding it is dropped (shutdown or hot reload). Both
+/// writes are best effort: a failure is logged and never blocks the connector.
+pub(crate) struct LifecycleRecorder {
+    client: Arc<ReconnectingClient<Client>>,
+    stream: String,
+    config_hash: String,
+    timeout: Duration,
+}
+
+impl LifecycleRecorder {
+    pub(crate) fn start(client: Arc<ReconnectingClient<Client>>, config: &KurrentConfig) -> Self {
+        let recorder = Self {
+            client,
+            stream: config.control_stream.clone(),
+            config_hash: config_hash(config),
+            timeout: Duration::from_millis(config.append_timeout_ms),
+        };
+        tokio::spawn(recorder.write(STARTED_EVENT_TYPE));
+        recorder
+    }
+
+    fn write(&self, event_type: &'static str) -> impl Future<Output = ()> + Send + 'static {
+        let client = self.client.current();
+        let stream = self.stream.clone();
+        let timeout = self.timeout;
+        let event = LifecycleEvent::now(&self.config_hash);
+
+        async move {
+            let result = match EventData::json(event_type, &event) {
+                Ok(data) => with_deadline(
+                    timeout,
+                    client.append_to_stream(
+                        stream.clone(),
+                        &Default::default(),
+                        data.id(Uuid::new_v4()),
+                    ),
+                )
+                .await
+                .map(|_| ())
+                .map_err(|err| err.to_string()),
+                Err(err) => Err(err.to_string()),
+            };
+
+            match result {
+                Ok(()) => {
+                    tracing::info!(event_type, control_stream = %stream, "Recorded connector lifecycle event")
+                }
+                Err(error) => {
+                    tracing::warn!(error = %error, event_type, control_stream = %stream, "Failed to record connector lifecycle event")
+                }
+            }
+        }
+    }
+}
+
+impl Drop for LifecycleRecorder {
+    fn drop(&mut self) {
+        // Without a runtime (e.g. the process is already tearing down) there is
+        // nothing left to write with.
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(self.write(STOPPED_EVENT_TYPE));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn config_hash_follows_the_configuration() {
+        let default = KurrentConfig::default();
+        let changed: KurrentConfig =
+            serde_json::from_value(json!({ "stream_prefix": "loans-" })).unwrap();
+
+        assert_eq!(
+            config_hash(&default),
+            config_hash(&KurrentConfig::default())
+        );
+        assert_ne!(config_hash(&default), config_hash(&changed));
+        assert_eq!(8, config_hash(&default).len());
+    }
+
+    #[test]
+    fn lifecycle_event_carries_the_connector_version() {
+        let event = LifecycleEvent::now("0badf00d");
+
+        assert_eq!(env!("CARGO_PKG_VERSION"), event.version);
+        assert_eq!("0badf00d", event.config_hash);
+        assert!(!event.hostname.is_empty());
+    }
+}
@@ -1,10 +1,56 @@
-use kurrentdb::{Client, ClientSettings, EventData};
+use super::{
+    builder::KurrentServiceBuilder,
+    circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitOpenAction},
+    coalescing::{Coalescer, PendingBatch},
+    connection::{Deadline, ReconnectingClient, with_deadline},
+    credentials::{
+        AppendCredentials, AppendCredentialsConfig, read_credentials_file,
+        with_connection_credentials,
+    },
+    deletion::{self, DeleteMutation, StreamDeletion},
+    encoding::{CONTENT_TYPE_METADATA_KEY, EventEncoding},
+    env,
+    error::KurrentError,
+    idempotency::IdempotencyCache,
+    lifecycle::LifecycleRecorder,
+    naming::{
+        self, CaseTransform, EventTypeCase, MissingKeyPolicy, NameSource, SchemaVersion, StreamKey,
+        StreamNameSanitization, StreamNaming,
+    },
+    persist_queue::{self, PersistQueue, QueueFullPolicy},
+    pool::ClientPool,
+    revision_chain::RevisionChain,
+    routing::{self, RoutingRule, TargetConfig},
+    scan_header::{self, ScanHeader},
+    sharding::ShardConfig,
+    stream_limiter::StreamLimiter,
+    stream_metadata::{self, ConfiguredStreams, StreamMetadataConfig},
+    timestamp::EventTimestamp,
+    tls,
+    validation::ArgumentValidators,
+    wal::WriteAheadLog,
+};
+use futures::future::{self, BoxFuture};
+use kurrentdb::{
+    AppendToStreamOptions, Client, ClientSettings, DeleteStreamOptions, EventData,
+    ExpectedRevision, ReadStreamOptions, SetStreamMetadataOptions, StreamPosition,
+    TombstoneStreamOptions,
+};
 use schemars::JsonSchema;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{Map, Value};
-use std::{io, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
 use tokio::task;
-use tower::BoxError;
+use tracing::Instrument;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,41 +59,176 @@ pub struct MutationArg {
     pub value: Value,
 }
 
+fn arguments_to_map(args: &[MutationArg]) -> Map<String, Value> {
+    args.iter()
+        .map(|arg| (arg.name.clone(), arg.value.clone()))
+        .collect()
+}
+
 fn serialize_arguments_as_map<S>(args: &Vec<MutationArg>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    let map: Map<String, Value> = args
-        .iter()
-        .map(|arg| (arg.name.clone(), arg.value.clone()))
-        .collect();
+    let map = arguments_to_map(args);
+    if map.len() < args.len() {
+        tracing::warn!(
+            arguments = ?args.iter().map(|arg| &arg.name).collect::<Vec<_>>(),
+            "Duplicate argument names, only the last value of each is kept in the map form"
+        );
+    }
     map.serialize(serializer)
 }
 
+/// Accepts both the map and the list form, so events written with either
+/// `argument_format` read back.
 fn deserialize_arguments_from_map<'de, D>(deserializer: D) -> Result<Vec<MutationArg>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let map = Map::<String, Value>::deserialize(deserializer)?;
-    Ok(map
-        .into_iter()
-        .map(|(name, value)| MutationArg { name, value })
-        .collect())
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Arguments {
+        Map(Map<String, Value>),
+        List(Vec<MutationArg>),
+    }
+
+    Ok(match Arguments::deserialize(deserializer)? {
+        Arguments::Map(map) => map
+            .into_iter()
+            .map(|(name, value)| MutationArg { name, value })
+            .collect(),
+        Arguments::List(list) => list,
+    })
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Shape of `arguments` in the persisted payload.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ArgumentFormat {
+    /// `{ "input": {...} }`, keyed by argument name.
+    #[default]
+    Map,
+    /// `[{ "name": "input", "value": {...} }]`, in document order.
+    List,
+}
+
+/// A field selected on the mutation's result: the key it appears under in the
+/// response (its alias, if any) and the schema field it resolves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "SelectedFieldRepr")]
+pub struct SelectedField {
+    pub response_key: String,
+    pub field_name: String,
+}
+
+impl SelectedField {
+    pub fn new(response_key: impl Into<String>, field_name: impl Into<String>) -> Self {
+        Self {
+            response_key: response_key.into(),
+            field_name: field_name.into(),
+        }
+    }
+}
+
+/// Events written before selected fields were structured hold plain names.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SelectedFieldRepr {
+    Name(String),
+    Structured {
+        response_key: String,
+        field_name: String,
+    },
+}
+
+impl From<SelectedFieldRepr> for SelectedField {
+    fn from(repr: SelectedFieldRepr) -> Self {
+        match repr {
+            SelectedFieldRepr::Name(name) => SelectedField::new(name.clone(), name),
+            SelectedFieldRepr::Structured {
+                response_key,
+                field_name,
+            } => SelectedField::new(response_key, field_name),
+        }
+    }
+}
+
+/// GraphQL operation type a call was captured from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationType {
+    #[default]
+    Mutation,
+    Query,
+}
+
+impl OperationType {
+    pub fn is_mutation(&self) -> bool {
+        *self == OperationType::Mutation
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct MutationCall {
+    /// Left out of the payload for anonymous operations, unless
+    /// `default_operation_name` is configured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub operation_name: Option<String>,
     pub field_name: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub loan_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub alias: Option<String>,
     #[serde(
         serialize_with = "serialize_arguments_as_map",
         deserialize_with = "deserialize_arguments_from_map"
     )]
     pub arguments: Vec<MutationArg>,
-    pub selected_fields: Vec<String>,
+    pub selected_fields: Vec<SelectedField>,
+    /// Position of the field within the operation, in document (= execution) order.
+    #[serde(default)]
+    pub sequence: usize,
+    /// Stream requested by the operation itself (e.g. `@persist(stream: "loans")`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_override: Option<String>,
+    /// Authenticated user who performed the mutation, taken from a JWT claim.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    /// Set for query fields captured under `operation_types: [query]`.
+    #[serde(default, skip_serializing_if = "OperationType::is_mutation")]
+    pub operation_type: OperationType,
+    /// Subgraph the call was captured from, for calls recorded at the subgraph layer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subgraph: Option<String>,
+    /// Tenant the request was made for; prepended to the stream name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Response `extensions` selected by `capture_extensions`, e.g. a server-assigned
+    /// version.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub extensions: Map<String, Value>,
+    /// Entries written to the KurrentDB event metadata instead of the event payload.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub metadata: Map<String, Value>,
+}
+
+impl MutationCall {
+    /// Arguments keyed by name, as they appear in the persisted payload.
+    pub fn arguments_map(&self) -> Map<String, Value> {
+        arguments_to_map(&self.arguments)
+    }
+
+    /// Looks up a dotted argument path such as `input.NationalID`.
+    pub fn argument_value(&self, path: &str) -> Option<&Value> {
+        let mut segments = path.split('.');
+        let arg_name = segments.next()?;
+        let value = &self
+            .arguments
+            .iter()
+            .find(|arg| arg.name == arg_name)?
+            .value;
+        segments.try_fold(value, |current, segment| current.get(segment))
+    }
 }
 
 fn default_connection_string() -> String {
@@ -58,80 +239,2104 @@ fn default_stream_prefix() -> String {
     "graphql-mutation-".to_string()
 }
 
-#[derive(Debug, Deserialize, JsonSchema)]
+fn default_query_stream_prefix() -> String {
+    "graphql-query-".to_string()
+}
+
+fn default_credentials_reload_interval_ms() -> u64 {
+    10_000
+}
+
+fn default_health_check_interval_ms() -> u64 {
+    5_000
+}
+
+fn default_reconnect_after_failures() -> u32 {
+    3
+}
+
+fn default_append_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_connection_pool_size() -> usize {
+    1
+}
+
+fn default_idempotency_window_ms() -> u64 {
+    60_000
+}
+
+fn default_idempotency_cache_size() -> usize {
+    10_000
+}
+
+fn default_dead_letter_stream() -> String {
+    "graphql-mutation-dead-letter".to_string()
+}
+
+fn default_control_stream() -> String {
+    "graphql-mutation-connector-control".to_string()
+}
+
+fn default_outbox_stream() -> String {
+    "graphql-mutation-outbox".to_string()
+}
+
+fn default_per_stream_concurrency() -> usize {
+    1
+}
+
+fn default_max_batch_size() -> usize {
+    100
+}
+
+fn default_queue_block_timeout_ms() -> u64 {
+    1_000
+}
+
+fn default_queue_workers() -> usize {
+    4
+}
+
+/// Stream read by the health probe. It never exists, so a `ResourceNotFound`
+/// answer is proof enough that the server is reachable.
+const HEALTH_PROBE_STREAM: &str = "$starstuff-health-probe";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct KurrentConfig {
     #[serde(default = "default_connection_string")]
     pub connection_string: String,
+    /// PEM client certificate for mutual TLS; requires `tls_key_path`.
+    #[serde(default)]
+    pub tls_cert_path: Option<String>,
+    /// PEM private key matching `tls_cert_path`.
+    #[serde(default)]
+    pub tls_key_path: Option<String>,
+    /// PEM CA certificate used to verify the server.
+    #[serde(default)]
+    pub tls_ca_path: Option<String>,
     #[serde(default = "default_stream_prefix")]
     pub stream_prefix: String,
+    /// Used instead of `stream_prefix` for captured query fields.
+    #[serde(default = "default_query_stream_prefix")]
+    pub query_stream_prefix: String,
+    #[serde(default)]
+    pub stream_naming: StreamNaming,
+    /// Category used by `stream_naming: category`; must not contain `-`.
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Handling of calls without an aggregate id under `stream_naming: category`.
+    #[serde(default)]
+    pub missing_key_policy: MissingKeyPolicy,
+    /// Whether the field name or the operation name follows `stream_prefix`.
+    #[serde(default)]
+    pub stream_key: StreamKey,
+    /// Whether aliased calls are named after their field name or their alias.
+    #[serde(default)]
+    pub name_source: NameSource,
+    /// Target stream per mutation field name, e.g. `recordLoanRequested: loans`.
+    /// Values may contain placeholders such as `{loan_id}` or `{input.region}`.
+    #[serde(default)]
+    pub stream_overrides: HashMap<String, String>,
+    /// Mutation fields whose stream is split into `count` buckets by a shard key.
+    #[serde(default)]
+    pub shards: HashMap<String, ShardConfig>,
+    /// Stream receiving mutations no stream override, routing rule or shard
+    /// covers and without an aggregate id, as `GraphQL.Unmatched.<field>` events.
+    #[serde(default)]
+    pub catch_all_stream: Option<String>,
+    /// Handling of derived stream names KurrentDB would reject.
+    #[serde(default)]
+    pub stream_name_sanitization: StreamNameSanitization,
+    #[serde(default)]
+    pub event_type_case: EventTypeCase,
+    /// Case of the field and operation names in event types and stream names.
+    #[serde(default)]
+    pub case_transform: CaseTransform,
+    /// Keeps generated names usable by the `$by_category` and `$by_event_type`
+    /// projections: one `-` per stream name, none in event types.
+    #[serde(default)]
+    pub projection_safe: bool,
+    /// Version of each mutation's event shape, e.g. `2` or `{ recordCreditChecked: 2 }`.
+    /// Appended to the event type as `.v2` and recorded as `schema_version` metadata.
+    #[serde(default)]
+    pub schema_version: Option<SchemaVersion>,
+    #[serde(default = "default_health_check_interval_ms")]
+    pub health_check_interval_ms: u64,
+    /// KurrentDB clients, each with its own connection, that appends to the
+    /// primary cluster are spread over round-robin.
+    #[serde(default = "default_connection_pool_size")]
+    pub connection_pool_size: usize,
+    /// Consecutive connection errors after which the client is rebuilt.
+    #[serde(default = "default_reconnect_after_failures")]
+    pub reconnect_after_failures: u32,
+    /// Stop appending after repeated failed batches, for a cooldown, instead of
+    /// attempting (and failing) every batch during an outage.
+    #[serde(default)]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    /// Users appends (and stream metadata writes) are made as, for streams whose ACL
+    /// requires a writer other than the connection's identity.
+    #[serde(default)]
+    pub append_credentials: AppendCredentialsConfig,
+    /// JSON file with the `{ username, password }` to connect as, replacing any
+    /// user in `connection_string`. Re-read every `credentials_reload_interval_ms`;
+    /// when they changed, the client is rebuilt without restarting the router.
+    #[serde(default)]
+    pub credentials_file: Option<String>,
+    #[serde(default = "default_credentials_reload_interval_ms")]
+    pub credentials_reload_interval_ms: u64,
+    /// Upper bound on a single append. A timed-out append counts as a connection
+    /// failure and the call is dead-lettered.
+    #[serde(default = "default_append_timeout_ms")]
+    pub append_timeout_ms: u64,
+    /// Appends in flight per stream. The default of 1 keeps appends to one stream
+    /// in issue order; appends to different streams are never limited.
+    #[serde(default = "default_per_stream_concurrency")]
+    pub per_stream_concurrency: usize,
+    /// Attach a `partition_key` to each event's metadata, resolved from `loan_id`
+    /// (the aggregate id) or a dotted argument path such as `input.NationalID`.
+    #[serde(default)]
+    pub partition_key_field: Option<String>,
+    /// Source of the `timestamp` written to each event's metadata: `now`,
+    /// `{ argument: input.CreditCheckedTimestamp }` or `{ response: createdAt }`.
+    #[serde(default)]
+    pub event_timestamp: Option<EventTimestamp>,
+    /// Skip calls whose idempotency key (`loan_id` or a dotted argument path) was
+    /// already persisted for the same mutation within `idempotency_window_ms`.
+    #[serde(default)]
+    pub idempotency_key_field: Option<String>,
+    #[serde(default = "default_idempotency_window_ms")]
+    pub idempotency_window_ms: u64,
+    #[serde(default = "default_idempotency_cache_size")]
+    pub idempotency_cache_size: usize,
+    /// Before appending a call with an `idempotency_key` in its metadata, read the
+    /// last event of the target stream and skip the append if it carries the same
+    /// key. Costs a read per append.
+    #[serde(default)]
+    pub skip_duplicate_idempotency_keys: bool,
+    /// JSON Schemas (inline, or a path to a schema file) keyed by mutation field
+    /// name. Calls whose arguments do not conform are dead-lettered.
+    #[serde(default)]
+    pub argument_schemas: HashMap<String, Value>,
+    /// Stream receiving calls that cannot be persisted to their target stream.
+    #[serde(default = "default_dead_letter_stream")]
+    pub dead_letter_stream: String,
+    /// Calls whose serialized payload exceeds this many bytes are dead-lettered
+    /// (without their arguments) instead of being appended.
+    #[serde(default)]
+    pub max_event_bytes: Option<usize>,
+    /// Upper bound on the events of one coalesced append. A stream's larger group
+    /// is split into several appends, in order, each expecting the revision the
+    /// previous one left the stream at.
+    #[serde(default)]
+    pub max_append_batch: Option<usize>,
+    /// Append all events of one request to `outbox_stream` in a single, atomic
+    /// append; a projection fans them out to their target streams.
+    #[serde(default)]
+    pub transactional_outbox: bool,
+    #[serde(default = "default_outbox_stream")]
+    pub outbox_stream: String,
+    /// Chain the expected revision of appends to the same stream within one
+    /// request, so a concurrent writer cannot interleave between them.
+    #[serde(default)]
+    pub chain_revisions: bool,
+    /// Argument-based rules sending calls to another cluster and/or stream.
+    #[serde(default)]
+    pub routing_rules: Vec<RoutingRule>,
+    /// Additional KurrentDB clusters addressable from `routing_rules`.
+    #[serde(default)]
+    pub targets: HashMap<String, TargetConfig>,
+    /// Adds a compact hex-encoded `scan_header` (event type hash, aggregate id hash,
+    /// timestamp) to event metadata for consumers that filter without parsing JSON.
+    #[serde(default)]
+    pub scan_header: bool,
+    /// Logs each event's payload at debug level before it is appended, for
+    /// checking what extraction and enrichment produced. Payloads are logged after
+    /// redaction.
+    #[serde(default)]
+    pub log_payloads: bool,
+    #[serde(default)]
+    pub argument_format: ArgumentFormat,
+    /// `operation_name` written to the payload of events from anonymous
+    /// operations, which otherwise have none. Event types are unaffected.
+    #[serde(default)]
+    pub default_operation_name: Option<String>,
+    /// Codec of mutation event payloads. Anything but `json` is written as a
+    /// binary event with its content type in the `content_type` metadata.
+    #[serde(default)]
+    pub event_encoding: EventEncoding,
+    /// Metadata (retention, ACL) set on a mutation field's streams before the first
+    /// append to each, keyed by field name or `*` for all fields.
+    #[serde(default)]
+    pub stream_metadata: HashMap<String, StreamMetadataConfig>,
+    /// Mutation fields that delete an aggregate, keyed by field name. They are
+    /// written as `GraphQL.<aggregate>Deleted` events, after which their stream
+    /// can be soft-deleted or tombstoned if it is per aggregate.
+    #[serde(default)]
+    pub delete_mutations: HashMap<String, DeleteMutation>,
+    /// Allow `delete_mutations` to tombstone streams, which is irreversible.
+    #[serde(default)]
+    pub allow_hard_delete: bool,
+    /// Local file batches are logged to before they are persisted, and replayed
+    /// from on startup until KurrentDB acknowledged them.
+    #[serde(default)]
+    pub wal_path: Option<String>,
+    /// Write `Connector.Started`/`Connector.Stopped` events to `control_stream`.
+    #[serde(default)]
+    pub lifecycle_events: bool,
+    #[serde(default = "default_control_stream")]
+    pub control_stream: String,
+    /// Hold batches for up to this long and persist them together, with one
+    /// append per stream. Unset persists every batch as soon as it arrives.
+    #[serde(default)]
+    pub flush_interval_ms: Option<u64>,
+    /// Buffered calls that flush the held batches before `flush_interval_ms`.
+    #[serde(default = "default_max_batch_size")]
+    pub max_batch_size: usize,
+    /// Batches waiting to be appended, at most; unbounded when unset. Not used
+    /// with `flush_interval_ms`, whose buffer is flushed by size instead.
+    #[serde(default)]
+    pub max_queued_batches: Option<usize>,
+    #[serde(default)]
+    pub queue_full_policy: QueueFullPolicy,
+    #[serde(default = "default_queue_block_timeout_ms")]
+    pub queue_block_timeout_ms: u64,
+    /// Tasks appending queued batches concurrently.
+    #[serde(default = "default_queue_workers")]
+    pub queue_workers: usize,
+}
+
+impl Default for KurrentConfig {
+    /// The defaults applied when an option is omitted from `router.yaml`.
+    fn default() -> Self {
+        serde_json::from_value(Value::Object(Map::new())).expect("every option has a default")
+    }
 }
 
 #[derive(Clone)]
 pub struct KurrentService {
-    client: Arc<Client>,
-    stream_prefix: String,
+    client: Arc<ReconnectingClient<Client>>,
+    /// `client` and the further clients of `connection_pool_size`, which appends to
+    /// the primary cluster are spread over.
+    pool: Arc<ClientPool<Client>>,
+    config: Arc<KurrentConfig>,
+    connected: Arc<AtomicBool>,
+    idempotency: Option<Arc<IdempotencyCache>>,
+    validators: Arc<ArgumentValidators>,
+    targets: Arc<HashMap<String, Arc<ReconnectingClient<Client>>>>,
+    configured_streams: Arc<ConfiguredStreams>,
+    stream_limiter: Arc<StreamLimiter>,
+    wal: Option<Arc<WriteAheadLog>>,
+    coalescer: Option<Coalescer>,
+    queue: Option<PersistQueue>,
+    breaker: Option<Arc<CircuitBreaker>>,
+    /// Held only for its drop, which records the shutdown.
+    _lifecycle: Option<Arc<LifecycleRecorder>>,
 }
 
 pub trait MutationSink: Send + Sync {
     fn persist_mutations(&self, calls: Vec<MutationCall>);
+
+    /// Like `persist_mutations`, but resolves once the calls are persisted (or
+    /// failed to be). Sinks that write synchronously are done when it returns.
+    fn persist_and_wait(
+        &self,
+        calls: Vec<MutationCall>,
+    ) -> BoxFuture<'static, Result<(), KurrentError>> {
+        self.persist_mutations(calls);
+        Box::pin(future::ready(Ok(())))
+    }
+
+    /// Whether the sink can currently accept writes. Sinks without a remote
+    /// dependency are always healthy.
+    fn is_healthy(&self) -> bool {
+        true
+    }
+
+    /// `false` while mutations should be rejected before they execute, because
+    /// what they would persist cannot be taken (`queue_full_policy: reject_request`).
+    fn has_capacity(&self) -> bool {
+        true
+    }
 }
 
 impl KurrentService {
-    pub async fn new(config: KurrentConfig) -> Result<Self, BoxError> {
-        let settings: ClientSettings = config
-            .connection_string
-            .parse()
-            .map_err(|err| -> BoxError { Box::new(err) })?;
+    pub fn builder() -> KurrentServiceBuilder {
+        KurrentServiceBuilder::new()
+    }
 
-        let client = Client::new(settings)
-            .map_err(|err| -> BoxError { Box::new(io::Error::new(io::ErrorKind::Other, err)) })?;
+    pub async fn new(mut config: KurrentConfig) -> Result<Self, KurrentError> {
+        env::resolve_env_placeholders(&mut config)?;
+        naming::validate(&config)?;
+        deletion::validate_delete_mutations(&config)?;
+        persist_queue::validate_queue(&config)?;
+        routing::validate_rules(&config.routing_rules, &config.targets)?;
+        let validators = Arc::new(ArgumentValidators::compile(&config.argument_schemas)?);
+
+        let connection_string = tls::with_client_certificates(&config)?;
+        let credentials = config
+            .credentials_file
+            .as_deref()
+            .map(read_credentials_file)
+            .transpose()?;
+        let settings = Self::client_settings(&connection_string, credentials.as_ref())?;
+
+        let client = Self::connect(&settings)?;
 
         tracing::info!(connection = %config.connection_string, "KurrentService connected to KurrentDB");
 
-        Ok(Self {
-            client: Arc::new(client),
-            stream_prefix: config.stream_prefix,
-        })
+        let reconnecting = |client, settings: ClientSettings| {
+            ReconnectingClient::new(client, config.reconnect_after_failures, move || {
+                Self::connect(&settings).map_err(Into::into)
+            })
+        };
+        let client = Arc::new(reconnecting(client, settings.clone()));
+        let pool = Arc::new(ClientPool::new(
+            client.clone(),
+            config.connection_pool_size,
+            || Ok::<_, KurrentError>(reconnecting(Self::connect(&settings)?, settings.clone())),
+        )?);
+        if pool.len() > 1 {
+            tracing::info!(
+                size = pool.len(),
+                "Appending over a pool of KurrentDB clients"
+            );
+        }
+        let health_check_interval = Duration::from_millis(config.health_check_interval_ms.max(1));
+        let connected = Arc::new(AtomicBool::new(
+            Self::ping(&client.current(), health_check_interval).await,
+        ));
+        if let (Some(path), Some(credentials)) = (&config.credentials_file, credentials) {
+            Self::spawn_credentials_reloader(
+                &pool,
+                connection_string,
+                path.clone(),
+                credentials,
+                Duration::from_millis(config.credentials_reload_interval_ms.max(1)),
+            );
+        }
+
+        let mut targets = HashMap::new();
+        for (name, target) in &config.targets {
+            let settings: ClientSettings = target.connection_string.parse().map_err(|err| {
+                KurrentError::config(format!(
+                    "invalid connection_string for target {name}: {err}"
+                ))
+            })?;
+            let target_client = ReconnectingClient::new(
+                Self::connect(&settings)?,
+                config.reconnect_after_failures,
+                move || Self::connect(&settings).map_err(Into::into),
+            );
+            tracing::info!(target = %name, connection = %target.connection_string, "KurrentService connected to routing target");
+            targets.insert(name.clone(), Arc::new(target_client));
+        }
+
+        Self::spawn_health_monitor(client.clone(), &connected, health_check_interval);
+
+        let idempotency = config.idempotency_key_field.as_ref().map(|_| {
+            Arc::new(IdempotencyCache::new(
+                config.idempotency_cache_size,
+                Duration::from_millis(config.idempotency_window_ms),
+            ))
+        });
+
+        let (wal, pending) = match &config.wal_path {
+            Some(path) => {
+                let (wal, pending) = WriteAheadLog::open(path).map_err(KurrentError::Wal)?;
+                (Some(Arc::new(wal)), pending)
+            }
+            None => (None, Vec::new()),
+        };
+
+        let stream_limiter = Arc::new(StreamLimiter::new(config.per_stream_concurrency));
+        let breaker = config
+            .circuit_breaker
+            .as_ref()
+            .map(|breaker| Arc::new(CircuitBreaker::new(breaker)));
+
+        let lifecycle = config
+            .lifecycle_events
+            .then(|| Arc::new(LifecycleRecorder::start(client.clone(), &config)));
+
+        let mut service = Self {
+            client,
+            pool,
+            config: Arc::new(config),
+            connected,
+            idempotency,
+            validators,
+            targets: Arc::new(targets),
+            configured_streams: Arc::new(ConfiguredStreams::default()),
+            stream_limiter,
+            wal,
+            coalescer: None,
+            queue: None,
+            breaker,
+            _lifecycle: lifecycle,
+        };
+
+        if let Some(interval_ms) = service.config.flush_interval_ms {
+            // The worker's copy has no coalescer of its own, so dropping the last
+            // service handle closes the channel and flushes what is still held.
+            let flusher = service.clone();
+            service.coalescer = Some(Coalescer::spawn(
+                Duration::from_millis(interval_ms),
+                service.config.max_batch_size,
+                move |batches| {
+                    let flusher = flusher.clone();
+                    async move { flusher.persist_coalesced(batches).await }
+                },
+            ));
+        }
+
+        if let Some(capacity) = service.config.max_queued_batches {
+            // Like the flusher, the workers' copy has no queue of its own.
+            let worker = service.clone();
+            service.queue = Some(PersistQueue::spawn(
+                capacity,
+                service.config.queue_workers,
+                service.config.queue_full_policy,
+                Duration::from_millis(service.config.queue_block_timeout_ms),
+                move |batch: PendingBatch| {
+                    let worker = worker.clone();
+                    let span = tracing::info_span!("persist_mutations", count = batch.calls.len());
+                    async move { worker.persist_pending(batch).await }.instrument(span)
+                },
+            ));
+        }
+
+        if !pending.is_empty() {
+            tracing::info!(
+                batches = pending.len(),
+                "Replaying unacknowledged batches from the write-ahead log"
+            );
+        }
+        for (wal_id, calls) in pending {
+            service.spawn_persist(calls, Some(wal_id));
+        }
+
+        Ok(service)
     }
 
-    async fn persist_batch(
-        client: Arc<Client>,
-        stream_prefix: String,
-        calls: Vec<MutationCall>,
-    ) -> Result<(), BoxError> {
-        for call in calls {
-            let stream_name = format!("{}{}", stream_prefix, call.field_name);
-            let event_type = format!(
-                "GraphQL.{}",
-                call.operation_name.as_deref().unwrap_or(&call.field_name)
+    fn client_settings(
+        connection_string: &str,
+        credentials: Option<&AppendCredentials>,
+    ) -> Result<ClientSettings, KurrentError> {
+        let connection_string = match credentials {
+            Some(credentials) => with_connection_credentials(connection_string, credentials),
+            None => connection_string.to_string(),
+        };
+        connection_string
+            .parse()
+            .map_err(|err| KurrentError::config(format!("invalid connection_string: {err}")))
+    }
+
+    /// Rebuilds every pooled client when the credentials in `path` change. Appends
+    /// already in flight finish on the old clients, and queued batches are untouched.
+    fn spawn_credentials_reloader(
+        pool: &Arc<ClientPool<Client>>,
+        connection_string: String,
+        path: String,
+        mut current: AppendCredentials,
+        interval: Duration,
+    ) {
+        let pool = Arc::downgrade(pool);
+
+        task::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+
+            loop {
+                ticker.tick().await;
+                let Some(pool) = pool.upgrade() else {
+                    break;
+                };
+
+                let credentials = match read_credentials_file(&path) {
+                    Ok(credentials) if credentials == current => continue,
+                    Ok(credentials) => credentials,
+                    Err(error) => {
+                        tracing::warn!(error = %error, "Failed to reload KurrentDB credentials, keeping the current ones");
+                        continue;
+                    }
+                };
+                let settings = match Self::client_settings(&connection_string, Some(&credentials)) {
+                    Ok(settings) => settings,
+                    Err(error) => {
+                        tracing::warn!(error = %error, "Rotated KurrentDB credentials are unusable, keeping the current ones");
+                        continue;
+                    }
+                };
+                let reconnected = pool.iter().try_for_each(|client| {
+                    let settings = settings.clone();
+                    client.reconnect_with(move || Self::connect(&settings).map_err(Into::into))
+                });
+                match reconnected {
+                    Ok(()) => {
+                        tracing::info!(user = %credentials.username, "Reconnected to KurrentDB with rotated credentials");
+                        current = credentials;
+                    }
+                    Err(error) => {
+                        tracing::warn!(error = %error, "Failed to reconnect with rotated credentials, keeping the current client");
+                    }
+                }
+            }
+        });
+    }
+
+    fn connect(settings: &ClientSettings) -> Result<Client, KurrentError> {
+        Client::new(settings.clone())
+            .map_err(|err| KurrentError::Connect(io::Error::new(io::ErrorKind::Other, err)))
+    }
+
+    /// Last known reachability of KurrentDB, refreshed by the health monitor.
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Probes KurrentDB with a one-event read; one not answered within `timeout`
+    /// counts as unreachable.
+    async fn ping(client: &Client, timeout: Duration) -> bool {
+        let options = ReadStreamOptions::default().max_count(1);
+        let probe = async {
+            match client.read_stream(HEALTH_PROBE_STREAM, &options).await {
+                Ok(mut stream) => stream.next().await.map(|_| ()),
+                Err(err) => Err(err),
+            }
+        };
+
+        match tokio::time::timeout(timeout, probe).await {
+            Ok(Ok(()) | Err(kurrentdb::Error::ResourceNotFound)) => true,
+            Ok(Err(error)) => {
+                tracing::debug!(error = %error, "KurrentDB health probe failed");
+                false
+            }
+            Err(_) => {
+                tracing::debug!(
+                    timeout_ms = timeout.as_millis() as u64,
+                    "KurrentDB health probe timed out"
+                );
+                false
+            }
+        }
+    }
+
+    fn spawn_health_monitor(
+        client: Arc<ReconnectingClient<Client>>,
+        connected: &Arc<AtomicBool>,
+        interval: Duration,
+    ) {
+        // Hold only a weak reference so the monitor stops once the service is dropped
+        // (e.g. after a router hot reload).
+        let connected = Arc::downgrade(connected);
+
+        task::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+
+            loop {
+                ticker.tick().await;
+                let Some(connected) = connected.upgrade() else {
+                    break;
+                };
+
+                let reachable = Self::ping(&client.current(), interval).await;
+                if connected.swap(reachable, Ordering::Relaxed) != reachable {
+                    if reachable {
+                        tracing::info!("KurrentDB is reachable again");
+                    } else {
+                        tracing::warn!("KurrentDB became unreachable");
+                    }
+                }
+            }
+        });
+    }
+
+    async fn persist_batch(&self, calls: Vec<MutationCall>) -> Result<(), KurrentError> {
+        if self.config.transactional_outbox {
+            return self.persist_outbox(calls).await;
+        }
+
+        let mut chain = RevisionChain::default();
+        for mut call in calls {
+            let (mut stream_name, client) = self.target(&call);
+            let event_type = naming::event_type(&call, &self.config);
+            let event_id = assigned_event_id(&call);
+
+            let span = tracing::info_span!(
+                "persist_mutation",
+                field_name = %call.field_name,
+                operation_name = ?call.operation_name,
+                stream = %stream_name,
+                event_id = %event_id,
             );
 
-            let event_id = Uuid::new_v4();
-            let event = EventData::json(&event_type, &call)
-                .map_err(|err| -> BoxError { Box::new(err) })?
-                .id(event_id);
+            async {
+                if let Some(reason) = self.rejection_reason(&mut call, &mut stream_name) {
+                    return self.dead_letter(call, &stream_name, reason).await;
+                }
+                if chain.is_aborted(&stream_name) {
+                    let reason = "revision chain aborted by an earlier append of this request".to_string();
+                    return self.dead_letter(call, &stream_name, reason).await;
+                }
+
+                self.ensure_stream_metadata(client, &call.field_name, &stream_name)
+                    .await;
+                let metadata = self.take_metadata(&mut call, &event_type, &stream_name);
+                if self.repeats_last_event(client, &stream_name, &metadata).await {
+                    tracing::info!(stream = %stream_name, "Skipping mutation whose idempotency key matches the last event of its stream");
+                    return Ok(());
+                }
+                if self.config.log_payloads {
+                    log_payload(&event_type, &stream_name, &call, &self.config);
+                }
+                let event = build_event(
+                    &event_type,
+                    event_id,
+                    &call,
+                    &metadata,
+                    &self.config,
+                )?;
+                match self
+                    .append(client, &stream_name, chain.expected(&stream_name), vec![event])
+                    .await
+                {
+                    Ok(revision) => {
+                        self.record_persisted([&call]);
+                        if self.config.chain_revisions {
+                            chain.appended(&stream_name, revision);
+                        }
+                        if let Some(deletion) =
+                            deletion::stream_deletion(&call, &stream_name, &self.config)
+                        {
+                            self.delete_stream(
+                                client,
+                                &stream_name,
+                                deletion,
+                                ExpectedRevision::Exact(revision),
+                            )
+                            .await;
+                        }
+                    }
+                    Err(Deadline::Elapsed(timeout)) => {
+                        // The append may still land, so the chain's revision is unknown.
+                        if self.config.chain_revisions {
+                            chain.abort(&stream_name);
+                        }
+                        let reason = format!("append timed out after {}ms", timeout.as_millis());
+                        return self.dead_letter(call, &stream_name, reason).await;
+                    }
+                    Err(Deadline::Failed(kurrentdb::Error::WrongExpectedVersion { .. }))
+                        if self.config.chain_revisions =>
+                    {
+                        tracing::warn!(stream = %stream_name, "Concurrent write interleaved with this request, aborting its remaining appends to the stream");
+                        chain.abort(&stream_name);
+                        let reason = "append conflicted with a concurrent writer".to_string();
+                        return self.dead_letter(call, &stream_name, reason).await;
+                    }
+                    Err(err) => return Err(KurrentError::append(&stream_name, err)),
+                }
 
-            client
-                .append_to_stream(stream_name.clone(), &Default::default(), event)
+                tracing::info!(event_type = %event_type, "Persisted GraphQL mutation event to KurrentDB");
+                Ok::<(), KurrentError>(())
+            }
+            .instrument(span)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Persists batches held by the coalescer, acknowledging their write-ahead log
+    /// entries once all of them are persisted.
+    async fn persist_coalesced(&self, batches: Vec<PendingBatch>) {
+        let wal_ids: Vec<u64> = batches.iter().filter_map(|batch| batch.wal_id).collect();
+        let calls: Vec<MutationCall> = batches.into_iter().flat_map(|batch| batch.calls).collect();
+        let span = tracing::info_span!("persist_mutations", count = calls.len(), coalesced = true);
+
+        let result = if self.config.transactional_outbox {
+            self.persist_outbox(calls).instrument(span).await
+        } else {
+            self.persist_grouped(calls).instrument(span).await
+        };
+        self.record_outcome(&result);
+        match result {
+            Ok(()) => self.ack_wal(&wal_ids),
+            Err(error) => {
+                tracing::error!(error = %error, kind = error.kind(), retryable = error.is_retryable(), "Failed to persist mutations to KurrentDB");
+            }
+        }
+    }
+
+    /// Appends the events of each stream in one append, or in appends of at most
+    /// `max_append_batch` events, deleting the stream right after the event of each
+    /// delete mutation. Rejected calls are dead-lettered individually; a timed-out
+    /// append dead-letters its events and those of the stream's later appends, as
+    /// does tombstoning the stream.
+    async fn persist_grouped(&self, calls: Vec<MutationCall>) -> Result<(), KurrentError> {
+        let mut groups: Vec<StreamGroup> = Vec::new();
+        for mut call in calls {
+            let (mut stream_name, client) = self.target(&call);
+            if let Some(reason) = self.rejection_reason(&mut call, &mut stream_name) {
+                self.dead_letter(call, &stream_name, reason).await?;
+                continue;
+            }
+
+            let event_type = naming::event_type(&call, &self.config);
+            let event_id = assigned_event_id(&call);
+            let metadata = self.take_metadata(&mut call, &event_type, &stream_name);
+            if self
+                .repeats_last_event(client, &stream_name, &metadata)
                 .await
-                .map_err(|err| -> BoxError { Box::new(err) })?;
+            {
+                tracing::info!(stream = %stream_name, "Skipping mutation whose idempotency key matches the last event of its stream");
+                continue;
+            }
+            if self.config.log_payloads {
+                log_payload(&event_type, &stream_name, &call, &self.config);
+            }
+            let event = build_event(&event_type, event_id, &call, &metadata, &self.config)?;
+            let deletion = deletion::stream_deletion(&call, &stream_name, &self.config);
 
-            tracing::info!(stream = %stream_name, event_type = %event_type, event_id = %event_id, "Persisted GraphQL mutation event to KurrentDB");
+            let group = match groups.iter().position(|group| {
+                group.stream == stream_name && self.same_cluster(group.client, client)
+            }) {
+                Some(index) => &mut groups[index],
+                None => {
+                    groups.push(StreamGroup {
+                        stream: stream_name,
+                        client,
+                        events: Vec::new(),
+                        calls: Vec::new(),
+                        deletions: Vec::new(),
+                    });
+                    groups.last_mut().expect("just pushed")
+                }
+            };
+            group.events.push(event);
+            group.calls.push(call);
+            group.deletions.push(deletion);
+        }
+
+        for group in groups {
+            self.ensure_stream_metadata(group.client, &group.calls[0].field_name, &group.stream)
+                .await;
+            let mut events = group.events.into_iter();
+            let mut expected = ExpectedRevision::Any;
+            let mut persisted = 0;
+            let mut unpersisted = None;
+            for (count, deletion) in deletion::deletion_segments(&group.deletions) {
+                let chained = append_chained(
+                    append_batches(
+                        events.by_ref().take(count).collect(),
+                        self.config.max_append_batch,
+                    ),
+                    expected,
+                    |expected, events| self.append(group.client, &group.stream, expected, events),
+                )
+                .await;
+                persisted += chained.appended;
+                if chained.appended > 0 {
+                    tracing::info!(stream = %group.stream, count = chained.appended, "Persisted coalesced GraphQL mutation events to KurrentDB");
+                }
+                match chained.outcome {
+                    Ok(()) => {}
+                    Err(Deadline::Elapsed(timeout)) => {
+                        unpersisted =
+                            Some(format!("append timed out after {}ms", timeout.as_millis()));
+                        break;
+                    }
+                    Err(err) => return Err(KurrentError::append(&group.stream, err)),
+                }
+                expected = chained.expected;
+                if let Some(deletion) = deletion {
+                    self.delete_stream(group.client, &group.stream, deletion, expected)
+                        .await;
+                    // A soft-deleted stream is recreated by the next append, whatever its revision.
+                    expected = ExpectedRevision::Any;
+                    if deletion == StreamDeletion::Hard {
+                        unpersisted =
+                            Some("stream was tombstoned by an earlier delete mutation".to_string());
+                        break;
+                    }
+                }
+            }
+            self.record_persisted(&group.calls[..persisted]);
+            if let Some(reason) = unpersisted {
+                for call in group.calls.into_iter().skip(persisted) {
+                    self.dead_letter(call, &group.stream, reason.clone())
+                        .await?;
+                }
+            }
         }
 
         Ok(())
     }
+
+    /// Writes every call of the batch to the outbox stream in one append, so the
+    /// request is persisted all or nothing. Each event records its intended stream
+    /// as `target_stream` in its metadata. If any call is rejected, the whole batch
+    /// is dead-lettered instead.
+    async fn persist_outbox(&self, calls: Vec<MutationCall>) -> Result<(), KurrentError> {
+        let outbox = &self.config.outbox_stream;
+        let mut batch = Vec::with_capacity(calls.len());
+        let mut rejection = None;
+        for mut call in calls {
+            let (mut stream_name, _) = self.target(&call);
+            if rejection.is_none() {
+                rejection = self.rejection_reason(&mut call, &mut stream_name);
+            }
+            batch.push((call, stream_name));
+        }
+
+        if let Some(reason) = rejection {
+            return self.dead_letter_batch(batch, &reason).await;
+        }
+
+        let mut events = Vec::with_capacity(batch.len());
+        for (call, stream_name) in batch.iter_mut() {
+            let event_type = naming::event_type(call, &self.config);
+            let event_id = assigned_event_id(call);
+            let metadata = self.take_metadata(call, &event_type, stream_name);
+            let metadata = outbox_metadata(metadata, stream_name);
+            events.push(build_event(
+                &event_type,
+                event_id,
+                call,
+                &metadata,
+                &self.config,
+            )?);
+        }
+
+        let count = events.len();
+        match self
+            .append(&self.client, outbox, ExpectedRevision::Any, events)
+            .await
+        {
+            Ok(_) => {
+                tracing::info!(outbox = %outbox, count, "Persisted GraphQL mutation batch to the outbox");
+                self.record_persisted(batch.iter().map(|(call, _)| call));
+                Ok(())
+            }
+            Err(Deadline::Elapsed(timeout)) => {
+                let reason = format!("outbox append timed out after {}ms", timeout.as_millis());
+                self.dead_letter_batch(batch, &reason).await
+            }
+            Err(err) => Err(KurrentError::append(outbox, err)),
+        }
+    }
+
+    async fn dead_letter_batch(
+        &self,
+        batch: Vec<(MutationCall, String)>,
+        reason: &str,
+    ) -> Result<(), KurrentError> {
+        for (call, stream_name) in batch {
+            self.dead_letter(
+                call,
+                &stream_name,
+                format!("outbox batch rejected: {reason}"),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Stream and connection a call is written to, after routing rules.
+    fn target(&self, call: &MutationCall) -> (String, &ReconnectingClient<Client>) {
+        let route = routing::matching_rule(call, &self.config.routing_rules);
+        let stream_name = match route.and_then(|rule| rule.stream.clone()) {
+            Some(stream) => naming::tenant_scoped(call, stream),
+            None => naming::stream_name(call, &self.config),
+        };
+        (
+            stream_name,
+            self.client_for(route.and_then(|rule| rule.target.as_deref())),
+        )
+    }
+
+    /// Why `call` must be dead-lettered instead of appended, if it must. Sanitizes
+    /// `stream_name` in place.
+    fn rejection_reason(
+        &self,
+        call: &mut MutationCall,
+        stream_name: &mut String,
+    ) -> Option<String> {
+        if naming::missing_key_policy(call, &self.config) == Some(MissingKeyPolicy::DeadLetter) {
+            return Some("no aggregate id for its per-aggregate stream".to_string());
+        }
+
+        match naming::sanitize_stream_name(stream_name, self.config.stream_name_sanitization) {
+            Ok(sanitized) => *stream_name = sanitized,
+            Err(reason) => return Some(reason),
+        }
+
+        if let Err(reason) = self.validators.validate(call) {
+            return Some(reason);
+        }
+
+        let reason = oversize_reason(call, self.config.max_event_bytes)?;
+        // The arguments are what made it too big; keep the dead letter appendable.
+        call.arguments.clear();
+        Some(reason)
+    }
+
+    /// Event metadata for `call`; the call's own metadata moves there so it is not
+    /// duplicated in the payload.
+    fn take_metadata(
+        &self,
+        call: &mut MutationCall,
+        event_type: &str,
+        stream_name: &str,
+    ) -> Map<String, Value> {
+        let mut metadata = event_metadata(call, &self.config);
+        if self.config.scan_header {
+            let header = ScanHeader::for_call(call, event_type, stream_name, scan_header::now_ms());
+            metadata.insert("scan_header".to_string(), Value::String(header.encode()));
+        }
+        call.metadata = Map::new();
+        metadata
+    }
+
+    /// Whether `skip_duplicate_idempotency_keys` applies and the last event of
+    /// `stream_name` carries the idempotency key of `metadata`. A failed read is
+    /// logged and never blocks the append.
+    async fn repeats_last_event(
+        &self,
+        client: &ReconnectingClient<Client>,
+        stream_name: &str,
+        metadata: &Map<String, Value>,
+    ) -> bool {
+        if !self.config.skip_duplicate_idempotency_keys {
+            return false;
+        }
+        let Some(key) = metadata.get(IDEMPOTENCY_KEY_METADATA_KEY) else {
+            return false;
+        };
+
+        let stream = stream_name.to_string();
+        let timeout = Duration::from_millis(self.config.append_timeout_ms);
+        let mut options = ReadStreamOptions::default()
+            .position(StreamPosition::End)
+            .backwards()
+            .max_count(1);
+        if let Some(credentials) = self.config.append_credentials.for_stream(stream_name) {
+            options = options.authenticated(credentials.to_credentials());
+        }
+        let result = client
+            .run(|client| async move {
+                with_deadline(timeout, async move {
+                    let mut events = client.read_stream(stream, &options).await?;
+                    events.next().await
+                })
+                .await
+            })
+            .await;
+
+        match result {
+            Ok(Some(last)) => {
+                carries_idempotency_key(&last.get_original_event().custom_metadata, key)
+            }
+            Ok(None) | Err(Deadline::Failed(kurrentdb::Error::ResourceNotFound)) => false,
+            Err(error) => {
+                tracing::warn!(error = %error, stream = %stream_name, "Failed to read the last event for the idempotency check, appending anyway");
+                false
+            }
+        }
+    }
+
+    /// Sets the configured stream metadata before the first append to `stream_name`.
+    /// A failure is logged and retried on the next append; it never blocks the event.
+    async fn ensure_stream_metadata(
+        &self,
+        client: &ReconnectingClient<Client>,
+        field_name: &str,
+        stream_name: &str,
+    ) {
+        let Some(config) = stream_metadata::metadata_for(&self.config.stream_metadata, field_name)
+        else {
+            return;
+        };
+        if self.configured_streams.contains(stream_name) {
+            return;
+        }
+
+        let metadata = config.to_metadata();
+        let stream = stream_name.to_string();
+        let timeout = Duration::from_millis(self.config.append_timeout_ms);
+        let mut options = SetStreamMetadataOptions::default();
+        if let Some(credentials) = self.config.append_credentials.for_stream(stream_name) {
+            options = options.authenticated(credentials.to_credentials());
+        }
+        let result = client
+            .run(|client| async move {
+                with_deadline(
+                    timeout,
+                    client.set_stream_metadata(stream, &options, &metadata),
+                )
+                .await
+            })
+            .await;
+
+        match result {
+            Ok(_) => {
+                self.configured_streams.insert(stream_name);
+                tracing::debug!(stream = %stream_name, "Set stream metadata");
+            }
+            Err(error) => {
+                tracing::warn!(error = %error, stream = %stream_name, "Failed to set stream metadata, appending anyway");
+            }
+        }
+    }
+
+    /// Soft-deletes or tombstones the stream of a delete mutation once its deletion
+    /// event is appended, expecting the stream to be where that append left it. A
+    /// failure is logged; the deletion event is persisted either way.
+    async fn delete_stream(
+        &self,
+        client: &ReconnectingClient<Client>,
+        stream_name: &str,
+        deletion: StreamDeletion,
+        expected: ExpectedRevision,
+    ) {
+        let stream = stream_name.to_string();
+        let timeout = Duration::from_millis(self.config.append_timeout_ms);
+        let credentials = self
+            .config
+            .append_credentials
+            .for_stream(stream_name)
+            .map(|credentials| credentials.to_credentials());
+        let result = match deletion {
+            StreamDeletion::None => return,
+            StreamDeletion::Soft => {
+                let mut options = DeleteStreamOptions::default().expected_revision(expected);
+                if let Some(credentials) = credentials {
+                    options = options.authenticated(credentials);
+                }
+                client
+                    .run(|client| async move {
+                        with_deadline(timeout, client.delete_stream(stream, &options)).await
+                    })
+                    .await
+            }
+            StreamDeletion::Hard => {
+                let mut options = TombstoneStreamOptions::default().expected_revision(expected);
+                if let Some(credentials) = credentials {
+                    options = options.authenticated(credentials);
+                }
+                client
+                    .run(|client| async move {
+                        with_deadline(timeout, client.tombstone_stream(stream, &options)).await
+                    })
+                    .await
+            }
+        };
+
+        match result {
+            Ok(_) => {
+                tracing::info!(stream = %stream_name, deletion = ?deletion, "Deleted the stream of a delete mutation");
+            }
+            Err(error) => {
+                tracing::warn!(error = %error, stream = %stream_name, deletion = ?deletion, "Failed to delete the stream of a delete mutation");
+            }
+        }
+    }
+
+    /// Persists `calls` in the background, acknowledging `wal_id` on success. A
+    /// failed batch stays in the write-ahead log for the next startup.
+    fn spawn_persist(&self, calls: Vec<MutationCall>, wal_id: Option<u64>) {
+        let service = self.clone();
+        // Created here so the batch span is parented to the request that produced it;
+        // spans are not carried into spawned tasks automatically.
+        let span = tracing::info_span!("persist_mutations", count = calls.len());
+
+        task::spawn(
+            async move {
+                service
+                    .persist_pending(PendingBatch { calls, wal_id })
+                    .await
+            }
+            .instrument(span),
+        );
+    }
+
+    async fn persist_pending(&self, batch: PendingBatch) {
+        let result = self.persist_batch(batch.calls).await;
+        self.record_outcome(&result);
+        match result {
+            Ok(()) => self.ack_wal(batch.wal_id.as_slice()),
+            Err(error) => {
+                tracing::error!(error = %error, kind = error.kind(), retryable = error.is_retryable(), "Failed to persist mutations to KurrentDB");
+            }
+        }
+    }
+
+    /// Hands a batch to the bounded queue, if there is one, applying its
+    /// `queue_full_policy`; spawns its append otherwise.
+    fn enqueue(&self, calls: Vec<MutationCall>, wal_id: Option<u64>) {
+        let Some(queue) = &self.queue else {
+            return self.spawn_persist(calls, wal_id);
+        };
+        let batch = PendingBatch { calls, wal_id };
+
+        if queue.policy() == QueueFullPolicy::BlockWithTimeout {
+            let service = self.clone();
+            task::spawn(async move {
+                let Some(queue) = &service.queue else { return };
+                if let Some(dropped) = queue.push_or_wait(batch).await {
+                    service.drop_queued(dropped);
+                }
+            });
+        } else if let Some(dropped) = queue.push(batch) {
+            self.drop_queued(dropped);
+        }
+    }
+
+    /// Drops a batch the full queue had no room for. It is acknowledged in the
+    /// write-ahead log, as dropping it was the configured choice.
+    fn drop_queued(&self, batch: PendingBatch) {
+        tracing::warn!(
+            monotonic_counter.kurrent_queue_dropped_total = batch.calls.len() as u64,
+            policy = ?self.config.queue_full_policy,
+            count = batch.calls.len(),
+            "Persist queue is full, dropping mutation(s)"
+        );
+        self.ack_wal(batch.wal_id.as_slice());
+    }
+
+    /// Feeds a batch's outcome to the circuit breaker. Only retryable append errors
+    /// count as failures; anything else means KurrentDB answered.
+    fn record_outcome(&self, result: &Result<(), KurrentError>) {
+        let Some(breaker) = &self.breaker else {
+            return;
+        };
+        match result {
+            Err(error) if error.is_retryable() => breaker.record_failure(),
+            _ => breaker.record_success(),
+        }
+    }
+
+    /// Handles a batch refused by the open circuit breaker, without appending it.
+    fn reject_while_open(
+        &self,
+        calls: Vec<MutationCall>,
+        wal_id: Option<u64>,
+        action: CircuitOpenAction,
+    ) {
+        tracing::debug!(
+            monotonic_counter.kurrent_circuit_rejected_total = calls.len() as u64,
+            action = ?action,
+            "Circuit breaker open, not appending mutation(s)"
+        );
+        match action {
+            // Left unacknowledged, so it is replayed on the next startup.
+            CircuitOpenAction::Wal if wal_id.is_some() => {}
+            CircuitOpenAction::Wal | CircuitOpenAction::Drop => {
+                tracing::warn!(
+                    count = calls.len(),
+                    "Dropping mutations while the circuit breaker is open"
+                );
+                self.ack_wal(wal_id.as_slice());
+            }
+            CircuitOpenAction::DeadLetter => {
+                let service = self.clone();
+                task::spawn(async move {
+                    for call in calls {
+                        let (stream_name, _) = service.target(&call);
+                        let reason = "circuit breaker open".to_string();
+                        if let Err(error) = service.dead_letter(call, &stream_name, reason).await {
+                            tracing::error!(error = %error, "Failed to dead-letter mutation while the circuit breaker is open");
+                            return;
+                        }
+                    }
+                    service.ack_wal(wal_id.as_slice());
+                });
+            }
+        }
+    }
+
+    fn ack_wal(&self, ids: &[u64]) {
+        let Some(wal) = &self.wal else {
+            return;
+        };
+        for id in ids {
+            if let Err(error) = wal.ack(*id) {
+                tracing::warn!(error = %error, "Failed to acknowledge batch in the write-ahead log");
+            }
+        }
+    }
+
+    fn client_for(&self, target: Option<&str>) -> &ReconnectingClient<Client> {
+        match target.and_then(|name| self.targets.get(name)) {
+            Some(client) => client,
+            None => self.pool.pick(),
+        }
+    }
+
+    /// Whether `a` and `b` append to the same cluster.
+    fn same_cluster(&self, a: &ReconnectingClient<Client>, b: &ReconnectingClient<Client>) -> bool {
+        std::ptr::eq(a, b) || (self.pool.contains(a) && self.pool.contains(b))
+    }
+
+    async fn append(
+        &self,
+        client: &ReconnectingClient<Client>,
+        stream_name: &str,
+        expected: ExpectedRevision,
+        events: Vec<EventData>,
+    ) -> Result<u64, Deadline<kurrentdb::Error>> {
+        let _permit = self.stream_limiter.acquire(stream_name).await;
+        let stream = stream_name.to_string();
+        let timeout = Duration::from_millis(self.config.append_timeout_ms);
+        let mut options = AppendToStreamOptions::default().expected_revision(expected);
+        if let Some(credentials) = self.config.append_credentials.for_stream(stream_name) {
+            tracing::debug!(stream = %stream_name, user = %credentials.username, "Appending with configured credentials");
+            options = options.authenticated(credentials.to_credentials());
+        }
+        let result = client
+            .run(|client| async move {
+                with_deadline(timeout, client.append_to_stream(stream, &options, events)).await
+            })
+            .await;
+
+        match result {
+            Ok(written) => Ok(written.next_expected_version),
+            Err(Deadline::Elapsed(timeout)) => {
+                tracing::warn!(stream = %stream_name, timeout_ms = timeout.as_millis() as u64, "KurrentDB append timed out");
+                Err(Deadline::Elapsed(timeout))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes a call that cannot go to its target stream to the dead-letter stream,
+    /// together with the reason and the stream it was meant for.
+    async fn dead_letter(
+        &self,
+        call: MutationCall,
+        intended_stream: &str,
+        reason: String,
+    ) -> Result<(), KurrentError> {
+        tracing::warn!(reason = %reason, dead_letter_stream = %self.config.dead_letter_stream, "Routing mutation to dead-letter stream");
+
+        let payload = dead_letter_payload(&call, intended_stream, &reason);
+        let event = EventData::json(DEAD_LETTER_EVENT_TYPE, &payload)?.id(Uuid::new_v4());
+        let dead_letter_stream = &self.config.dead_letter_stream;
+        self.append(
+            &self.client,
+            dead_letter_stream,
+            ExpectedRevision::Any,
+            vec![event],
+        )
+        .await
+        .map(|_| ())
+        .map_err(|err| KurrentError::append(dead_letter_stream, err))
+    }
+}
+
+pub const DEAD_LETTER_EVENT_TYPE: &str = "GraphQL.DeadLetter";
+
+fn build_event(
+    event_type: &str,
+    event_id: Uuid,
+    call: &MutationCall,
+    metadata: &Map<String, Value>,
+    config: &KurrentConfig,
+) -> Result<EventData, KurrentError> {
+    let payload = event_payload(call, config)?;
+    let (event, metadata) = match config.event_encoding {
+        EventEncoding::Json => (
+            EventData::json(event_type, &payload)?,
+            Cow::Borrowed(metadata),
+        ),
+        encoding => {
+            let codec = encoding.codec();
+            let mut metadata = metadata.clone();
+            metadata.insert(
+                CONTENT_TYPE_METADATA_KEY.to_string(),
+                Value::from(codec.content_type()),
+            );
+            (
+                EventData::binary(event_type, codec.encode(&payload)?.into()),
+                Cow::Owned(metadata),
+            )
+        }
+    };
+    let mut event = event.id(event_id);
+
+    if !metadata.is_empty() {
+        event = event.metadata_as_json(&*metadata)?;
+    }
+
+    Ok(event)
+}
+
+fn event_payload(call: &MutationCall, config: &KurrentConfig) -> Result<Value, serde_json::Error> {
+    let mut payload = serde_json::to_value(call)?;
+    if config.argument_format == ArgumentFormat::List {
+        payload["arguments"] = serde_json::to_value(&call.arguments)?;
+    }
+    if call.operation_name.is_none()
+        && let Some(name) = &config.default_operation_name
+    {
+        payload["operation_name"] = Value::from(name.clone());
+    }
+    Ok(payload)
+}
+
+/// Logs the payload `call` is appended with, as a structured `payload` field.
+fn log_payload(event_type: &str, stream: &str, call: &MutationCall, config: &KurrentConfig) {
+    match event_payload(call, config) {
+        Ok(payload) => {
+            tracing::debug!(event_type = %event_type, stream = %stream, payload = %payload, "Resolved event payload");
+        }
+        Err(error) => {
+            tracing::debug!(event_type = %event_type, stream = %stream, error = %error, "Event payload could not be serialized for logging");
+        }
+    }
+}
+
+fn dead_letter_payload(call: &MutationCall, intended_stream: &str, reason: &str) -> Value {
+    serde_json::json!({
+        "reason": reason,
+        "intended_stream": intended_stream,
+        "call": call,
+    })
+}
+
+/// Events of a coalesced flush bound for the same stream on the same cluster.
+struct StreamGroup<'a> {
+    stream: String,
+    client: &'a ReconnectingClient<Client>,
+    events: Vec<EventData>,
+    calls: Vec<MutationCall>,
+    /// Per call, the deletion of the stream to run once its event is appended.
+    deletions: Vec<Option<StreamDeletion>>,
+}
+
+/// Where [`append_chained`] left a stream.
+struct ChainedAppend {
+    /// Events appended before the chain completed or stopped.
+    appended: usize,
+    /// Revision the next append to the stream should expect.
+    expected: ExpectedRevision,
+    outcome: Result<(), Deadline<kurrentdb::Error>>,
+}
+
+/// Appends one stream's `batches` in order through `append`, the first expecting
+/// `expected` and each later one exactly the revision the previous one produced.
+/// Stops at the first failed append.
+async fn append_chained<T, F, Fut>(
+    batches: Vec<Vec<T>>,
+    mut expected: ExpectedRevision,
+    mut append: F,
+) -> ChainedAppend
+where
+    F: FnMut(ExpectedRevision, Vec<T>) -> Fut,
+    Fut: Future<Output = Result<u64, Deadline<kurrentdb::Error>>>,
+{
+    let mut appended = 0;
+    for batch in batches {
+        let count = batch.len();
+        match append(expected, batch).await {
+            Ok(revision) => {
+                expected = ExpectedRevision::Exact(revision);
+                appended += count;
+            }
+            Err(error) => {
+                return ChainedAppend {
+                    appended,
+                    expected,
+                    outcome: Err(error),
+                };
+            }
+        }
+    }
+    ChainedAppend {
+        appended,
+        expected,
+        outcome: Ok(()),
+    }
+}
+
+/// Splits the events of one stream into appends of at most `max_append_batch`
+/// events each, keeping their order.
+fn append_batches<T>(events: Vec<T>, max_append_batch: Option<usize>) -> Vec<Vec<T>> {
+    let Some(max) = max_append_batch.map(|max| max.max(1)) else {
+        return vec![events];
+    };
+    let mut batches = Vec::with_capacity(events.len().div_ceil(max));
+    let mut events = events.into_iter().peekable();
+    while events.peek().is_some() {
+        batches.push(events.by_ref().take(max).collect());
+    }
+    batches
+}
+
+/// Metadata of an outbox event: the regular metadata plus the stream the fan-out
+/// projection must write the event to.
+fn outbox_metadata(mut metadata: Map<String, Value>, target_stream: &str) -> Map<String, Value> {
+    metadata.insert(
+        "target_stream".to_string(),
+        Value::String(target_stream.to_string()),
+    );
+    metadata
+}
+
+fn oversize_reason(call: &MutationCall, max_event_bytes: Option<usize>) -> Option<String> {
+    let max_event_bytes = max_event_bytes?;
+    let size = serde_json::to_vec(call).map(|bytes| bytes.len()).ok()?;
+    (size > max_event_bytes).then(|| {
+        format!("serialized event is {size} bytes, above max_event_bytes {max_event_bytes}")
+    })
+}
+
+fn event_metadata(call: &MutationCall, config: &KurrentConfig) -> Map<String, Value> {
+    let mut metadata = call.metadata.clone();
+    metadata.remove(EVENT_ID_METADATA_KEY);
+    metadata.insert("sequence".to_string(), Value::from(call.sequence));
+    if let Some(version) = naming::schema_version(call, config) {
+        metadata.insert("schema_version".to_string(), Value::from(version));
+    }
+
+    if let Some(field) = config.partition_key_field.as_deref() {
+        match resolve_call_value(call, field) {
+            Some(key) => {
+                metadata.insert("partition_key".to_string(), Value::String(key));
+            }
+            None => {
+                tracing::debug!(partition_key_field = %field, mutation = %call.field_name, "Partition key field not present on mutation");
+            }
+        }
+    }
+
+    metadata
+}
+
+/// Event metadata entry holding a call's application-level idempotency key.
+pub const IDEMPOTENCY_KEY_METADATA_KEY: &str = "idempotency_key";
+
+/// KurrentDB's standard metadata entry for the id of the flow an event belongs to.
+pub const CORRELATION_ID_METADATA_KEY: &str = "$correlationId";
+
+/// KurrentDB's standard metadata entry for the id of the event or command that
+/// caused an event.
+pub const CAUSATION_ID_METADATA_KEY: &str = "$causationId";
+
+/// Metadata entry with the event id assigned to a call before it is persisted, so
+/// that other events can refer to it. Used as the KurrentDB event id instead of
+/// being written to the metadata.
+pub const EVENT_ID_METADATA_KEY: &str = "event_id";
+
+/// The event id assigned to `call` under [`EVENT_ID_METADATA_KEY`], or a new one.
+fn assigned_event_id(call: &MutationCall) -> Uuid {
+    call.metadata
+        .get(EVENT_ID_METADATA_KEY)
+        .and_then(Value::as_str)
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .unwrap_or_else(Uuid::new_v4)
+}
+
+/// Whether the JSON event metadata `custom_metadata` records `key` as its
+/// idempotency key.
+fn carries_idempotency_key(custom_metadata: &[u8], key: &Value) -> bool {
+    serde_json::from_slice::<Map<String, Value>>(custom_metadata)
+        .is_ok_and(|metadata| metadata.get(IDEMPOTENCY_KEY_METADATA_KEY) == Some(key))
+}
+
+/// Resolves `loan_id` or a dotted argument path (e.g. `input.NationalID`) to a string.
+pub(crate) fn resolve_call_value(call: &MutationCall, field: &str) -> Option<String> {
+    if field == "loan_id" {
+        return call.loan_id.clone();
+    }
+
+    match call.argument_value(field)? {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// The key `call` is recorded under in the [`IdempotencyCache`]: its mutation
+/// field and the value at `key_field`.
+fn idempotency_cache_key(call: &MutationCall, key_field: &str) -> Option<String> {
+    resolve_call_value(call, key_field).map(|key| format!("{}:{}", call.field_name, key))
+}
+
+fn drop_duplicate_calls(
+    calls: Vec<MutationCall>,
+    cache: &IdempotencyCache,
+    key_field: &str,
+) -> Vec<MutationCall> {
+    calls
+        .into_iter()
+        .filter(|call| {
+            let Some(key) = idempotency_cache_key(call, key_field) else {
+                return true;
+            };
+            let duplicate = cache.is_duplicate(&key);
+            if duplicate {
+                tracing::info!(mutation = %call.field_name, idempotency_key = %key, "Skipping duplicate mutation persisted within the idempotency window");
+            }
+            !duplicate
+        })
+        .collect()
+}
+
+/// Drops calls the `skip_persist` missing key policy applies to.
+fn drop_calls_without_key(calls: Vec<MutationCall>, config: &KurrentConfig) -> Vec<MutationCall> {
+    calls
+        .into_iter()
+        .filter(|call| {
+            let skip =
+                naming::missing_key_policy(call, config) == Some(MissingKeyPolicy::SkipPersist);
+            if skip {
+                tracing::info!(mutation = %call.field_name, "Skipping mutation without an aggregate id");
+            }
+            !skip
+        })
+        .collect()
+}
+
+impl KurrentService {
+    /// Records the idempotency keys of `calls` once they are appended, so retries
+    /// of calls that failed to persist are not skipped.
+    fn record_persisted<'a>(&self, calls: impl IntoIterator<Item = &'a MutationCall>) {
+        if let (Some(cache), Some(key_field)) =
+            (&self.idempotency, &self.config.idempotency_key_field)
+        {
+            calls
+                .into_iter()
+                .filter_map(|call| idempotency_cache_key(call, key_field))
+                .for_each(|key| cache.record(&key));
+        }
+    }
+
+    /// Drops the calls that must not be appended and logs the rest to the
+    /// write-ahead log. `None` when nothing is left to append.
+    fn admit(&self, calls: Vec<MutationCall>) -> Option<(Vec<MutationCall>, Option<u64>)> {
+        let calls = drop_calls_without_key(calls, &self.config);
+        let mut calls = match (&self.idempotency, &self.config.idempotency_key_field) {
+            (Some(cache), Some(key_field)) => drop_duplicate_calls(calls, cache, key_field),
+            _ => calls,
+        };
+        if calls.is_empty() {
+            return None;
+        }
+        // Stamped before the write-ahead log, so a replayed batch keeps its time.
+        if let Some(source) = &self.config.event_timestamp {
+            calls.iter_mut().for_each(|call| source.stamp(call));
+        }
+
+        let wal_id = match &self.wal {
+            Some(wal) => match wal.append(&calls) {
+                Ok(id) => Some(id),
+                Err(error) => {
+                    tracing::error!(error = %error, path = %wal.path().display(), "Failed to write batch to the write-ahead log, persisting without it");
+                    None
+                }
+            },
+            None => None,
+        };
+        Some((calls, wal_id))
+    }
+
+    /// Whether the circuit breaker refuses new batches, in which case `calls` were
+    /// handled per its `on_open` action.
+    fn rejected_by_breaker(&self, calls: &mut Vec<MutationCall>, wal_id: Option<u64>) -> bool {
+        let Some(breaker) = &self.breaker else {
+            return false;
+        };
+        if breaker.allow() {
+            return false;
+        }
+        let action = self
+            .config
+            .circuit_breaker
+            .as_ref()
+            .map(|config| config.on_open)
+            .unwrap_or_default();
+        self.reject_while_open(std::mem::take(calls), wal_id, action);
+        true
+    }
 }
 
 impl MutationSink for KurrentService {
     fn persist_mutations(&self, calls: Vec<MutationCall>) {
-        let client = self.client.clone();
-        let stream_prefix = self.stream_prefix.clone();
+        let Some((mut calls, wal_id)) = self.admit(calls) else {
+            return;
+        };
+        if self.rejected_by_breaker(&mut calls, wal_id) {
+            return;
+        }
 
-        task::spawn(async move {
-            if let Err(error) = KurrentService::persist_batch(client, stream_prefix, calls).await {
-                tracing::error!(error = %error, "Failed to persist mutations to KurrentDB");
+        match &self.coalescer {
+            Some(coalescer) => coalescer.push(PendingBatch { calls, wal_id }),
+            None => self.enqueue(calls, wal_id),
+        }
+    }
+
+    /// Appends right away, bypassing the coalescer, so the caller is not held for
+    /// a whole `flush_interval_ms`.
+    fn persist_and_wait(
+        &self,
+        calls: Vec<MutationCall>,
+    ) -> BoxFuture<'static, Result<(), KurrentError>> {
+        let Some((mut calls, wal_id)) = self.admit(calls) else {
+            return Box::pin(future::ready(Ok(())));
+        };
+        if self.rejected_by_breaker(&mut calls, wal_id) {
+            return Box::pin(future::ready(Err(KurrentError::CircuitOpen)));
+        }
+
+        let service = self.clone();
+        let span = tracing::info_span!("persist_mutations", count = calls.len());
+        Box::pin(
+            async move {
+                let result = service.persist_batch(calls).await;
+                service.record_outcome(&result);
+                if result.is_ok() {
+                    service.ack_wal(wal_id.as_slice());
+                }
+                result
             }
+            .instrument(span),
+        )
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.is_connected()
+    }
+
+    fn has_capacity(&self) -> bool {
+        self.queue.as_ref().is_none_or(|queue| {
+            queue.policy() != QueueFullPolicy::RejectRequest || !queue.is_full()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::captured_logs::CapturedLogs;
+    use crate::plugins::kurrent_mapper::redaction::RedactionConfig;
+    use serde_json::json;
+
+    fn config(config: Value) -> KurrentConfig {
+        serde_json::from_value(config).unwrap()
+    }
+
+    fn credit_checked_call() -> MutationCall {
+        MutationCall {
+            operation_name: Some("CheckCredit".to_string()),
+            field_name: "recordCreditChecked".to_string(),
+            loan_id: Some("loan-123".to_string()),
+            arguments: vec![MutationArg {
+                name: "input".to_string(),
+                value: json!({ "loanId": "loan-123", "NationalID": "987654321", "Score": 720 }),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn logged_payloads_are_redacted() {
+        let redaction: RedactionConfig = serde_json::from_value(
+            json!({ "rules": [{ "pointer": "/arguments/input/NationalID" }] }),
+        )
+        .unwrap();
+        let mut call = credit_checked_call();
+        redaction.redact(&mut call);
+
+        let logs = CapturedLogs::default();
+        tracing::subscriber::with_default(logs.clone(), || {
+            log_payload(
+                "GraphQL.CheckCredit",
+                "graphql-mutation-recordCreditChecked",
+                &call,
+                &KurrentConfig::default(),
+            )
         });
+
+        let logs = logs.0.lock().unwrap();
+        let payload = logs
+            .iter()
+            .find(|field| field.starts_with("payload="))
+            .expect("payload is logged");
+        assert!(payload.contains(r#""NationalID":"***""#), "{payload}");
+        assert!(!payload.contains("987654321"), "{payload}");
+        assert!(payload.contains(r#""Score":720"#), "{payload}");
+    }
+
+    #[test]
+    fn partition_key_resolves_from_argument_path() {
+        let config = config(json!({ "partition_key_field": "input.NationalID" }));
+
+        let metadata = event_metadata(&credit_checked_call(), &config);
+
+        assert_eq!(Some(&json!("987654321")), metadata.get("partition_key"));
+    }
+
+    #[test]
+    fn partition_key_resolves_from_aggregate_id() {
+        let config = config(json!({ "partition_key_field": "loan_id" }));
+
+        let metadata = event_metadata(&credit_checked_call(), &config);
+
+        assert_eq!(Some(&json!("loan-123")), metadata.get("partition_key"));
+    }
+
+    #[test]
+    fn partition_key_is_omitted_when_field_is_missing_or_unconfigured() {
+        let missing = config(json!({ "partition_key_field": "input.Region" }));
+
+        assert!(!event_metadata(&credit_checked_call(), &missing).contains_key("partition_key"));
+        assert!(
+            !event_metadata(&credit_checked_call(), &config(json!({})))
+                .contains_key("partition_key")
+        );
+    }
+
+    #[test]
+    fn sequence_is_always_written_to_event_metadata() {
+        let mut call = credit_checked_call();
+        call.sequence = 2;
+
+        let metadata = event_metadata(&call, &config(json!({})));
+
+        assert_eq!(Some(&json!(2)), metadata.get("sequence"));
+    }
+
+    #[test]
+    fn configured_event_timestamp_is_written_to_event_metadata() {
+        let config =
+            config(json!({ "event_timestamp": { "argument": "input.CreditCheckedTimestamp" } }));
+        let mut call = credit_checked_call();
+        call.arguments[0].value["CreditCheckedTimestamp"] = json!("2025-01-02T03:04:05Z");
+
+        config.event_timestamp.as_ref().unwrap().stamp(&mut call);
+        let metadata = event_metadata(&call, &config);
+
+        assert_eq!(
+            Some(&json!("2025-01-02T03:04:05Z")),
+            metadata.get("timestamp")
+        );
+    }
+
+    #[test]
+    fn call_metadata_is_carried_into_event_metadata() {
+        let mut call = credit_checked_call();
+        call.metadata.insert(
+            "schema_coordinate".to_string(),
+            json!("Mutation.recordCreditChecked"),
+        );
+
+        let metadata = event_metadata(&call, &config(json!({})));
+
+        assert_eq!(
+            Some(&json!("Mutation.recordCreditChecked")),
+            metadata.get("schema_coordinate")
+        );
+    }
+
+    #[test]
+    fn large_stream_groups_are_split_in_order() {
+        let config = config(json!({ "max_append_batch": 2 }));
+        let calls: Vec<MutationCall> = (0..5)
+            .map(|sequence| MutationCall {
+                sequence,
+                ..credit_checked_call()
+            })
+            .collect();
+        let stream = naming::stream_name(&calls[0], &config);
+        assert!(
+            calls
+                .iter()
+                .all(|call| naming::stream_name(call, &config) == stream)
+        );
+
+        let batches = append_batches(calls, config.max_append_batch);
+
+        let sequences: Vec<Vec<usize>> = batches
+            .iter()
+            .map(|batch| batch.iter().map(|call| call.sequence).collect())
+            .collect();
+        assert_eq!(vec![vec![0, 1], vec![2, 3], vec![4]], sequences);
+        assert_eq!(1, append_batches(vec![1, 2, 3], None).len());
+    }
+
+    /// Appends through [`append_chained`] against a stream at revision 9, recording
+    /// the revision each append expected and failing the append numbered `fail_at`
+    /// with a timeout.
+    async fn chain_appends(
+        batches: Vec<Vec<u32>>,
+        fail_at: Option<usize>,
+    ) -> (ChainedAppend, Vec<ExpectedRevision>) {
+        let mut expectations = Vec::new();
+        let mut revision = 9;
+        let chained = append_chained(batches, ExpectedRevision::Any, |expected, events| {
+            expectations.push(expected);
+            let failed = fail_at == Some(expectations.len() - 1);
+            revision += events.len() as u64;
+            let result = if failed {
+                Err(Deadline::Elapsed(Duration::from_millis(5)))
+            } else {
+                Ok(revision)
+            };
+            future::ready(result)
+        })
+        .await;
+        (chained, expectations)
+    }
+
+    #[tokio::test]
+    async fn split_appends_chain_their_expected_revisions() {
+        let batches = append_batches((0..5).collect(), Some(2));
+
+        let (chained, expectations) = chain_appends(batches, None).await;
+
+        assert!(matches!(
+            expectations[..],
+            [
+                ExpectedRevision::Any,
+                ExpectedRevision::Exact(11),
+                ExpectedRevision::Exact(13)
+            ]
+        ));
+        assert_eq!(5, chained.appended);
+        assert!(matches!(chained.expected, ExpectedRevision::Exact(14)));
+        assert!(chained.outcome.is_ok());
+    }
+
+    #[tokio::test]
+    async fn a_timed_out_split_append_stops_the_chain() {
+        let batches = append_batches((0..5).collect(), Some(2));
+
+        let (chained, expectations) = chain_appends(batches, Some(1)).await;
+
+        assert_eq!(2, expectations.len());
+        assert_eq!(2, chained.appended);
+        assert!(matches!(chained.expected, ExpectedRevision::Exact(11)));
+        assert!(matches!(chained.outcome, Err(Deadline::Elapsed(_))));
+    }
+
+    #[test]
+    fn assigned_event_ids_are_used_instead_of_written_to_the_metadata() {
+        let event_id = Uuid::new_v4();
+        let mut call = credit_checked_call();
+        call.metadata.insert(
+            EVENT_ID_METADATA_KEY.to_string(),
+            json!(event_id.to_string()),
+        );
+
+        let metadata = event_metadata(&call, &config(json!({})));
+
+        assert_eq!(event_id, assigned_event_id(&call));
+        assert!(!metadata.contains_key(EVENT_ID_METADATA_KEY));
+        assert_ne!(event_id, assigned_event_id(&credit_checked_call()));
+    }
+
+    #[test]
+    fn duplicate_idempotency_key_is_dropped_once_persisted() {
+        let cache = IdempotencyCache::new(16, Duration::from_secs(60));
+
+        let first = drop_duplicate_calls(vec![credit_checked_call()], &cache, "input.loanId");
+        let retried = drop_duplicate_calls(vec![credit_checked_call()], &cache, "input.loanId");
+        cache.record(&idempotency_cache_key(&first[0], "input.loanId").unwrap());
+        let repeated = drop_duplicate_calls(vec![credit_checked_call()], &cache, "input.loanId");
+
+        assert_eq!(1, first.len());
+        assert_eq!(1, retried.len());
+        assert!(repeated.is_empty());
+    }
+
+    #[test]
+    fn appends_repeating_the_last_idempotency_key_are_skipped() {
+        let key = json!("client-req-1");
+        let mut call = credit_checked_call();
+        call.metadata
+            .insert(IDEMPOTENCY_KEY_METADATA_KEY.to_string(), key.clone());
+        let last_event_metadata =
+            serde_json::to_vec(&event_metadata(&call, &KurrentConfig::default())).unwrap();
+
+        assert!(carries_idempotency_key(&last_event_metadata, &key));
+        assert!(!carries_idempotency_key(
+            &last_event_metadata,
+            &json!("client-req-2")
+        ));
+        assert!(!carries_idempotency_key(br#"{"sequence":0}"#, &key));
+        assert!(!carries_idempotency_key(b"", &key));
+    }
+
+    #[test]
+    fn dead_letter_payload_carries_the_call_and_reason() {
+        let payload = dead_letter_payload(
+            &credit_checked_call(),
+            "graphql-mutation-recordCreditChecked",
+            "\"high\" is not of type \"integer\"",
+        );
+
+        assert_eq!(
+            json!("graphql-mutation-recordCreditChecked"),
+            payload["intended_stream"]
+        );
+        assert_eq!(
+            json!("\"high\" is not of type \"integer\""),
+            payload["reason"]
+        );
+        assert_eq!(json!("recordCreditChecked"), payload["call"]["field_name"]);
+        assert_eq!(json!(720), payload["call"]["arguments"]["input"]["Score"]);
+    }
+
+    #[test]
+    fn oversized_argument_values_exceed_max_event_bytes() {
+        let mut call = credit_checked_call();
+        call.arguments.push(MutationArg {
+            name: "attachments".to_string(),
+            value: json!(["x".repeat(64 * 1024)]),
+        });
+
+        let reason = oversize_reason(&call, Some(16 * 1024)).expect("oversized");
+
+        assert!(reason.contains("above max_event_bytes 16384"));
+        assert_eq!(
+            None,
+            oversize_reason(&credit_checked_call(), Some(16 * 1024))
+        );
+        assert_eq!(None, oversize_reason(&call, None));
+    }
+
+    #[test]
+    fn outbox_metadata_records_the_target_stream() {
+        let call = credit_checked_call();
+        let config = config(json!({ "transactional_outbox": true }));
+
+        let metadata = outbox_metadata(
+            event_metadata(&call, &config),
+            &naming::stream_name(&call, &config),
+        );
+
+        assert_eq!(
+            Some(&json!("graphql-mutation-recordCreditChecked")),
+            metadata.get("target_stream")
+        );
+        assert_eq!(Some(&json!(0)), metadata.get("sequence"));
+        assert_eq!("graphql-mutation-outbox", config.outbox_stream);
+    }
+
+    #[test]
+    fn arguments_serialize_as_a_map_or_a_list() {
+        let call = MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            arguments: vec![
+                MutationArg {
+                    name: "input".to_string(),
+                    value: json!({ "Score": 700 }),
+                },
+                MutationArg {
+                    name: "dryRun".to_string(),
+                    value: json!(false),
+                },
+            ],
+            ..Default::default()
+        };
+
+        let map = event_payload(&call, &config(json!({}))).unwrap();
+        let list = event_payload(&call, &config(json!({ "argument_format": "list" }))).unwrap();
+
+        assert_eq!(
+            json!({ "input": { "Score": 700 }, "dryRun": false }),
+            map["arguments"]
+        );
+        assert_eq!(
+            json!([
+                { "name": "input", "value": { "Score": 700 } },
+                { "name": "dryRun", "value": false }
+            ]),
+            list["arguments"]
+        );
+        for payload in [map, list] {
+            let read_back: MutationCall = serde_json::from_value(payload).unwrap();
+            assert_eq!(2, read_back.arguments.len());
+        }
+    }
+
+    #[test]
+    fn anonymous_mutations_have_no_null_operation_name() {
+        let call = MutationCall {
+            operation_name: None,
+            ..credit_checked_call()
+        };
+
+        let omitted = event_payload(&call, &config(json!({}))).unwrap();
+        let defaulted = event_payload(
+            &call,
+            &config(json!({ "default_operation_name": "Anonymous" })),
+        )
+        .unwrap();
+
+        assert!(!omitted.as_object().unwrap().contains_key("operation_name"));
+        assert!(!omitted.as_object().unwrap().contains_key("alias"));
+        assert!(
+            omitted
+                .as_object()
+                .unwrap()
+                .values()
+                .all(|value| !value.is_null())
+        );
+        assert_eq!(json!("Anonymous"), defaulted["operation_name"]);
+        assert_eq!(
+            "GraphQL.recordCreditChecked",
+            naming::event_type(
+                &call,
+                &config(json!({ "default_operation_name": "Anonymous" }))
+            )
+        );
+        let read_back: MutationCall = serde_json::from_value(omitted).unwrap();
+        assert_eq!(None, read_back.operation_name);
+    }
+
+    #[test]
+    fn selected_fields_serialize_with_their_response_keys() {
+        let call = MutationCall {
+            field_name: "createOrder".to_string(),
+            selected_fields: vec![
+                SelectedField::new("newOrder", "order"),
+                SelectedField::new("success", "success"),
+            ],
+            ..Default::default()
+        };
+
+        let payload = serde_json::to_value(&call).unwrap();
+
+        assert_eq!(
+            json!([
+                { "response_key": "newOrder", "field_name": "order" },
+                { "response_key": "success", "field_name": "success" }
+            ]),
+            payload["selected_fields"]
+        );
+        let read_back: MutationCall = serde_json::from_value(payload).unwrap();
+        assert_eq!(call.selected_fields, read_back.selected_fields);
+    }
+
+    #[test]
+    fn skip_persist_drops_only_calls_without_an_aggregate_id() {
+        let config = config(json!({
+            "stream_naming": "category",
+            "category": "loan",
+            "missing_key_policy": "skip_persist"
+        }));
+        let without_id = MutationCall {
+            loan_id: None,
+            ..credit_checked_call()
+        };
+
+        let kept = drop_calls_without_key(vec![credit_checked_call(), without_id], &config);
+
+        assert_eq!(1, kept.len());
+        assert_eq!(Some("loan-123".to_string()), kept[0].loan_id);
     }
 }
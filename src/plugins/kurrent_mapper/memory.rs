@@ -0,0 +1,76 @@
+use std::sync::{Arc, Mutex, OnceLock};
+
+use super::mapper::{MutationCall, MutationSink};
+
+/// Records calls in memory instead of persisting them, for local development and
+/// end-to-end tests without a KurrentDB. Every call is also logged as a JSON line
+/// under the `starstuff::memory_sink` target.
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    calls: Mutex<Vec<MutationCall>>,
+}
+
+impl MemorySink {
+    /// The instance plugins configured with `sink: memory` write to. It is shared
+    /// process-wide, so tests running the router in-process can read it back.
+    pub fn shared() -> Arc<MemorySink> {
+        static SHARED: OnceLock<Arc<MemorySink>> = OnceLock::new();
+        SHARED.get_or_init(Default::default).clone()
+    }
+
+    pub fn recorded(&self) -> Vec<MutationCall> {
+        self.lock().clone()
+    }
+
+    /// Returns the recorded calls and forgets them, so each test sees its own.
+    pub fn take(&self) -> Vec<MutationCall> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<MutationCall>> {
+        self.calls
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl MutationSink for MemorySink {
+    fn persist_mutations(&self, calls: Vec<MutationCall>) {
+        for call in &calls {
+            match serde_json::to_string(call) {
+                Ok(line) => {
+                    tracing::info!(target: "starstuff::memory_sink", call = %line, "Recorded mutation")
+                }
+                Err(error) => {
+                    tracing::warn!(error = %error, "Failed to serialize recorded mutation")
+                }
+            }
+        }
+        self.lock().extend(calls);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_calls_can_be_read_and_taken() {
+        let sink = MemorySink::default();
+        let call = MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            ..Default::default()
+        };
+
+        sink.persist_mutations(vec![call.clone(), call]);
+
+        assert_eq!(2, sink.recorded().len());
+        assert_eq!(2, sink.take().len());
+        assert!(sink.recorded().is_empty());
+    }
+
+    #[test]
+    fn the_shared_sink_is_one_instance() {
+        assert!(Arc::ptr_eq(&MemorySink::shared(), &MemorySink::shared()));
+    }
+}
@@ -1,3 +1,63 @@
+pub mod builder;
+pub mod circuit_breaker;
+pub mod coalescing;
+pub mod composite;
+pub mod connection;
+pub mod credentials;
+pub mod deletion;
+pub mod disabled;
+pub mod encoding;
+pub mod env;
+pub mod error;
+pub mod idempotency;
+pub mod lifecycle;
 pub mod mapper;
+pub mod memory;
+pub mod naming;
+pub mod persist_queue;
+pub mod pool;
+pub mod redaction;
+pub mod replay;
+pub mod revision_chain;
+pub mod routing;
+pub mod sampling;
+pub mod scan_header;
+pub mod sharding;
+pub mod stream_limiter;
+pub mod stream_metadata;
+pub mod timestamp;
+pub mod tls;
+pub mod validation;
+pub mod wal;
 
+pub use builder::*;
+pub use circuit_breaker::*;
+pub use coalescing::*;
+pub use composite::*;
+pub use connection::*;
+pub use credentials::*;
+pub use deletion::*;
+pub use disabled::*;
+pub use encoding::*;
+pub use env::*;
+pub use error::*;
+pub use idempotency::*;
+pub use lifecycle::*;
 pub use mapper::*;
+pub use memory::*;
+pub use naming::*;
+pub use persist_queue::*;
+pub use pool::*;
+pub use redaction::*;
+pub use replay::*;
+pub use revision_chain::*;
+pub use routing::*;
+pub use sampling::*;
+pub use scan_header::*;
+pub use sharding::*;
+pub use stream_limiter::*;
+pub use stream_metadata::*;
+pub use timestamp::*;
+pub use tls::*;
+pub use validation::*;
+pub use wal::*;
@@ -0,0 +1,968 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::{
+    deletion,
+    error::KurrentError,
+    mapper::{KurrentConfig, MutationCall, OperationType, resolve_call_value},
+    routing, sharding,
+};
+
+/// Case applied to the final event type, segment by segment (`.` is preserved).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EventTypeCase {
+    #[default]
+    AsIs,
+    Lower,
+    Snake,
+    Kebab,
+}
+
+impl EventTypeCase {
+    pub fn apply(self, event_type: &str) -> String {
+        match self {
+            EventTypeCase::AsIs => event_type.to_string(),
+            EventTypeCase::Lower => event_type.to_lowercase(),
+            EventTypeCase::Snake => map_segments(event_type, |s| join_words(s, "_")),
+            EventTypeCase::Kebab => map_segments(event_type, |s| join_words(s, "-")),
+        }
+    }
+}
+
+/// Case applied to the field and operation names that go into event types and
+/// stream names, e.g. `recordCreditChecked` to `record-credit-checked`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CaseTransform {
+    #[default]
+    None,
+    Snake,
+    Kebab,
+    Pascal,
+}
+
+impl CaseTransform {
+    pub fn apply(self, name: &str) -> String {
+        match self {
+            CaseTransform::None => name.to_string(),
+            CaseTransform::Snake => join_words(name, "_"),
+            CaseTransform::Kebab => join_words(name, "-"),
+            CaseTransform::Pascal => split_words(name)
+                .iter()
+                .map(|word| {
+                    let mut chars = word.chars();
+                    chars.next().map_or_else(String::new, |first| {
+                        first.to_uppercase().chain(chars).collect()
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Version of a mutation's event shape, for all fields or per field name (`*` for
+/// the rest). Versioned events get a `.v<n>` suffix on their event type.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum SchemaVersion {
+    Global(u32),
+    PerField(HashMap<String, u32>),
+}
+
+impl SchemaVersion {
+    pub fn for_field(&self, field_name: &str) -> Option<u32> {
+        match self {
+            SchemaVersion::Global(version) => Some(*version),
+            SchemaVersion::PerField(versions) => versions
+                .get(field_name)
+                .or_else(|| versions.get("*"))
+                .copied(),
+        }
+    }
+}
+
+/// How the target stream of a call is derived.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamNaming {
+    /// `{stream_prefix}{field_name}`, one stream per mutation type.
+    #[default]
+    Prefix,
+    /// `{category}-{loan_id}`, one stream per aggregate, grouped by KurrentDB's
+    /// `$by_category` projection into `$ce-{category}`.
+    Category,
+}
+
+/// What happens to a call bound for a per-aggregate (`category`) stream that has no
+/// aggregate id.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingKeyPolicy {
+    /// Write it to the prefix stream of its mutation field.
+    #[default]
+    FallbackToFieldStream,
+    /// Drop it without persisting.
+    SkipPersist,
+    /// Write it to the dead-letter stream.
+    DeadLetter,
+}
+
+/// Which name of the call keys its stream under `stream_naming: prefix`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamKey {
+    /// The mutation field, e.g. `recordAutomatedSummary`.
+    #[default]
+    FieldName,
+    /// The operation, e.g. `RecordSummary`; the field name for anonymous operations.
+    OperationName,
+}
+
+/// Which name of an aliased call its stream and event type are derived from. The
+/// response is looked up by alias either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NameSource {
+    /// The schema field name, e.g. `recordLoanManuallyApproved`, whatever the
+    /// client aliased it to.
+    #[default]
+    FieldName,
+    /// The alias, e.g. `approval`; the field name for calls without one.
+    Alias,
+}
+
+/// The field name or alias naming `call`, per `name_source`.
+fn naming_field<'a>(call: &'a MutationCall, config: &KurrentConfig) -> &'a str {
+    match (config.name_source, call.alias.as_deref()) {
+        (NameSource::Alias, Some(alias)) => alias,
+        _ => &call.field_name,
+    }
+}
+
+/// What to do with a derived stream name KurrentDB would reject or misinterpret:
+/// empty, starting with `$` (reserved for system streams), or containing
+/// whitespace or control characters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamNameSanitization {
+    /// Dead-letter the call.
+    #[default]
+    Reject,
+    /// Replace offending characters with `-` and drop a leading `$`; dead-letter
+    /// the call if nothing is left.
+    Slugify,
+}
+
+/// Checks `stream` against the rules of [`StreamNameSanitization`]. `Err` holds the
+/// reason the call must be dead-lettered.
+pub fn sanitize_stream_name(
+    stream: &str,
+    sanitization: StreamNameSanitization,
+) -> Result<String, String> {
+    let invalid = |c: char| c.is_whitespace() || c.is_control();
+    if !stream.is_empty() && !stream.starts_with('$') && !stream.contains(invalid) {
+        return Ok(stream.to_string());
+    }
+
+    match sanitization {
+        StreamNameSanitization::Reject => Err(format!("invalid stream name {stream:?}")),
+        StreamNameSanitization::Slugify => {
+            let slug = stream
+                .trim_start_matches('$')
+                .split(invalid)
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join("-");
+            if slug.is_empty() {
+                Err(format!(
+                    "stream name {stream:?} is empty after sanitization"
+                ))
+            } else {
+                tracing::debug!(stream = %stream, sanitized = %slug, "Sanitized stream name");
+                Ok(slug)
+            }
+        }
+    }
+}
+
+/// Separator used by KurrentDB's `$by_category` projection. The projection splits
+/// on the first occurrence, so the category itself must not contain it.
+pub const CATEGORY_SEPARATOR: char = '-';
+
+/// Separator KurrentDB's `$by_event_type` projection puts between `$et` and the
+/// event type; event types containing it are not projected reliably.
+const EVENT_TYPE_SEPARATOR: char = '-';
+
+/// Rejects naming configuration that would silently break category projections.
+pub fn validate(config: &KurrentConfig) -> Result<(), KurrentError> {
+    if config.projection_safe {
+        if config.case_transform == CaseTransform::Kebab {
+            return Err(KurrentError::config(
+                "case_transform `kebab` cannot be combined with projection_safe, which replaces `-` in names; use `snake`",
+            ));
+        }
+        if config.event_type_case == EventTypeCase::Kebab {
+            return Err(KurrentError::config(
+                "event_type_case `kebab` cannot be combined with projection_safe, which forbids `-` in event types; use `snake`",
+            ));
+        }
+    }
+
+    if config.stream_naming != StreamNaming::Category {
+        return Ok(());
+    }
+
+    match config.category.as_deref() {
+        None | Some("") => Err(KurrentError::config(
+            "stream_naming `category` requires a non-empty `category`",
+        )),
+        Some(category) if category.contains(CATEGORY_SEPARATOR) => {
+            Err(KurrentError::config(format!(
+                "category `{category}` must not contain `{CATEGORY_SEPARATOR}`; $by_category would split it"
+            )))
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+pub fn stream_name(call: &MutationCall, config: &KurrentConfig) -> String {
+    let stream = match catch_all_stream(call, config) {
+        Some(stream) => stream.to_string(),
+        None => {
+            let stream = untenanted_stream_name(call, config);
+            match config.shards.get(&call.field_name) {
+                Some(shard) => sharding::sharded_stream(call, shard, stream),
+                None => stream,
+            }
+        }
+    };
+    let stream = tenant_scoped(call, stream);
+    if config.projection_safe {
+        projection_safe_stream(&stream)
+    } else {
+        stream
+    }
+}
+
+/// The stream of `call`'s own aggregate, named the way [`stream_name`] names it:
+/// its `stream_overrides` template when that contains `{loan_id}`, otherwise
+/// `{category}-{loan_id}` under `category` naming. `None` without an aggregate id,
+/// or when the call's stream is not per aggregate: a `@persist` stream, any other
+/// override, `prefix` naming, or a sharded field.
+pub fn aggregate_stream_name(call: &MutationCall, config: &KurrentConfig) -> Option<String> {
+    let id = call.loan_id.as_deref()?;
+    if call.stream_override.is_some() || config.shards.contains_key(&call.field_name) {
+        return None;
+    }
+    let stream = match config.stream_overrides.get(&call.field_name) {
+        Some(template) if template.contains("{loan_id}") => {
+            interpolate(template, call, config.case_transform)?
+        }
+        Some(_) => return None,
+        None => match (config.stream_naming, config.category.as_deref()) {
+            (StreamNaming::Category, Some(category)) => {
+                format!("{category}{CATEGORY_SEPARATOR}{id}")
+            }
+            _ => return None,
+        },
+    };
+    let stream = tenant_scoped(call, stream);
+    Some(if config.projection_safe {
+        projection_safe_stream(&stream)
+    } else {
+        stream
+    })
+}
+
+/// Keeps the first `-`, which `$by_category` splits on, as the only one: later
+/// ones become `_`, so `graphql-mutation-record` is `graphql-mutation_record`.
+pub fn projection_safe_stream(stream: &str) -> String {
+    match stream.split_once(CATEGORY_SEPARATOR) {
+        Some((category, rest)) => format!(
+            "{category}{CATEGORY_SEPARATOR}{}",
+            rest.replace(CATEGORY_SEPARATOR, "_")
+        ),
+        None => stream.to_string(),
+    }
+}
+
+/// Replaces every `-` with `_`, so the event type has a usable `$et-{eventType}`
+/// stream.
+pub fn projection_safe_event_type(event_type: &str) -> String {
+    event_type.replace(EVENT_TYPE_SEPARATOR, "_")
+}
+
+/// Event type prefix of mutations written to `catch_all_stream`.
+pub const UNMATCHED_EVENT_TYPE_PREFIX: &str = "GraphQL.Unmatched";
+
+/// `catch_all_stream`, if configured and no specific rule covers `call`: no
+/// stream override (configured or `@persist`), routing rule or shard applies, and
+/// no aggregate id was extracted.
+fn catch_all_stream<'a>(call: &MutationCall, config: &'a KurrentConfig) -> Option<&'a str> {
+    let stream = config.catch_all_stream.as_deref()?;
+    let unmatched = call.operation_type == OperationType::Mutation
+        && call.subgraph.is_none()
+        && call.loan_id.is_none()
+        && call.stream_override.is_none()
+        && !config.stream_overrides.contains_key(&call.field_name)
+        && !config.shards.contains_key(&call.field_name)
+        && routing::matching_rule(call, &config.routing_rules).is_none();
+    unmatched.then_some(stream)
+}
+
+/// The configured [`MissingKeyPolicy`], if `call` would go to a per-aggregate stream
+/// but has no aggregate id. Calls with an explicit stream are never affected.
+pub fn missing_key_policy(call: &MutationCall, config: &KurrentConfig) -> Option<MissingKeyPolicy> {
+    let per_aggregate = config.stream_naming == StreamNaming::Category
+        && call.stream_override.is_none()
+        && !config.stream_overrides.contains_key(&call.field_name);
+    (per_aggregate && call.loan_id.is_none()).then_some(config.missing_key_policy)
+}
+
+/// Prefixes `stream` with the call's tenant (`{tenant}-{stream}`), if it has one.
+pub fn tenant_scoped(call: &MutationCall, stream: String) -> String {
+    match &call.tenant {
+        Some(tenant) => format!("{tenant}-{stream}"),
+        None => stream,
+    }
+}
+
+fn untenanted_stream_name(call: &MutationCall, config: &KurrentConfig) -> String {
+    if let Some(stream) = &call.stream_override {
+        return stream.clone();
+    }
+
+    if let Some(template) = config.stream_overrides.get(&call.field_name) {
+        match interpolate(template, call, config.case_transform) {
+            Some(stream) => return stream,
+            None => {
+                tracing::warn!(mutation = %call.field_name, template = %template, "Stream override placeholder could not be resolved, using the default stream");
+            }
+        }
+    }
+
+    match (
+        config.stream_naming,
+        config.category.as_deref(),
+        call.loan_id.as_deref(),
+    ) {
+        (StreamNaming::Category, Some(category), Some(id)) => {
+            format!("{category}{CATEGORY_SEPARATOR}{id}")
+        }
+        (StreamNaming::Category, _, None) => {
+            tracing::debug!(mutation = %call.field_name, "No aggregate id for category stream, using the prefix stream");
+            prefix_stream_name(call, config)
+        }
+        _ => prefix_stream_name(call, config),
+    }
+}
+
+/// Replaces `{field_name}`, `{operation_name}` (both in `case`), `{loan_id}` and
+/// dotted argument path placeholders (e.g. `{input.region}`). `None` if any
+/// placeholder is unresolved or empty.
+pub fn interpolate(template: &str, call: &MutationCall, case: CaseTransform) -> Option<String> {
+    let mut interpolated = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        interpolated.push_str(&rest[..start]);
+        let end = start + rest[start..].find('}')?;
+        let value = match &rest[start + 1..end] {
+            "field_name" => Some(case.apply(&call.field_name)),
+            "operation_name" => call.operation_name.as_deref().map(|name| case.apply(name)),
+            placeholder => resolve_call_value(call, placeholder),
+        }
+        .filter(|value| !value.is_empty())?;
+        interpolated.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+
+    interpolated.push_str(rest);
+    Some(interpolated)
+}
+
+fn prefix_stream_name(call: &MutationCall, config: &KurrentConfig) -> String {
+    let key = match (config.stream_key, call.operation_name.as_deref()) {
+        (StreamKey::OperationName, Some(operation_name)) => operation_name,
+        _ => naming_field(call, config),
+    };
+    let prefix = match call.operation_type {
+        OperationType::Mutation => &config.stream_prefix,
+        OperationType::Query => &config.query_stream_prefix,
+    };
+    format!("{prefix}{}", config.case_transform.apply(key))
+}
+
+pub fn event_type(call: &MutationCall, config: &KurrentConfig) -> String {
+    let case = config.case_transform;
+    let field = naming_field(call, config);
+    let name = call.operation_name.as_deref().unwrap_or(field);
+    let mut event_type = match &call.subgraph {
+        None if catch_all_stream(call, config).is_some() => {
+            tracing::debug!(mutation = %call.field_name, "No rule covers this mutation, persisting it to the catch-all stream");
+            format!("{UNMATCHED_EVENT_TYPE_PREFIX}.{}", case.apply(field))
+        }
+        // Subgraph fetches carry planner-generated operation names; the field is
+        // the meaningful part.
+        Some(subgraph) => format!("GraphQL.Subgraph.{subgraph}.{}", case.apply(field)),
+        None if call.operation_type == OperationType::Query => {
+            format!("GraphQL.Query.{}", case.apply(name))
+        }
+        None => match deletion::delete_mutation(call, &config.delete_mutations) {
+            Some(delete) => delete.event_type(),
+            None => format!("GraphQL.{}", case.apply(name)),
+        },
+    };
+    if let Some(version) = schema_version(call, config) {
+        event_type = format!("{event_type}.v{version}");
+    }
+    let event_type = config.event_type_case.apply(&event_type);
+    if config.projection_safe {
+        projection_safe_event_type(&event_type)
+    } else {
+        event_type
+    }
+}
+
+pub fn schema_version(call: &MutationCall, config: &KurrentConfig) -> Option<u32> {
+    config
+        .schema_version
+        .as_ref()
+        .and_then(|version| version.for_field(&call.field_name))
+}
+
+fn map_segments(value: &str, f: impl Fn(&str) -> String) -> String {
+    value.split('.').map(f).collect::<Vec<_>>().join(".")
+}
+
+fn join_words(segment: &str, separator: &str) -> String {
+    split_words(segment)
+        .iter()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Splits `camelCase`, `PascalCase`, acronyms (`GraphQLRequest` -> `Graph`, `QL`,
+/// `Request`) and `_`/`-`/space separated words.
+pub(crate) fn split_words(segment: &str) -> Vec<String> {
+    let chars: Vec<char> = segment.chars().collect();
+    let mut words = Vec::new();
+    let mut current = String::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        if matches!(c, '_' | '-' | ' ') {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+
+        if c.is_uppercase() && !current.is_empty() {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if prev.is_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_uppercase() && next_is_lower)
+            {
+                words.push(std::mem::take(&mut current));
+            }
+        }
+        current.push(c);
+    }
+
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn splits_camel_pascal_and_acronym_words() {
+        assert_eq!(
+            vec!["record", "Credit", "Checked"],
+            split_words("recordCreditChecked")
+        );
+        assert_eq!(
+            vec!["Graph", "QL", "Request"],
+            split_words("GraphQLRequest")
+        );
+        assert_eq!(vec!["loan", "id"], split_words("loan_id"));
+    }
+
+    #[test]
+    fn applies_event_type_case_per_segment() {
+        let event_type = "GraphQL.RecordSummary";
+
+        assert_eq!(event_type, EventTypeCase::AsIs.apply(event_type));
+        assert_eq!(
+            "graphql.recordsummary",
+            EventTypeCase::Lower.apply(event_type)
+        );
+        assert_eq!(
+            "graph_ql.record_summary",
+            EventTypeCase::Snake.apply(event_type)
+        );
+        assert_eq!(
+            "graph-ql.record-summary",
+            EventTypeCase::Kebab.apply(event_type)
+        );
+    }
+
+    #[test]
+    fn case_transforms_rename_fields_and_operations() {
+        let field = "recordCreditChecked";
+
+        assert_eq!(field, CaseTransform::None.apply(field));
+        assert_eq!("record_credit_checked", CaseTransform::Snake.apply(field));
+        assert_eq!("record-credit-checked", CaseTransform::Kebab.apply(field));
+        assert_eq!("RecordCreditChecked", CaseTransform::Pascal.apply(field));
+        assert_eq!(
+            "GraphQLRequest",
+            CaseTransform::Pascal.apply("graphQLRequest")
+        );
+    }
+
+    #[test]
+    fn case_transform_applies_to_names_in_event_types_and_streams() {
+        let config: KurrentConfig =
+            serde_json::from_value(json!({ "case_transform": "kebab" })).unwrap();
+        let call = MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            ..Default::default()
+        };
+        let operation = MutationCall {
+            operation_name: Some("CheckCredit".to_string()),
+            ..call.clone()
+        };
+
+        assert_eq!("GraphQL.record-credit-checked", event_type(&call, &config));
+        assert_eq!("GraphQL.check-credit", event_type(&operation, &config));
+        assert_eq!(
+            "graphql-mutation-record-credit-checked",
+            stream_name(&call, &config)
+        );
+    }
+
+    fn category_config() -> KurrentConfig {
+        serde_json::from_value(json!({ "stream_naming": "category", "category": "loan" })).unwrap()
+    }
+
+    #[test]
+    fn category_naming_uses_the_aggregate_id() {
+        let call = MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            loan_id: Some("123".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!("loan-123", stream_name(&call, &category_config()));
+    }
+
+    #[test]
+    fn category_naming_falls_back_to_prefix_without_an_aggregate_id() {
+        let call = MutationCall {
+            field_name: "recordLoanRequested".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "graphql-mutation-recordLoanRequested",
+            stream_name(&call, &category_config())
+        );
+    }
+
+    #[test]
+    fn category_must_not_contain_the_separator() {
+        let config: KurrentConfig = serde_json::from_value(
+            json!({ "stream_naming": "category", "category": "loan-events" }),
+        )
+        .unwrap();
+
+        assert!(validate(&config).is_err());
+        assert!(validate(&category_config()).is_ok());
+    }
+
+    fn projection_safe_config(extra: serde_json::Value) -> KurrentConfig {
+        let mut config = json!({ "projection_safe": true });
+        config
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(config).unwrap()
+    }
+
+    /// What KurrentDB's `$by_category` and `$by_event_type` projections need: one
+    /// category separator per stream, none in the event type.
+    fn assert_projection_compatible(stream: &str, event_type: &str) {
+        assert_eq!(1, stream.matches(CATEGORY_SEPARATOR).count(), "{stream}");
+        assert!(!event_type.contains(EVENT_TYPE_SEPARATOR), "{event_type}");
+    }
+
+    #[test]
+    fn projection_safe_names_are_projection_compatible() {
+        let call = MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            loan_id: Some("loan-123".to_string()),
+            tenant: Some("acme".to_string()),
+            ..Default::default()
+        };
+        let subgraph_call = MutationCall {
+            subgraph: Some("loan-origination".to_string()),
+            tenant: None,
+            ..call.clone()
+        };
+
+        for config in [
+            projection_safe_config(json!({})),
+            projection_safe_config(json!({ "stream_naming": "category", "category": "loan" })),
+            projection_safe_config(json!({ "case_transform": "snake" })),
+            projection_safe_config(json!({ "shards": { "recordCreditChecked": { "count": 4 } } })),
+        ] {
+            for call in [&call, &subgraph_call] {
+                assert_projection_compatible(
+                    &stream_name(call, &config),
+                    &event_type(call, &config),
+                );
+            }
+        }
+
+        let config =
+            projection_safe_config(json!({ "stream_naming": "category", "category": "loan" }));
+        assert_eq!("acme-loan_loan_123", stream_name(&call, &config));
+        assert_eq!("loan-loan_123", stream_name(&subgraph_call, &config));
+        assert_eq!(
+            "GraphQL.Subgraph.loan_origination.recordCreditChecked",
+            event_type(&subgraph_call, &config)
+        );
+        assert_eq!(
+            "graphql-mutation_recordCreditChecked",
+            stream_name(&subgraph_call, &projection_safe_config(json!({})))
+        );
+    }
+
+    #[test]
+    fn projection_safe_rejects_kebab_cases() {
+        assert!(validate(&projection_safe_config(json!({}))).is_ok());
+        assert!(
+            validate(&projection_safe_config(
+                json!({ "case_transform": "kebab" })
+            ))
+            .is_err()
+        );
+        assert!(
+            validate(&projection_safe_config(
+                json!({ "event_type_case": "kebab" })
+            ))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn mutations_no_rule_covers_go_to_the_catch_all_stream() {
+        let config: KurrentConfig = serde_json::from_value(json!({
+            "catch_all_stream": "graphql-mutation-unmatched",
+            "stream_overrides": { "recordLoanRequested": "loans" }
+        }))
+        .unwrap();
+        let unmatched = MutationCall {
+            operation_name: Some("Archive".to_string()),
+            field_name: "archiveApplication".to_string(),
+            ..Default::default()
+        };
+        let overridden = MutationCall {
+            field_name: "recordLoanRequested".to_string(),
+            ..Default::default()
+        };
+        let with_id = MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            loan_id: Some("loan-1".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "graphql-mutation-unmatched",
+            stream_name(&unmatched, &config)
+        );
+        assert_eq!(
+            "GraphQL.Unmatched.archiveApplication",
+            event_type(&unmatched, &config)
+        );
+        assert_eq!("loans", stream_name(&overridden, &config));
+        assert_eq!(
+            "GraphQL.recordLoanRequested",
+            event_type(&overridden, &config)
+        );
+        assert_eq!(
+            "graphql-mutation-recordCreditChecked",
+            stream_name(&with_id, &config)
+        );
+        assert_eq!(
+            "graphql-mutation-archiveApplication",
+            stream_name(&unmatched, &KurrentConfig::default())
+        );
+    }
+
+    #[test]
+    fn query_calls_get_their_own_event_type_and_stream_prefix() {
+        let config = KurrentConfig::default();
+        let call = MutationCall {
+            operation_name: Some("LoanStatus".to_string()),
+            field_name: "loan".to_string(),
+            operation_type: OperationType::Query,
+            ..Default::default()
+        };
+
+        assert_eq!("GraphQL.Query.LoanStatus", event_type(&call, &config));
+        assert_eq!("graphql-query-loan", stream_name(&call, &config));
+    }
+
+    #[test]
+    fn configured_case_is_applied_to_the_final_event_type() {
+        let config: KurrentConfig =
+            serde_json::from_value(json!({ "event_type_case": "kebab" })).unwrap();
+        let call = MutationCall {
+            operation_name: Some("RecordSummary".to_string()),
+            field_name: "recordAutomatedSummary".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!("graph-ql.record-summary", event_type(&call, &config));
+    }
+
+    #[test]
+    fn stream_overrides_take_precedence_and_interpolate_placeholders() {
+        let config: KurrentConfig = serde_json::from_value(json!({
+            "stream_overrides": {
+                "recordLoanRequested": "loans",
+                "recordCreditChecked": "credit-checks-{loan_id}",
+                "recordAutomatedSummary": "summaries-{input.region}"
+            }
+        }))
+        .unwrap();
+        let call = |field_name: &str| MutationCall {
+            field_name: field_name.to_string(),
+            loan_id: Some("123".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!("loans", stream_name(&call("recordLoanRequested"), &config));
+        assert_eq!(
+            "credit-checks-123",
+            stream_name(&call("recordCreditChecked"), &config)
+        );
+        assert_eq!(
+            "graphql-mutation-recordAutomatedSummary",
+            stream_name(&call("recordAutomatedSummary"), &config)
+        );
+        assert_eq!(
+            "graphql-mutation-recordLoanManualDecision",
+            stream_name(&call("recordLoanManualDecision"), &config)
+        );
+    }
+
+    #[test]
+    fn stream_key_can_use_the_operation_name() {
+        let config: KurrentConfig =
+            serde_json::from_value(json!({ "stream_key": "operation_name" })).unwrap();
+        let named = MutationCall {
+            operation_name: Some("RecordSummary".to_string()),
+            field_name: "recordAutomatedSummary".to_string(),
+            ..Default::default()
+        };
+        let anonymous = MutationCall {
+            field_name: "recordAutomatedSummary".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "graphql-mutation-RecordSummary",
+            stream_name(&named, &config)
+        );
+        assert_eq!(
+            "graphql-mutation-recordAutomatedSummary",
+            stream_name(&anonymous, &config)
+        );
+    }
+
+    #[test]
+    fn tenants_write_to_distinct_streams() {
+        let config = KurrentConfig::default();
+        let call = |tenant: &str| MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            tenant: Some(tenant.to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "acme-graphql-mutation-recordCreditChecked",
+            stream_name(&call("acme"), &config)
+        );
+        assert_eq!(
+            "globex-graphql-mutation-recordCreditChecked",
+            stream_name(&call("globex"), &config)
+        );
+        assert_eq!(
+            "acme-loans",
+            stream_name(
+                &MutationCall {
+                    stream_override: Some("loans".to_string()),
+                    ..call("acme")
+                },
+                &config
+            )
+        );
+    }
+
+    #[test]
+    fn empty_interpolated_values_fall_back_to_the_default_stream() {
+        let config: KurrentConfig = serde_json::from_value(json!({
+            "stream_overrides": { "recordAutomatedSummary": "summaries-{input.region}" }
+        }))
+        .unwrap();
+        let call = MutationCall {
+            field_name: "recordAutomatedSummary".to_string(),
+            arguments: vec![crate::plugins::kurrent_mapper::MutationArg {
+                name: "input".to_string(),
+                value: json!({ "region": "" }),
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "graphql-mutation-recordAutomatedSummary",
+            stream_name(&call, &config)
+        );
+        assert!(sanitize_stream_name("", StreamNameSanitization::Slugify).is_err());
+    }
+
+    #[test]
+    fn stream_names_with_spaces_are_rejected_or_slugified() {
+        let stream = "credit-checks-ACME Corp\t";
+
+        assert!(sanitize_stream_name(stream, StreamNameSanitization::Reject).is_err());
+        assert_eq!(
+            Ok("credit-checks-ACME-Corp".to_string()),
+            sanitize_stream_name(stream, StreamNameSanitization::Slugify)
+        );
+        assert_eq!(
+            Ok("settings".to_string()),
+            sanitize_stream_name("$settings", StreamNameSanitization::Slugify)
+        );
+        assert_eq!(
+            Ok("loans".to_string()),
+            sanitize_stream_name("loans", StreamNameSanitization::Reject)
+        );
+    }
+
+    #[test]
+    fn subgraph_calls_get_their_own_event_type() {
+        let call = MutationCall {
+            operation_name: Some("CheckCredit__credit__0".to_string()),
+            field_name: "recordCreditChecked".to_string(),
+            subgraph: Some("credit".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "GraphQL.Subgraph.credit.recordCreditChecked",
+            event_type(&call, &KurrentConfig::default())
+        );
+    }
+
+    #[test]
+    fn versioned_fields_get_a_version_suffix() {
+        let config: KurrentConfig = serde_json::from_value(serde_json::json!({
+            "schema_version": { "recordCreditChecked": 2 }
+        }))
+        .unwrap();
+        let versioned = MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            ..Default::default()
+        };
+        let unversioned = MutationCall {
+            field_name: "recordLoanRequested".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "GraphQL.recordCreditChecked.v2",
+            event_type(&versioned, &config)
+        );
+        assert_eq!(
+            "GraphQL.recordLoanRequested",
+            event_type(&unversioned, &config)
+        );
+        assert_eq!(
+            "GraphQL.recordLoanRequested",
+            event_type(&unversioned, &KurrentConfig::default())
+        );
+    }
+
+    fn category_config(policy: &str) -> KurrentConfig {
+        serde_json::from_value(serde_json::json!({
+            "stream_naming": "category",
+            "category": "loan",
+            "missing_key_policy": policy
+        }))
+        .unwrap()
+    }
+
+    fn call_without_id() -> MutationCall {
+        MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn missing_keys_fall_back_to_the_field_stream_by_default() {
+        let config = category_config("fallback_to_field_stream");
+
+        assert_eq!(
+            Some(MissingKeyPolicy::FallbackToFieldStream),
+            missing_key_policy(&call_without_id(), &config)
+        );
+        assert_eq!(
+            "graphql-mutation-recordCreditChecked",
+            stream_name(&call_without_id(), &config)
+        );
+    }
+
+    #[test]
+    fn missing_keys_can_skip_persistence() {
+        let config = category_config("skip_persist");
+        let with_id = MutationCall {
+            loan_id: Some("loan-1".to_string()),
+            ..call_without_id()
+        };
+
+        assert_eq!(
+            Some(MissingKeyPolicy::SkipPersist),
+            missing_key_policy(&call_without_id(), &config)
+        );
+        assert_eq!(None, missing_key_policy(&with_id, &config));
+    }
+
+    #[test]
+    fn missing_keys_can_be_dead_lettered() {
+        let config = category_config("dead_letter");
+        let overridden = MutationCall {
+            stream_override: Some("credit-checks".to_string()),
+            ..call_without_id()
+        };
+
+        assert_eq!(
+            Some(MissingKeyPolicy::DeadLetter),
+            missing_key_policy(&call_without_id(), &config)
+        );
+        assert_eq!(None, missing_key_policy(&overridden, &config));
+    }
+}
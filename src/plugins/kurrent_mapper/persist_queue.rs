@@ -0,0 +1,324 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    future::Future,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{sync::Notify, task};
+
+use super::{coalescing::PendingBatch, error::KurrentError, mapper::KurrentConfig};
+
+/// What happens to a batch handed to a full queue.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueFullPolicy {
+    /// Drop the incoming batch.
+    #[default]
+    DropNewest,
+    /// Drop the batch that has waited longest, making room for the incoming one.
+    DropOldest,
+    /// Wait up to `queue_block_timeout_ms` for room, then drop the incoming batch.
+    BlockWithTimeout,
+    /// Answer mutations with a `503` while the queue is full, before they execute.
+    RejectRequest,
+}
+
+/// Rejects `max_queued_batches` together with `flush_interval_ms`: the coalescer
+/// takes batches over an unbounded channel, so the queue would never be built and
+/// `queue_full_policy` would silently not apply.
+pub fn validate_queue(config: &KurrentConfig) -> Result<(), KurrentError> {
+    if config.max_queued_batches.is_some() && config.flush_interval_ms.is_some() {
+        return Err(KurrentError::config(
+            "max_queued_batches cannot be combined with flush_interval_ms, whose coalescer does not bound its input; unset one of them",
+        ));
+    }
+    Ok(())
+}
+
+struct Inner {
+    batches: Mutex<VecDeque<PendingBatch>>,
+    capacity: usize,
+    /// Live [`PersistQueue`] handles; the workers stop once it drops to zero.
+    handles: AtomicUsize,
+    pushed: Notify,
+    popped: Notify,
+}
+
+impl Inner {
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<PendingBatch>> {
+        self.batches
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn try_push(&self, batch: PendingBatch) -> Result<(), PendingBatch> {
+        let mut batches = self.lock();
+        if batches.len() >= self.capacity {
+            return Err(batch);
+        }
+        batches.push_back(batch);
+        drop(batches);
+        self.pushed.notify_one();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.handles.load(Ordering::Acquire) == 0
+    }
+
+    /// The oldest batch, once there is one; `None` when every handle is gone and
+    /// nothing is left.
+    async fn pop(&self) -> Option<PendingBatch> {
+        loop {
+            let pushed = self.pushed.notified();
+            tokio::pin!(pushed);
+            pushed.as_mut().enable();
+
+            if let Some(batch) = self.lock().pop_front() {
+                self.popped.notify_one();
+                return Some(batch);
+            }
+            if self.is_closed() {
+                return None;
+            }
+            pushed.await;
+        }
+    }
+}
+
+/// Batches waiting to be appended by a fixed number of workers, at most
+/// `capacity` of them. Dropping the last handle lets the workers drain what is
+/// left and stop.
+pub struct PersistQueue {
+    inner: Arc<Inner>,
+    policy: QueueFullPolicy,
+    block_timeout: Duration,
+}
+
+impl PersistQueue {
+    pub fn spawn<F, Fut>(
+        capacity: usize,
+        workers: usize,
+        policy: QueueFullPolicy,
+        block_timeout: Duration,
+        persist: F,
+    ) -> Self
+    where
+        F: Fn(PendingBatch) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let inner = Arc::new(Inner {
+            batches: Mutex::new(VecDeque::new()),
+            capacity: capacity.max(1),
+            handles: AtomicUsize::new(1),
+            pushed: Notify::new(),
+            popped: Notify::new(),
+        });
+        for _ in 0..workers.max(1) {
+            let inner = inner.clone();
+            let persist = persist.clone();
+            task::spawn(async move {
+                while let Some(batch) = inner.pop().await {
+                    persist(batch).await;
+                }
+            });
+        }
+        Self {
+            inner,
+            policy,
+            block_timeout,
+        }
+    }
+
+    pub fn policy(&self) -> QueueFullPolicy {
+        self.policy
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.inner.lock().len() >= self.inner.capacity
+    }
+
+    /// Queues `batch` without waiting, returning the batch dropped if the queue is
+    /// full: the oldest one under `drop_oldest`, `batch` itself otherwise.
+    pub fn push(&self, batch: PendingBatch) -> Option<PendingBatch> {
+        if self.policy != QueueFullPolicy::DropOldest {
+            return self.inner.try_push(batch).err();
+        }
+
+        let mut batches = self.inner.lock();
+        let evicted = (batches.len() >= self.inner.capacity)
+            .then(|| batches.pop_front())
+            .flatten();
+        batches.push_back(batch);
+        drop(batches);
+        self.inner.pushed.notify_one();
+        evicted
+    }
+
+    /// Queues `batch` once there is room, returning it if none was made within the
+    /// block timeout.
+    pub async fn push_or_wait(&self, batch: PendingBatch) -> Option<PendingBatch> {
+        let mut batch = Some(batch);
+        let waited = tokio::time::timeout(self.block_timeout, async {
+            loop {
+                let popped = self.inner.popped.notified();
+                tokio::pin!(popped);
+                popped.as_mut().enable();
+
+                match self.inner.try_push(batch.take().expect("not yet queued")) {
+                    Ok(()) => return,
+                    Err(rejected) => batch = Some(rejected),
+                }
+                popped.await;
+            }
+        })
+        .await;
+        waited.err().and(batch)
+    }
+}
+
+impl Clone for PersistQueue {
+    fn clone(&self) -> Self {
+        self.inner.handles.fetch_add(1, Ordering::AcqRel);
+        Self {
+            inner: self.inner.clone(),
+            policy: self.policy,
+            block_timeout: self.block_timeout,
+        }
+    }
+}
+
+impl Drop for PersistQueue {
+    fn drop(&mut self) {
+        if self.inner.handles.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.inner.pushed.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::kurrent_mapper::MutationCall;
+    use tokio::sync::{Semaphore, mpsc};
+
+    #[test]
+    fn queue_is_rejected_together_with_coalescing() {
+        let config: KurrentConfig = serde_json::from_value(serde_json::json!({
+            "max_queued_batches": 8,
+            "flush_interval_ms": 50
+        }))
+        .unwrap();
+
+        let error = validate_queue(&config).unwrap_err();
+
+        assert_eq!("config", error.kind());
+        assert!(error.to_string().contains("flush_interval_ms"));
+    }
+
+    fn batch(id: u64) -> PendingBatch {
+        PendingBatch {
+            calls: vec![MutationCall::default()],
+            wal_id: Some(id),
+        }
+    }
+
+    /// A queue of two whose single worker holds each batch until a permit is
+    /// added, reporting the ids it persisted.
+    fn saturated_queue(
+        policy: QueueFullPolicy,
+    ) -> (PersistQueue, Arc<Semaphore>, mpsc::UnboundedReceiver<u64>) {
+        let gate = Arc::new(Semaphore::new(0));
+        let (persisted, receiver) = mpsc::unbounded_channel();
+        let worker_gate = gate.clone();
+        let queue = PersistQueue::spawn(2, 1, policy, Duration::from_millis(50), move |batch| {
+            let (gate, persisted) = (worker_gate.clone(), persisted.clone());
+            async move {
+                gate.acquire().await.unwrap().forget();
+                persisted.send(batch.wal_id.unwrap()).unwrap();
+            }
+        });
+        (queue, gate, receiver)
+    }
+
+    /// Fills the queue: batch 0 is taken by the worker, 1 and 2 wait.
+    async fn fill(queue: &PersistQueue) {
+        assert!(queue.push(batch(0)).is_none());
+        tokio::task::yield_now().await;
+        assert!(queue.push(batch(1)).is_none());
+        assert!(queue.push(batch(2)).is_none());
+        assert!(queue.is_full());
+    }
+
+    async fn drain(gate: &Semaphore, persisted: &mut mpsc::UnboundedReceiver<u64>) -> Vec<u64> {
+        gate.add_permits(10);
+        let mut ids = Vec::new();
+        while let Ok(Some(id)) =
+            tokio::time::timeout(Duration::from_millis(100), persisted.recv()).await
+        {
+            ids.push(id);
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn drop_newest_refuses_batches_once_full() {
+        let (queue, gate, mut persisted) = saturated_queue(QueueFullPolicy::DropNewest);
+        fill(&queue).await;
+
+        let dropped = queue.push(batch(3));
+
+        assert_eq!(Some(Some(3)), dropped.map(|batch| batch.wal_id));
+        assert_eq!(vec![0, 1, 2], drain(&gate, &mut persisted).await);
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_evicts_the_longest_waiting_batch() {
+        let (queue, gate, mut persisted) = saturated_queue(QueueFullPolicy::DropOldest);
+        fill(&queue).await;
+
+        let dropped = queue.push(batch(3));
+
+        assert_eq!(Some(Some(1)), dropped.map(|batch| batch.wal_id));
+        assert_eq!(vec![0, 2, 3], drain(&gate, &mut persisted).await);
+    }
+
+    #[tokio::test]
+    async fn block_with_timeout_waits_for_room_then_gives_up() {
+        let (queue, gate, mut persisted) = saturated_queue(QueueFullPolicy::BlockWithTimeout);
+        fill(&queue).await;
+
+        let timed_out = queue.push_or_wait(batch(3)).await;
+        assert_eq!(Some(Some(3)), timed_out.map(|batch| batch.wal_id));
+
+        let (queued, ()) =
+            tokio::join!(queue.push_or_wait(batch(4)), async { gate.add_permits(1) });
+        assert!(queued.is_none());
+        assert_eq!(vec![0, 1, 2, 4], drain(&gate, &mut persisted).await);
+    }
+
+    #[tokio::test]
+    async fn reject_request_reports_a_full_queue() {
+        let (queue, gate, mut persisted) = saturated_queue(QueueFullPolicy::RejectRequest);
+        fill(&queue).await;
+
+        assert!(queue.push(batch(3)).is_some());
+        assert_eq!(vec![0, 1, 2], drain(&gate, &mut persisted).await);
+        assert!(!queue.is_full());
+    }
+
+    #[tokio::test]
+    async fn workers_drain_and_stop_once_the_last_handle_is_dropped() {
+        let (queue, gate, mut persisted) = saturated_queue(QueueFullPolicy::DropNewest);
+        fill(&queue).await;
+
+        drop(queue);
+
+        assert_eq!(vec![0, 1, 2], drain(&gate, &mut persisted).await);
+    }
+}
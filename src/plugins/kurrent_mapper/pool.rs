@@ -0,0 +1,92 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use super::connection::ReconnectingClient;
+
+/// Clients appends are spread over round-robin. The `kurrentdb` client multiplexes
+/// every call over one HTTP/2 connection, whose concurrent stream limit caps
+/// in-flight appends; each pooled client has a connection of its own. Every client
+/// reconnects independently.
+pub struct ClientPool<C> {
+    clients: Vec<Arc<ReconnectingClient<C>>>,
+    next: AtomicUsize,
+}
+
+impl<C> ClientPool<C> {
+    /// A pool of `primary` and `size - 1` further clients made by `connect`.
+    pub fn new<E>(
+        primary: Arc<ReconnectingClient<C>>,
+        size: usize,
+        mut connect: impl FnMut() -> Result<ReconnectingClient<C>, E>,
+    ) -> Result<Self, E> {
+        let mut clients = vec![primary];
+        for _ in 1..size {
+            clients.push(Arc::new(connect()?));
+        }
+        Ok(Self {
+            clients,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// The next client in turn.
+    pub fn pick(&self) -> &ReconnectingClient<C> {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.clients.len();
+        &self.clients[index]
+    }
+
+    pub fn contains(&self, client: &ReconnectingClient<C>) -> bool {
+        self.clients
+            .iter()
+            .any(|pooled| std::ptr::eq(pooled.as_ref(), client))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<ReconnectingClient<C>>> {
+        self.clients.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    fn client(id: u32) -> ReconnectingClient<u32> {
+        ReconnectingClient::new(id, 1, move || Ok(id))
+    }
+
+    #[test]
+    fn clients_are_picked_round_robin() {
+        let mut ids = 1..;
+        let pool = ClientPool::new(Arc::new(client(0)), 3, || {
+            Ok::<_, Infallible>(client(ids.next().unwrap()))
+        })
+        .unwrap();
+
+        let picked: Vec<u32> = (0..6).map(|_| *pool.pick().current()).collect();
+
+        assert_eq!(3, pool.len());
+        assert_eq!(vec![0, 1, 2, 0, 1, 2], picked);
+        assert!(pool.contains(pool.pick()));
+        assert!(!pool.contains(&client(0)));
+    }
+
+    #[test]
+    fn a_pool_of_one_is_the_primary_client() {
+        let primary = Arc::new(client(7));
+        let pool = ClientPool::new(primary.clone(), 1, || Ok::<_, Infallible>(client(8))).unwrap();
+
+        assert_eq!(1, pool.len());
+        assert!(std::ptr::eq(primary.as_ref(), pool.pick()));
+    }
+}
@@ -0,0 +1,212 @@
+use futures::future::BoxFuture;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+use super::{
+    error::KurrentError,
+    mapper::{MutationCall, MutationSink},
+};
+
+const MASK: &str = "***";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactionMode {
+    /// Replace the value with `***`.
+    #[default]
+    Mask,
+    /// Replace the value with the hex SHA-256 of the salt followed by the value, a
+    /// stable pseudonym that still correlates equal values.
+    Hash,
+}
+
+/// A value to redact, addressed by a JSON pointer into the call's `arguments` (by
+/// argument name) or `metadata`, e.g. `/arguments/input/NationalID`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RedactionRule {
+    pub pointer: String,
+    #[serde(default)]
+    pub mode: RedactionMode,
+    /// Mutation field the rule applies to; all mutations when omitted.
+    #[serde(default)]
+    pub field: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct RedactionConfig {
+    /// Salt prepended to values before hashing. Keep it secret, or pseudonyms of
+    /// low-entropy values (national ids, phone numbers) can be reversed.
+    #[serde(default)]
+    pub salt: String,
+    #[serde(default)]
+    pub rules: Vec<RedactionRule>,
+}
+
+impl RedactionConfig {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn redact(&self, call: &mut MutationCall) {
+        for rule in &self.rules {
+            if rule
+                .field
+                .as_ref()
+                .is_some_and(|field| field != &call.field_name)
+            {
+                continue;
+            }
+            if let Some(value) = target(call, &rule.pointer) {
+                *value = match rule.mode {
+                    RedactionMode::Mask => Value::from(MASK),
+                    RedactionMode::Hash => Value::from(hash(&self.salt, value)),
+                };
+            }
+        }
+    }
+}
+
+/// The value `pointer` addresses in `call`, if present.
+fn target<'a>(call: &'a mut MutationCall, pointer: &str) -> Option<&'a mut Value> {
+    let mut segments = pointer.strip_prefix('/')?.splitn(3, '/');
+    let section = segments.next()?;
+    let key = unescape(segments.next()?);
+    let rest = segments.next().map(|rest| format!("/{rest}"));
+
+    let value = match section {
+        "arguments" => call
+            .arguments
+            .iter_mut()
+            .find(|arg| arg.name == key)
+            .map(|arg| &mut arg.value)?,
+        "metadata" => call.metadata.get_mut(&key)?,
+        _ => return None,
+    };
+    match rest {
+        Some(rest) => value.pointer_mut(&rest),
+        None => Some(value),
+    }
+}
+
+fn unescape(token: &str) -> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+fn hash(salt: &str, value: &Value) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    match value {
+        Value::String(s) => hasher.update(s.as_bytes()),
+        other => hasher.update(other.to_string().as_bytes()),
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Redacts every call before handing it to `inner`, so no sink (nor the
+/// write-ahead log) ever sees the raw values.
+pub struct RedactingSink {
+    inner: Arc<dyn MutationSink>,
+    config: RedactionConfig,
+}
+
+impl RedactingSink {
+    pub fn new(inner: Arc<dyn MutationSink>, config: RedactionConfig) -> Self {
+        Self { inner, config }
+    }
+}
+
+impl MutationSink for RedactingSink {
+    fn persist_mutations(&self, mut calls: Vec<MutationCall>) {
+        calls.iter_mut().for_each(|call| self.config.redact(call));
+        self.inner.persist_mutations(calls);
+    }
+
+    fn persist_and_wait(
+        &self,
+        mut calls: Vec<MutationCall>,
+    ) -> BoxFuture<'static, Result<(), KurrentError>> {
+        calls.iter_mut().for_each(|call| self.config.redact(call));
+        self.inner.persist_and_wait(calls)
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.inner.is_healthy()
+    }
+
+    fn has_capacity(&self) -> bool {
+        self.inner.has_capacity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::kurrent_mapper::MutationArg;
+    use serde_json::json;
+
+    fn call(national_id: &str) -> MutationCall {
+        MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            arguments: vec![MutationArg {
+                name: "input".to_string(),
+                value: json!({ "NationalID": national_id, "Phone": "555-0100", "Score": 700 }),
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn config(mode: &str) -> RedactionConfig {
+        serde_json::from_value(json!({
+            "salt": "pepper",
+            "rules": [
+                { "pointer": "/arguments/input/NationalID", "mode": mode },
+                { "pointer": "/arguments/input/Phone", "field": "recordLoanRequested" }
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn redacted(config: &RedactionConfig, national_id: &str) -> MutationCall {
+        let mut call = call(national_id);
+        config.redact(&mut call);
+        call
+    }
+
+    #[test]
+    fn hashing_is_stable_per_value_and_salt() {
+        let config = config("hash");
+
+        let first = redacted(&config, "987654321");
+        let again = redacted(&config, "987654321");
+        let other = redacted(&config, "123456789");
+
+        let id = first.argument_value("input.NationalID").unwrap();
+        assert_eq!(64, id.as_str().unwrap().len());
+        assert_eq!(id, again.argument_value("input.NationalID").unwrap());
+        assert_ne!(id, other.argument_value("input.NationalID").unwrap());
+        assert_ne!(&json!("987654321"), id);
+
+        let unsalted = RedactionConfig {
+            salt: String::new(),
+            ..config
+        };
+        assert_ne!(
+            id,
+            redacted(&unsalted, "987654321")
+                .argument_value("input.NationalID")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn masking_replaces_only_matching_pointers_and_fields() {
+        let call = redacted(&config("mask"), "987654321");
+
+        assert_eq!(Some(&json!(MASK)), call.argument_value("input.NationalID"));
+        assert_eq!(Some(&json!("555-0100")), call.argument_value("input.Phone"));
+        assert_eq!(Some(&json!(700)), call.argument_value("input.Score"));
+    }
+}
@@ -0,0 +1,155 @@
+use kurrentdb::{Client, ReadStreamOptions, StreamPosition};
+use serde_json::{Map, Value};
+use tower::BoxError;
+use uuid::Uuid;
+
+use super::{
+    encoding::{CONTENT_TYPE_METADATA_KEY, EventEncoding},
+    error::KurrentError,
+    mapper::{DEAD_LETTER_EVENT_TYPE, MutationCall},
+};
+
+/// A persisted mutation read back from KurrentDB. The event metadata is restored
+/// into `call.metadata`, so the call looks as it did before it was persisted.
+#[derive(Debug, Clone)]
+pub struct ReplayedEvent {
+    pub stream: String,
+    pub revision: u64,
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub call: MutationCall,
+}
+
+/// Receives the replayed events in stream order. An error stops the replay.
+#[async_trait::async_trait]
+pub trait ReplayHandler: Send {
+    async fn handle(&mut self, event: ReplayedEvent) -> Result<(), BoxError>;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplaySummary {
+    pub replayed: u64,
+    /// Dead letters and events that do not decode as a `MutationCall`.
+    pub skipped: u64,
+}
+
+/// Reads `stream` from the start and hands every mutation event to `handler`.
+pub async fn replay(
+    client: &Client,
+    stream: &str,
+    handler: &mut impl ReplayHandler,
+) -> Result<ReplaySummary, BoxError> {
+    let options = ReadStreamOptions::default()
+        .position(StreamPosition::Start)
+        .forwards();
+    let mut events = client
+        .read_stream(stream, &options)
+        .await
+        .map_err(KurrentError::Read)?;
+    let mut summary = ReplaySummary::default();
+
+    while let Some(resolved) = events.next().await.map_err(KurrentError::Read)? {
+        let recorded = resolved.get_original_event();
+        if recorded.event_type == DEAD_LETTER_EVENT_TYPE {
+            summary.skipped += 1;
+            continue;
+        }
+
+        match decode_call(&recorded.data, &recorded.custom_metadata) {
+            Ok(call) => {
+                handler
+                    .handle(ReplayedEvent {
+                        stream: recorded.stream_id.clone(),
+                        revision: recorded.revision,
+                        event_id: recorded.id,
+                        event_type: recorded.event_type.clone(),
+                        call,
+                    })
+                    .await?;
+                summary.replayed += 1;
+            }
+            Err(error) => {
+                tracing::warn!(error = %error, stream = %stream, revision = recorded.revision, event_type = %recorded.event_type, "Skipping event that is not a persisted mutation");
+                summary.skipped += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Decodes an event payload and its metadata back into the `MutationCall` that
+/// produced them. Both argument formats and every `event_encoding` are accepted.
+pub fn decode_call(data: &[u8], metadata: &[u8]) -> Result<MutationCall, KurrentError> {
+    let mut metadata: Map<String, Value> = if metadata.is_empty() {
+        Map::new()
+    } else {
+        serde_json::from_slice(metadata)?
+    };
+    let encoding = EventEncoding::of_metadata(&metadata)?;
+    // Describes the event, not the call.
+    metadata.remove(CONTENT_TYPE_METADATA_KEY);
+
+    let mut call: MutationCall = serde_json::from_value(encoding.codec().decode(data)?)?;
+    metadata.append(&mut call.metadata);
+    call.metadata = metadata;
+    Ok(call)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::kurrent_mapper::SelectedField;
+    use serde_json::json;
+
+    #[test]
+    fn decodes_a_persisted_event_with_its_metadata() {
+        let data = json!({
+            "operation_name": "CheckCredit",
+            "field_name": "recordCreditChecked",
+            "loan_id": "loan-123",
+            "alias": null,
+            "arguments": { "input": { "Score": 700 } },
+            "selected_fields": ["Score"],
+            "sequence": 0
+        });
+        let metadata = json!({ "$correlationId": "c-1", "total": 1 });
+
+        let call =
+            decode_call(data.to_string().as_bytes(), metadata.to_string().as_bytes()).unwrap();
+
+        assert_eq!("recordCreditChecked", call.field_name);
+        assert_eq!(Some("loan-123".to_string()), call.loan_id);
+        assert_eq!(
+            vec![SelectedField::new("Score", "Score")],
+            call.selected_fields
+        );
+        assert_eq!(Some(&json!(700)), call.argument_value("input.Score"));
+        assert_eq!(json!(metadata), Value::Object(call.metadata));
+    }
+
+    #[test]
+    fn events_that_are_not_mutations_do_not_decode() {
+        let lifecycle = json!({ "version": "0.1.0" }).to_string();
+
+        assert!(decode_call(lifecycle.as_bytes(), b"").is_err());
+    }
+
+    #[test]
+    fn decodes_events_written_with_another_encoding() {
+        let codec = EventEncoding::Msgpack.codec();
+        let data = codec
+            .encode(&json!({
+                "field_name": "recordCreditChecked",
+                "arguments": { "input": { "Score": 700 } },
+                "sequence": 0
+            }))
+            .unwrap();
+        let metadata = json!({ CONTENT_TYPE_METADATA_KEY: codec.content_type(), "total": 1 });
+
+        let call = decode_call(&data, metadata.to_string().as_bytes()).unwrap();
+
+        assert_eq!(Some(&json!(700)), call.argument_value("input.Score"));
+        assert_eq!(json!({ "total": 1 }), Value::Object(call.metadata));
+    }
+}
@@ -0,0 +1,60 @@
+use kurrentdb::ExpectedRevision;
+use std::collections::{HashMap, HashSet};
+
+/// Expected revisions for the streams one request appends to more than once. The
+/// first append to a stream expects any revision; each later one expects exactly
+/// the revision the previous append produced, so a concurrent writer cannot
+/// interleave. After a conflict the stream's remaining appends are abandoned.
+#[derive(Debug, Default)]
+pub struct RevisionChain {
+    next: HashMap<String, u64>,
+    aborted: HashSet<String>,
+}
+
+impl RevisionChain {
+    pub fn expected(&self, stream: &str) -> ExpectedRevision {
+        match self.next.get(stream) {
+            Some(revision) => ExpectedRevision::Exact(*revision),
+            None => ExpectedRevision::Any,
+        }
+    }
+
+    /// Records the revision an append to `stream` left the stream at.
+    pub fn appended(&mut self, stream: &str, revision: u64) {
+        self.next.insert(stream.to_string(), revision);
+    }
+
+    pub fn abort(&mut self, stream: &str) {
+        self.next.remove(stream);
+        self.aborted.insert(stream.to_string());
+    }
+
+    pub fn is_aborted(&self, stream: &str) -> bool {
+        self.aborted.contains(stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_appends_expect_the_previous_revision() {
+        let mut chain = RevisionChain::default();
+        assert!(matches!(chain.expected("loans"), ExpectedRevision::Any));
+
+        chain.appended("loans", 7);
+        assert!(matches!(
+            chain.expected("loans"),
+            ExpectedRevision::Exact(7)
+        ));
+        assert!(matches!(
+            chain.expected("credit-checks"),
+            ExpectedRevision::Any
+        ));
+
+        chain.abort("loans");
+        assert!(chain.is_aborted("loans"));
+        assert!(!chain.is_aborted("credit-checks"));
+    }
+}
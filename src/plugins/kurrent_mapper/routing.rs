@@ -0,0 +1,104 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::{error::KurrentError, mapper::MutationCall};
+
+/// Routes calls whose argument at `argument` equals `equals` to another cluster
+/// and/or stream, e.g. `input.region == "EU"` to the EU cluster for data residency.
+/// Rules are evaluated in order and the first match wins.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RoutingRule {
+    /// Mutation field the rule applies to; all mutations when omitted.
+    #[serde(default)]
+    pub field: Option<String>,
+    /// Dotted argument path, e.g. `input.region`.
+    pub argument: String,
+    pub equals: Value,
+    /// Name of an entry in `targets`; the primary connection when omitted.
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Stream replacing the one derived from the naming strategy.
+    #[serde(default)]
+    pub stream: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TargetConfig {
+    pub connection_string: String,
+}
+
+pub fn matching_rule<'a>(call: &MutationCall, rules: &'a [RoutingRule]) -> Option<&'a RoutingRule> {
+    rules.iter().find(|rule| {
+        rule.field
+            .as_ref()
+            .is_none_or(|field| field == &call.field_name)
+            && call.argument_value(&rule.argument) == Some(&rule.equals)
+    })
+}
+
+pub fn validate_rules(
+    rules: &[RoutingRule],
+    targets: &HashMap<String, TargetConfig>,
+) -> Result<(), KurrentError> {
+    for rule in rules {
+        if let Some(target) = &rule.target
+            && !targets.contains_key(target)
+        {
+            return Err(KurrentError::config(format!(
+                "routing rule refers to unknown target `{target}`"
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::kurrent_mapper::MutationArg;
+    use serde_json::json;
+
+    fn loan_requested(region: &str) -> MutationCall {
+        MutationCall {
+            field_name: "recordLoanRequested".to_string(),
+            arguments: vec![MutationArg {
+                name: "input".to_string(),
+                value: json!({ "Name": "Jane", "region": region }),
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn rules() -> Vec<RoutingRule> {
+        serde_json::from_value(json!([
+            { "argument": "input.region", "equals": "EU", "target": "eu", "stream": "loans-eu" }
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn region_argument_routes_to_the_configured_target() {
+        let rules = rules();
+
+        let rule = matching_rule(&loan_requested("EU"), &rules).expect("EU rule");
+
+        assert_eq!(Some("eu"), rule.target.as_deref());
+        assert_eq!(Some("loans-eu"), rule.stream.as_deref());
+        assert!(matching_rule(&loan_requested("US"), &rules).is_none());
+    }
+
+    #[test]
+    fn rules_must_reference_known_targets() {
+        let targets = HashMap::from([(
+            "eu".to_string(),
+            TargetConfig {
+                connection_string: "kurrentdb://eu:2113".to_string(),
+            },
+        )]);
+
+        assert!(validate_rules(&rules(), &targets).is_ok());
+        assert!(validate_rules(&rules(), &HashMap::new()).is_err());
+    }
+}
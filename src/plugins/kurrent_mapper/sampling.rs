@@ -0,0 +1,138 @@
+use futures::future::{self, BoxFuture};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::{
+    error::KurrentError,
+    mapper::{CORRELATION_ID_METADATA_KEY, MutationCall, MutationSink},
+    scan_header::fnv1a,
+};
+
+/// Keeps a `rate` fraction of calls. Per correlation id, the decision is made once
+/// for the whole request, so its mutations are kept or dropped together.
+#[derive(Debug, Clone, Copy)]
+pub struct Sampler {
+    rate: f64,
+    by_correlation_id: bool,
+}
+
+impl Sampler {
+    pub fn new(rate: f64, by_correlation_id: bool) -> Self {
+        Self {
+            rate: rate.clamp(0.0, 1.0),
+            by_correlation_id,
+        }
+    }
+
+    pub fn keeps(&self, call: &MutationCall) -> bool {
+        let correlation_id = call
+            .metadata
+            .get(CORRELATION_ID_METADATA_KEY)
+            .and_then(|id| id.as_str());
+        let bucket = match correlation_id {
+            Some(id) if self.by_correlation_id => fnv1a(id),
+            _ => Uuid::new_v4().as_u128() as u32,
+        };
+        // In [0, 1), so a rate of 1 keeps every call.
+        (bucket as f64 / (u32::MAX as f64 + 1.0)) < self.rate
+    }
+}
+
+/// Drops calls not kept by its [`Sampler`] before handing the rest to `inner`.
+pub struct SampledSink {
+    inner: Arc<dyn MutationSink>,
+    sampler: Sampler,
+}
+
+impl SampledSink {
+    pub fn new(inner: Arc<dyn MutationSink>, sampler: Sampler) -> Self {
+        Self { inner, sampler }
+    }
+}
+
+impl SampledSink {
+    fn sample(&self, mut calls: Vec<MutationCall>) -> Vec<MutationCall> {
+        let total = calls.len();
+        calls.retain(|call| self.sampler.keeps(call));
+        let sampled_out = (total - calls.len()) as u64;
+        if sampled_out > 0 {
+            tracing::debug!(
+                monotonic_counter.kurrent_sampled_out_total = sampled_out,
+                "Dropped mutation(s) by sample_rate"
+            );
+        }
+        calls
+    }
+}
+
+impl MutationSink for SampledSink {
+    fn persist_mutations(&self, calls: Vec<MutationCall>) {
+        let calls = self.sample(calls);
+        if !calls.is_empty() {
+            self.inner.persist_mutations(calls);
+        }
+    }
+
+    fn persist_and_wait(
+        &self,
+        calls: Vec<MutationCall>,
+    ) -> BoxFuture<'static, Result<(), KurrentError>> {
+        let calls = self.sample(calls);
+        if calls.is_empty() {
+            return Box::pin(future::ready(Ok(())));
+        }
+        self.inner.persist_and_wait(calls)
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.inner.is_healthy()
+    }
+
+    fn has_capacity(&self) -> bool {
+        self.inner.has_capacity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn call(correlation_id: &str) -> MutationCall {
+        let mut call = MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            ..Default::default()
+        };
+        call.metadata.insert(
+            CORRELATION_ID_METADATA_KEY.to_string(),
+            Value::from(correlation_id.to_string()),
+        );
+        call
+    }
+
+    #[test]
+    fn rates_of_zero_and_one_drop_or_keep_everything() {
+        let none = Sampler::new(0.0, false);
+        let all = Sampler::new(1.0, false);
+
+        assert!((0..100).all(|i| !none.keeps(&call(&i.to_string()))));
+        assert!((0..100).all(|i| all.keeps(&call(&i.to_string()))));
+    }
+
+    #[test]
+    fn requests_are_sampled_as_a_whole_by_correlation_id() {
+        let sampler = Sampler::new(0.5, true);
+
+        let decisions: Vec<bool> = (0..200)
+            .map(|i| {
+                let id = format!("request-{i}");
+                let first = sampler.keeps(&call(&id));
+                assert!((0..5).all(|_| sampler.keeps(&call(&id)) == first));
+                first
+            })
+            .collect();
+
+        let kept = decisions.iter().filter(|kept| **kept).count();
+        assert!((50..150).contains(&kept), "kept {kept} of 200");
+    }
+}
@@ -0,0 +1,118 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::mapper::MutationCall;
+
+/// Fixed 16-byte header written to event metadata so consumers scanning many
+/// events can filter on type, aggregate and time without parsing the JSON payload.
+///
+/// Layout (big-endian): event type hash (u32), aggregate id hash (u32),
+/// timestamp in milliseconds since the Unix epoch (u64). Hashes are 32-bit FNV-1a.
+/// The header is stored hex-encoded under the `scan_header` metadata key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanHeader {
+    pub event_type_hash: u32,
+    pub aggregate_id_hash: u32,
+    pub timestamp_ms: u64,
+}
+
+impl ScanHeader {
+    pub const LEN: usize = 16;
+
+    /// Header for `call` persisted as `event_type`. The aggregate is the call's
+    /// `loan_id`, falling back to the target stream when there is none.
+    pub fn for_call(
+        call: &MutationCall,
+        event_type: &str,
+        stream_name: &str,
+        timestamp_ms: u64,
+    ) -> Self {
+        let aggregate_id = call.loan_id.as_deref().unwrap_or(stream_name);
+        Self {
+            event_type_hash: fnv1a(event_type),
+            aggregate_id_hash: fnv1a(aggregate_id),
+            timestamp_ms,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        bytes[..4].copy_from_slice(&self.event_type_hash.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.aggregate_id_hash.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.timestamp_ms.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: [u8; Self::LEN]) -> Self {
+        let (event_type, rest) = bytes.split_at(4);
+        let (aggregate_id, timestamp) = rest.split_at(4);
+        Self {
+            event_type_hash: u32::from_be_bytes(event_type.try_into().unwrap()),
+            aggregate_id_hash: u32::from_be_bytes(aggregate_id.try_into().unwrap()),
+            timestamp_ms: u64::from_be_bytes(timestamp.try_into().unwrap()),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        self.to_bytes()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    pub fn decode(encoded: &str) -> Option<Self> {
+        if encoded.len() != Self::LEN * 2 || !encoded.is_ascii() {
+            return None;
+        }
+        let mut bytes = [0; Self::LEN];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&encoded[i * 2..i * 2 + 2], 16).ok()?;
+        }
+        Some(Self::from_bytes(bytes))
+    }
+}
+
+/// 32-bit FNV-1a, chosen because it is trivial to reimplement in any consumer.
+pub fn fnv1a(value: &str) -> u32 {
+    value.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_header_fields_match_the_event() {
+        let call = MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            loan_id: Some("loan-123".to_string()),
+            ..Default::default()
+        };
+
+        let header = ScanHeader::for_call(
+            &call,
+            "GraphQL.CheckCredit",
+            "graphql-mutation-recordCreditChecked",
+            1_700_000_000_000,
+        );
+        let decoded = ScanHeader::decode(&header.encode()).expect("valid header");
+
+        assert_eq!(fnv1a("GraphQL.CheckCredit"), decoded.event_type_hash);
+        assert_eq!(fnv1a("loan-123"), decoded.aggregate_id_hash);
+        assert_eq!(1_700_000_000_000, decoded.timestamp_ms);
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_vectors() {
+        assert_eq!(0x811c_9dc5, fnv1a(""));
+        assert_eq!(0xe40c_292c, fnv1a("a"));
+    }
+}
@@ -0,0 +1,80 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU32;
+
+use super::{
+    mapper::{MutationCall, resolve_call_value},
+    scan_header::fnv1a,
+};
+
+fn default_shard_key() -> String {
+    "loan_id".to_string()
+}
+
+/// Spreads one mutation type over `count` streams, `{base}-{bucket}`. Calls with
+/// the same key always land in the same bucket, so their order is preserved.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ShardConfig {
+    pub count: NonZeroU32,
+    /// `loan_id` or an argument path such as `input.customerId`.
+    #[serde(default = "default_shard_key")]
+    pub key: String,
+}
+
+impl ShardConfig {
+    /// Bucket of `call`, or `None` when its shard key cannot be resolved.
+    pub fn bucket(&self, call: &MutationCall) -> Option<u32> {
+        let key = resolve_call_value(call, &self.key)?;
+        Some(fnv1a(&key) % self.count.get())
+    }
+}
+
+/// Appends the call's bucket to `stream`. Calls without a shard key stay on the
+/// unsharded stream.
+pub fn sharded_stream(call: &MutationCall, shard: &ShardConfig, stream: String) -> String {
+    match shard.bucket(call) {
+        Some(bucket) => format!("{stream}-{bucket}"),
+        None => {
+            tracing::warn!(mutation = %call.field_name, key = %shard.key, "Shard key missing, using the unsharded stream");
+            stream
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(loan_id: &str) -> MutationCall {
+        MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            loan_id: Some(loan_id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn shard_assignment_is_deterministic_per_key() {
+        let shard: ShardConfig = serde_json::from_value(json!({ "count": 8 })).unwrap();
+
+        let first = sharded_stream(&call("loan-1"), &shard, "credit".to_string());
+        let again = sharded_stream(&call("loan-1"), &shard, "credit".to_string());
+        let buckets: std::collections::HashSet<_> = (0..100)
+            .filter_map(|i| shard.bucket(&call(&format!("loan-{i}"))))
+            .collect();
+
+        assert_eq!(first, again);
+        assert_eq!(format!("credit-{}", fnv1a("loan-1") % 8), first);
+        assert!(buckets.len() > 1 && buckets.iter().all(|bucket| *bucket < 8));
+        assert_eq!(
+            "credit",
+            sharded_stream(&MutationCall::default(), &shard, "credit".to_string())
+        );
+    }
+
+    #[test]
+    fn zero_shards_are_rejected() {
+        assert!(serde_json::from_value::<ShardConfig>(json!({ "count": 0 })).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Bounds the appends in flight to each stream, without limiting appends to
+/// different streams. Waiters are admitted in arrival order, so with one permit
+/// per stream, appends land in the order they were issued.
+#[derive(Debug)]
+pub struct StreamLimiter {
+    permits: usize,
+    streams: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+}
+
+impl StreamLimiter {
+    pub fn new(permits: usize) -> Self {
+        Self {
+            permits: permits.max(1),
+            streams: Arc::default(),
+        }
+    }
+
+    pub async fn acquire(&self, stream: &str) -> StreamPermit {
+        let semaphore = self
+            .lock()
+            .entry(stream.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.permits)))
+            .clone();
+        let permit = semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("stream semaphores are never closed");
+
+        StreamPermit {
+            stream: stream.to_string(),
+            semaphore,
+            permit: Some(permit),
+            streams: self.streams.clone(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Arc<Semaphore>>> {
+        self.streams
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// An admitted append. Dropping it admits the next one; the last one out removes
+/// the stream's entry, so idle streams do not accumulate.
+#[derive(Debug)]
+pub struct StreamPermit {
+    stream: String,
+    semaphore: Arc<Semaphore>,
+    permit: Option<OwnedSemaphorePermit>,
+    streams: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+}
+
+impl Drop for StreamPermit {
+    fn drop(&mut self) {
+        self.permit.take();
+        let mut streams = self
+            .streams
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        // Held only by the map and this permit: no one else holds or awaits it.
+        if Arc::strong_count(&self.semaphore) == 2 {
+            streams.remove(&self.stream);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Peak number of the `streams` appends that overlapped.
+    async fn peak_concurrency(limiter: Arc<StreamLimiter>, streams: &[&str]) -> usize {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let appends: Vec<_> = streams
+            .iter()
+            .map(|stream| {
+                let (limiter, in_flight, peak) = (limiter.clone(), in_flight.clone(), peak.clone());
+                let stream = stream.to_string();
+                tokio::spawn(async move {
+                    let _permit = limiter.acquire(&stream).await;
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for append in appends {
+            append.await.unwrap();
+        }
+
+        peak.load(Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn appends_to_one_stream_never_overlap() {
+        let limiter = Arc::new(StreamLimiter::new(1));
+
+        let peak = peak_concurrency(limiter.clone(), &["loan-1", "loan-1", "loan-1"]).await;
+
+        assert_eq!(1, peak);
+        assert!(limiter.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn appends_to_different_streams_run_concurrently() {
+        let limiter = Arc::new(StreamLimiter::new(1));
+
+        let peak = peak_concurrency(limiter, &["loan-1", "loan-2", "loan-3"]).await;
+
+        assert_eq!(3, peak);
+    }
+}
@@ -0,0 +1,111 @@
+use kurrentdb::{Acl, StreamAclBuilder, StreamMetadata, StreamMetadataBuilder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::Duration,
+};
+
+/// Key of the `stream_metadata` entry applied to mutation fields without their own.
+pub const ALL_FIELDS: &str = "*";
+
+/// Retention and access settings applied to a stream before the connector first
+/// writes to it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct StreamMetadataConfig {
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+    #[serde(default)]
+    pub max_count: Option<u64>,
+    #[serde(default)]
+    pub read_roles: Vec<String>,
+    #[serde(default)]
+    pub write_roles: Vec<String>,
+}
+
+impl StreamMetadataConfig {
+    pub fn to_metadata(&self) -> StreamMetadata {
+        let mut builder = StreamMetadataBuilder::new();
+        if let Some(max_age_secs) = self.max_age_secs {
+            builder = builder.max_age(Duration::from_secs(max_age_secs));
+        }
+        if let Some(max_count) = self.max_count {
+            builder = builder.max_count(max_count);
+        }
+        if !self.read_roles.is_empty() || !self.write_roles.is_empty() {
+            let acl = self
+                .read_roles
+                .iter()
+                .fold(StreamAclBuilder::new(), |acl, role| {
+                    acl.add_read_roles(role)
+                });
+            let acl = self
+                .write_roles
+                .iter()
+                .fold(acl, |acl, role| acl.add_write_roles(role));
+            builder = builder.acl(Acl::Stream(acl.build()));
+        }
+        builder.build()
+    }
+}
+
+/// Settings for the streams of `field_name`: its own entry, else the `*` entry.
+pub fn metadata_for<'a>(
+    configs: &'a HashMap<String, StreamMetadataConfig>,
+    field_name: &str,
+) -> Option<&'a StreamMetadataConfig> {
+    configs.get(field_name).or_else(|| configs.get(ALL_FIELDS))
+}
+
+/// Streams whose metadata this process has already set, so it is set once per
+/// stream rather than before every append.
+#[derive(Debug, Default)]
+pub struct ConfiguredStreams {
+    streams: Mutex<HashSet<String>>,
+}
+
+impl ConfiguredStreams {
+    pub fn contains(&self, stream: &str) -> bool {
+        self.streams.lock().unwrap().contains(stream)
+    }
+
+    pub fn insert(&self, stream: &str) {
+        self.streams.lock().unwrap().insert(stream.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn field_entries_take_precedence_over_the_wildcard() {
+        let configs: HashMap<String, StreamMetadataConfig> = serde_json::from_value(json!({
+            "*": { "max_age_secs": 86400 },
+            "recordCreditChecked": { "max_count": 1000 }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            Some(1000),
+            metadata_for(&configs, "recordCreditChecked").and_then(|c| c.max_count)
+        );
+        assert_eq!(
+            Some(86400),
+            metadata_for(&configs, "recordLoanRequested").and_then(|c| c.max_age_secs)
+        );
+        assert!(metadata_for(&HashMap::new(), "recordLoanRequested").is_none());
+    }
+
+    #[test]
+    fn streams_are_remembered_once_configured() {
+        let configured = ConfiguredStreams::default();
+        assert!(!configured.contains("graphql-mutation-recordCreditChecked"));
+
+        configured.insert("graphql-mutation-recordCreditChecked");
+
+        assert!(configured.contains("graphql-mutation-recordCreditChecked"));
+    }
+}
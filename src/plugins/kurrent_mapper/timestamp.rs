@@ -0,0 +1,111 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{mapper::MutationCall, scan_header::now_ms};
+
+pub const TIMESTAMP_METADATA_KEY: &str = "timestamp";
+
+/// Source of the logical event time written as `timestamp` metadata, independent
+/// of the time KurrentDB records the write at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EventTimestamp {
+    /// Milliseconds since the Unix epoch when the call reached the sink.
+    Now,
+    /// A dotted argument path, e.g. `input.CreditCheckedTimestamp`.
+    Argument(String),
+    /// A dotted path within the mutation's response, e.g. `createdAt`. Requires
+    /// `persist_response_data`.
+    Response(String),
+}
+
+impl EventTimestamp {
+    /// Records the call's logical time, unless it already carries one (e.g. a batch
+    /// replayed from the write-ahead log). A missing or `null` argument or response
+    /// value falls back to the current time.
+    pub fn stamp(&self, call: &mut MutationCall) {
+        if call.metadata.contains_key(TIMESTAMP_METADATA_KEY) {
+            return;
+        }
+
+        let path = match self {
+            EventTimestamp::Now => None,
+            EventTimestamp::Argument(path) => Some(path.clone()),
+            EventTimestamp::Response(path) => Some(format!("responseData.{path}")),
+        };
+        let value = match path {
+            Some(path) => match call.argument_value(&path).filter(|value| !value.is_null()) {
+                Some(value) => value.clone(),
+                None => {
+                    tracing::debug!(mutation = %call.field_name, path = %path, "No event timestamp at the configured path, using the current time");
+                    Value::from(now_ms())
+                }
+            },
+            None => Value::from(now_ms()),
+        };
+        call.metadata
+            .insert(TIMESTAMP_METADATA_KEY.to_string(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::kurrent_mapper::MutationArg;
+    use serde_json::json;
+
+    fn call() -> MutationCall {
+        MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            arguments: vec![
+                MutationArg {
+                    name: "input".to_string(),
+                    value: json!({ "CreditCheckedTimestamp": "2025-01-02T03:04:05Z" }),
+                },
+                MutationArg {
+                    name: "responseData".to_string(),
+                    value: json!({ "checkedAt": 1_735_787_045_000u64 }),
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn stamped(source: EventTimestamp) -> Value {
+        let mut call = call();
+        source.stamp(&mut call);
+        call.metadata[TIMESTAMP_METADATA_KEY].clone()
+    }
+
+    #[test]
+    fn timestamps_come_from_the_configured_source() {
+        assert_eq!(
+            json!("2025-01-02T03:04:05Z"),
+            stamped(EventTimestamp::Argument(
+                "input.CreditCheckedTimestamp".to_string()
+            ))
+        );
+        assert_eq!(
+            json!(1_735_787_045_000u64),
+            stamped(EventTimestamp::Response("checkedAt".to_string()))
+        );
+        assert!(stamped(EventTimestamp::Now).as_u64().unwrap() >= 1_735_787_045_000);
+        assert!(
+            stamped(EventTimestamp::Argument("input.Missing".to_string()))
+                .as_u64()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn an_existing_timestamp_is_kept() {
+        let mut call = call();
+        call.metadata
+            .insert(TIMESTAMP_METADATA_KEY.to_string(), json!(42));
+
+        EventTimestamp::Now.stamp(&mut call);
+
+        assert_eq!(json!(42), call.metadata[TIMESTAMP_METADATA_KEY]);
+    }
+}
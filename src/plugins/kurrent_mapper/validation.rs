@@ -0,0 +1,123 @@
+use jsonschema::Validator;
+use serde_json::Value;
+use std::{collections::HashMap, fs};
+
+use super::{error::KurrentError, mapper::MutationCall};
+
+/// Per-mutation JSON Schemas the resolved arguments (`{ "input": { ... } }`) must
+/// satisfy before a call is persisted.
+#[derive(Default)]
+pub struct ArgumentValidators {
+    validators: HashMap<String, Validator>,
+}
+
+impl ArgumentValidators {
+    /// Compiles the configured schemas. A string value is read as a path to a JSON
+    /// Schema file; any other value is used as an inline schema.
+    pub fn compile(schemas: &HashMap<String, Value>) -> Result<Self, KurrentError> {
+        let mut validators = HashMap::new();
+        for (field_name, schema) in schemas {
+            let schema = match schema {
+                Value::String(path) => {
+                    let contents = fs::read_to_string(path).map_err(|err| {
+                        KurrentError::config(format!(
+                            "cannot read argument schema {path} for {field_name}: {err}"
+                        ))
+                    })?;
+                    serde_json::from_str(&contents).map_err(|err| {
+                        KurrentError::config(format!(
+                            "invalid JSON in argument schema {path} for {field_name}: {err}"
+                        ))
+                    })?
+                }
+                inline => inline.clone(),
+            };
+
+            let validator = jsonschema::validator_for(&schema).map_err(|err| {
+                KurrentError::config(format!("invalid argument schema for {field_name}: {err}"))
+            })?;
+            validators.insert(field_name.clone(), validator);
+        }
+
+        Ok(Self { validators })
+    }
+
+    /// Returns the validation errors for calls whose arguments do not conform.
+    pub fn validate(&self, call: &MutationCall) -> Result<(), String> {
+        let Some(validator) = self.validators.get(&call.field_name) else {
+            return Ok(());
+        };
+
+        let instance = Value::Object(call.arguments_map());
+        let errors: Vec<String> = validator
+            .iter_errors(&instance)
+            .map(|error| error.to_string())
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::kurrent_mapper::MutationArg;
+    use serde_json::json;
+
+    fn credit_checked(score: Value) -> MutationCall {
+        MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            arguments: vec![MutationArg {
+                name: "input".to_string(),
+                value: json!({ "loanId": "loan-1", "Score": score }),
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn validators() -> ArgumentValidators {
+        let schemas = HashMap::from([(
+            "recordCreditChecked".to_string(),
+            json!({
+                "type": "object",
+                "required": ["input"],
+                "properties": {
+                    "input": {
+                        "type": "object",
+                        "required": ["Score"],
+                        "properties": { "Score": { "type": "integer", "minimum": 300 } }
+                    }
+                }
+            }),
+        )]);
+        ArgumentValidators::compile(&schemas).unwrap()
+    }
+
+    #[test]
+    fn conforming_arguments_pass() {
+        assert!(validators().validate(&credit_checked(json!(720))).is_ok());
+    }
+
+    #[test]
+    fn non_conforming_arguments_report_the_violation() {
+        let error = validators()
+            .validate(&credit_checked(json!("high")))
+            .unwrap_err();
+
+        assert!(error.contains("integer"), "unexpected error: {error}");
+    }
+
+    #[test]
+    fn mutations_without_a_schema_are_not_validated() {
+        let call = MutationCall {
+            field_name: "recordAutomatedSummary".to_string(),
+            ..Default::default()
+        };
+
+        assert!(validators().validate(&call).is_ok());
+    }
+}
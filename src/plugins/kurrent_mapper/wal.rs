@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use super::mapper::MutationCall;
+
+/// One line of the log: a batch handed to the sink, or the acknowledgement that
+/// it reached KurrentDB.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Record {
+    Batch { id: u64, calls: Vec<MutationCall> },
+    Ack { ack: u64 },
+}
+
+/// Local append-only log of batches not yet acknowledged by KurrentDB, so a crash
+/// between accepting and persisting a batch does not lose it. Each batch is
+/// written (and synced) before it is persisted and acknowledged after; the file is
+/// truncated whenever nothing is outstanding, and compacted once acknowledgements
+/// outnumber the batches still outstanding, e.g. ones that failed to persist.
+pub struct WriteAheadLog {
+    path: PathBuf,
+    state: Mutex<WalState>,
+}
+
+struct WalState {
+    file: File,
+    next_id: u64,
+    outstanding: usize,
+    /// Acknowledgements written since the log was last truncated or compacted.
+    acked: usize,
+}
+
+/// Acknowledgements after which a log that still has outstanding batches is
+/// compacted, provided they outnumber those batches.
+const COMPACTION_ACKS: usize = 256;
+
+impl WriteAheadLog {
+    /// Opens the log at `path`, returning it together with the batches a previous
+    /// process left unacknowledged, in the order they were written.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<(Self, Vec<(u64, Vec<MutationCall>)>)> {
+        let path = path.as_ref().to_path_buf();
+        let (file, pending) = compact(&path)?;
+
+        let wal = Self {
+            path,
+            state: Mutex::new(WalState {
+                file,
+                next_id: pending.keys().next_back().map_or(0, |id| id + 1),
+                outstanding: pending.len(),
+                acked: 0,
+            }),
+        };
+        Ok((wal, pending.into_iter().collect()))
+    }
+
+    /// Durably records `calls` and returns the id to acknowledge them with.
+    pub fn append(&self, calls: &[MutationCall]) -> io::Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        write_record(
+            &mut state.file,
+            &Record::Batch {
+                id,
+                calls: calls.to_vec(),
+            },
+        )?;
+        state.file.sync_data()?;
+        state.next_id += 1;
+        state.outstanding += 1;
+        Ok(id)
+    }
+
+    /// Marks batch `id` as persisted.
+    pub fn ack(&self, id: u64) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.outstanding = state.outstanding.saturating_sub(1);
+        if state.outstanding == 0 {
+            state.file.set_len(0)?;
+            state.acked = 0;
+            return Ok(());
+        }
+        write_record(&mut state.file, &Record::Ack { ack: id })?;
+
+        state.acked += 1;
+        if state.acked >= COMPACTION_ACKS && state.acked > state.outstanding {
+            let (file, pending) = compact(&self.path)?;
+            tracing::debug!(path = %self.path.display(), outstanding = pending.len(), "Compacted the write-ahead log");
+            state.file = file;
+            state.outstanding = pending.len();
+            state.acked = 0;
+        }
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Rewrites the log at `path` with only its unacknowledged batches, returning it
+/// opened for appending together with those batches. The rewrite goes to a file
+/// next to the log that replaces it once synced, so a crash while compacting leaves
+/// the old log intact.
+fn compact(path: &Path) -> io::Result<(File, BTreeMap<u64, Vec<MutationCall>>)> {
+    let pending = match File::open(path) {
+        Ok(file) => unacknowledged(BufReader::new(file))?,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(error) => return Err(error),
+    };
+
+    let compacted = compaction_path(path);
+    let mut file = File::create(&compacted)?;
+    for (id, calls) in &pending {
+        write_record(
+            &mut file,
+            &Record::Batch {
+                id: *id,
+                calls: calls.clone(),
+            },
+        )?;
+    }
+    file.sync_data()?;
+    fs::rename(&compacted, path)?;
+    let file = OpenOptions::new().append(true).open(path)?;
+    Ok((file, pending))
+}
+
+/// `<path>.compacting`, in the log's own directory so the rename is atomic.
+fn compaction_path(path: &Path) -> PathBuf {
+    let mut compacted = path.as_os_str().to_owned();
+    compacted.push(".compacting");
+    PathBuf::from(compacted)
+}
+
+fn write_record(file: &mut File, record: &Record) -> io::Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    file.write_all(&line)
+}
+
+fn unacknowledged(reader: impl BufRead) -> io::Result<BTreeMap<u64, Vec<MutationCall>>> {
+    let mut pending = BTreeMap::new();
+    for line in reader.lines() {
+        let line = line?;
+        // A torn last line is what a crash mid-write leaves; its batch was never
+        // handed on, so it is safe to drop.
+        match serde_json::from_str(&line) {
+            Ok(Record::Batch { id, calls }) => {
+                pending.insert(id, calls);
+            }
+            Ok(Record::Ack { ack }) => {
+                pending.remove(&ack);
+            }
+            Err(error) => {
+                tracing::warn!(error = %error, "Skipping unreadable write-ahead log entry");
+            }
+        }
+    }
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("starstuff-wal-{}.log", uuid::Uuid::new_v4()))
+    }
+
+    fn call(field_name: &str) -> MutationCall {
+        MutationCall {
+            field_name: field_name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unacknowledged_batches_are_replayed_after_a_restart() {
+        let path = temp_path();
+        let (wal, pending) = WriteAheadLog::open(&path).unwrap();
+        assert!(pending.is_empty());
+
+        let first = wal.append(&[call("recordLoanRequested")]).unwrap();
+        wal.append(&[call("recordCreditChecked")]).unwrap();
+        wal.ack(first).unwrap();
+        drop(wal);
+
+        let (wal, pending) = WriteAheadLog::open(&path).unwrap();
+        assert_eq!(1, pending.len());
+        assert_eq!("recordCreditChecked", pending[0].1[0].field_name);
+        assert!(!compaction_path(&path).exists());
+
+        let next = wal.append(&[call("recordAutomatedSummary")]).unwrap();
+        assert!(next > pending[0].0);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn log_is_truncated_once_everything_is_acknowledged() {
+        let path = temp_path();
+        let (wal, _) = WriteAheadLog::open(&path).unwrap();
+
+        let id = wal.append(&[call("recordLoanRequested")]).unwrap();
+        wal.ack(id).unwrap();
+
+        assert_eq!(0, std::fs::metadata(wal.path()).unwrap().len());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn log_with_a_failed_batch_is_compacted_while_running() {
+        let path = temp_path();
+        let (wal, _) = WriteAheadLog::open(&path).unwrap();
+        let failed = wal.append(&[call("recordLoanRequested")]).unwrap();
+        let single_batch = std::fs::metadata(wal.path()).unwrap().len();
+
+        for _ in 0..COMPACTION_ACKS {
+            let id = wal.append(&[call("recordCreditChecked")]).unwrap();
+            wal.ack(id).unwrap();
+        }
+
+        assert_eq!(single_batch, std::fs::metadata(wal.path()).unwrap().len());
+        drop(wal);
+        let (_, pending) = WriteAheadLog::open(&path).unwrap();
+        assert_eq!(
+            vec![failed],
+            pending.iter().map(|(id, _)| *id).collect::<Vec<_>>()
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
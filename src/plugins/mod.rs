@@ -1,2 +1,11 @@
+#[cfg(test)]
+pub mod captured_logs;
+pub mod corpus;
+pub mod enricher;
+pub mod enum_tagging;
+pub mod field_pattern;
+pub mod input_defaults;
 pub mod kurrent_mapper;
 pub mod mutation_plugin;
+pub mod scalar_coercion;
+pub mod subgraph_owners;
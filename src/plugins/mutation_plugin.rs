@@ -1,37 +1,616 @@
-use apollo_parser::{Parser, cst::CstNode};
+use apollo_parser::{Parser, SyntaxTree, cst::CstNode};
 use apollo_router::{
+    Context, graphql,
     layers::ServiceBuilderExt,
     plugin::{Plugin, PluginInit},
-    services::supergraph,
+    services::{subgraph, supergraph},
 };
 use futures::stream::StreamExt;
+use http::{HeaderMap, StatusCode};
+use opentelemetry::trace::TraceContextExt;
 use schemars::JsonSchema;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ops::ControlFlow;
 use std::sync::Arc;
+use std::time::Duration;
 use tower::ServiceExt;
 use tower::{BoxError, ServiceBuilder};
+use uuid::Uuid;
 
 use apollo_parser::cst::Value::*;
-use apollo_parser::cst::{Definition, Selection, SelectionSet, Value as ASTValue};
+use apollo_parser::cst::{
+    Definition, Document, FragmentDefinition, OperationDefinition, Selection, SelectionSet,
+    Value as ASTValue,
+};
 
+use crate::plugins::enricher::{Enricher, ResponseIdEnricher, value_at_path};
+use crate::plugins::enum_tagging::{EnumTypes, tag_enum};
+use crate::plugins::field_pattern::FieldPatterns;
+use crate::plugins::input_defaults::InputDefaults;
 use crate::plugins::kurrent_mapper::{
-    KurrentConfig, KurrentService, MutationArg, MutationCall, MutationSink,
+    CAUSATION_ID_METADATA_KEY, CORRELATION_ID_METADATA_KEY, CompositeSink, DisabledSink,
+    EVENT_ID_METADATA_KEY, IDEMPOTENCY_KEY_METADATA_KEY, KurrentConfig, KurrentService, MemorySink,
+    MutationArg, MutationCall, MutationSink, OperationType, RedactingSink, RedactionConfig,
+    SampledSink, Sampler, SelectedField, SinkDefinition, resolve_call_value,
 };
+use crate::plugins::scalar_coercion::{InvalidScalarPolicy, ScalarCoercion, ScalarCoercions};
+use crate::plugins::subgraph_owners::SubgraphOwners;
+
+/// Name the plugin is registered under (`plugins.starstuff.mutation_plugin` in
+/// `router.yaml`). Logs and metrics carry it as `plugin`, so they are attributable
+/// when several plugins run. Must match the `register_plugin!` call below.
+pub const PLUGIN_NAME: &str = "starstuff.mutation_plugin";
+
+/// Which top-level mutation fields are persisted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PersistMode {
+    /// Every mutation field is persisted.
+    #[default]
+    All,
+    /// Only fields annotated with `@persist` in the operation are persisted.
+    Directive,
+}
+
+/// What to do with a document defining several operations with the same name,
+/// which the GraphQL spec forbids.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateOperations {
+    /// Reject the request with a `DUPLICATE_OPERATION_NAME` error.
+    #[default]
+    Reject,
+    /// Persist only the first operation with a given name and log a warning.
+    FirstWins,
+}
+
+/// Suppresses persistence when the mutation's response value has `equals` at `path`,
+/// e.g. `{ path: "result.changed", equals: false }` for no-op updates.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct SkipCondition {
+    /// Mutation field the condition applies to; all mutations when omitted.
+    #[serde(default)]
+    pub field: Option<String>,
+    /// Dotted path into the mutation's response value.
+    pub path: String,
+    pub equals: Value,
+}
+
+impl SkipCondition {
+    fn matches(&self, call: &MutationCall, response_value: &Value) -> bool {
+        self.field
+            .as_ref()
+            .is_none_or(|field| field == &call.field_name)
+            && value_at_path(response_value, &self.path) == Some(&self.equals)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EnrichmentOptions {
+    pub skip_when: Vec<SkipCondition>,
+    /// Applied in order to each call with a response value.
+    pub enrichers: Vec<Arc<dyn Enricher>>,
+    /// Mutations whose scalar response is their aggregate id; they get no
+    /// `responseData`.
+    pub id_from_scalar_response: FieldPatterns,
+    pub dedup_within_batch: bool,
+    pub persist_without_data: bool,
+    pub partial_success_policy: PartialSuccessPolicy,
+    /// Leave the `responseData` argument out; the response is still used for ids
+    /// and `skip_when`.
+    pub omit_response_data: bool,
+    /// Response extension keys copied onto each call; `*` selects all of them.
+    pub capture_extensions: Vec<String>,
+}
+
+/// Where mutations are persisted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PrimarySink {
+    #[default]
+    Kurrent,
+    /// [`MemorySink::shared`], for local development and tests without KurrentDB.
+    Memory,
+}
+
+/// Whether persisted events carry the W3C `traceparent` of the request that
+/// produced them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceparentMode {
+    #[default]
+    Off,
+    /// The active span's context, or the incoming header; omitted without either.
+    Propagate,
+    /// Like `propagate`, but a new unsampled trace context is generated for
+    /// requests without one.
+    Generate,
+}
+
+/// What is persisted of a response carrying both `data` and `errors`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PartialSuccessPolicy {
+    /// Every call, as if the response were fully successful.
+    #[default]
+    PersistAll,
+    /// The calls no error's `path` points into.
+    PersistWithoutErroredFields,
+    /// Nothing.
+    Skip,
+}
+
+/// How integer arguments outside the range JSON consumers can represent exactly
+/// (±2^53 - 1, e.g. JavaScript numbers) are persisted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LargeIntegers {
+    /// As JSON numbers.
+    #[default]
+    Number,
+    /// As strings holding the literal digits.
+    String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtractionOptions {
+    pub persist_mode: PersistMode,
+    /// Names of the argument wrapping the mutation payload, tried in order.
+    pub input_argument_names: Vec<String>,
+    pub duplicate_operations: DuplicateOperations,
+    /// Set when enum values are persisted tagged as `{ "__enum": "ACTIVE" }`.
+    pub enum_tagging: Option<Arc<EnumTypes>>,
+    /// Input object field defaults of the supergraph, applied to absent fields.
+    pub input_defaults: Option<Arc<InputDefaults>>,
+    pub large_integers: LargeIntegers,
+    /// Drop top-level arguments that resolve to `null`, including absent variables.
+    pub skip_null_args: bool,
+    /// Operation types whose fields are captured.
+    pub operation_types: Vec<OperationType>,
+    /// Query fields captured when queries are enabled; all of them when `None`.
+    pub query_fields: Option<FieldPatterns>,
+    /// Argument paths a call must resolve to a non-null value to be persisted,
+    /// per field name.
+    pub require_args: HashMap<String, Vec<String>>,
+    pub scalar_coercions: ScalarCoercions,
+}
+
+impl Default for ExtractionOptions {
+    fn default() -> Self {
+        Self {
+            persist_mode: PersistMode::default(),
+            input_argument_names: default_input_argument_names(),
+            duplicate_operations: DuplicateOperations::default(),
+            enum_tagging: None,
+            input_defaults: None,
+            large_integers: LargeIntegers::default(),
+            skip_null_args: false,
+            operation_types: default_operation_types(),
+            query_fields: None,
+            require_args: HashMap::new(),
+            scalar_coercions: ScalarCoercions::default(),
+        }
+    }
+}
+
+impl ExtractionOptions {
+    fn value_conversion(&self) -> ValueConversion {
+        ValueConversion {
+            tag_enums: self.enum_tagging.is_some(),
+            large_integers: self.large_integers,
+        }
+    }
+}
+
+fn default_id_from_scalar_response() -> FieldPatterns {
+    FieldPatterns::compile(&["recordLoanRequested"]).expect("valid default pattern")
+}
+
+fn default_operation_types() -> Vec<OperationType> {
+    vec![OperationType::Mutation]
+}
+
+fn default_input_argument_names() -> Vec<String> {
+    vec!["input".to_string()]
+}
+
+fn default_scopes_claim() -> String {
+    "scope".to_string()
+}
+
+fn default_raw_query_max_bytes() -> usize {
+    16 * 1024
+}
+
+fn default_persist_response_data() -> bool {
+    true
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_sample_rate() -> f64 {
+    1.0
+}
+
+/// Context key under which the router's JWT authentication stores verified claims.
+const JWT_CLAIMS_CONTEXT_KEY: &str = "apollo::authentication::jwt_claims";
+
+/// Context key holding the id shared by every mutation persisted from one request.
+pub const CORRELATION_ID_CONTEXT_KEY: &str = "starstuff::correlation_id";
+
+/// Actor recorded for unauthenticated requests when `record_anonymous_actor` is set.
+const ANONYMOUS_ACTOR: &str = "anonymous";
+
+/// Which JWT claim identifies the actor, and what to record when it is missing.
+struct ActorCapture {
+    claim: String,
+    record_anonymous: bool,
+}
+
+impl ActorCapture {
+    fn resolve(&self, context: &Context) -> Option<String> {
+        actor_from_context(context, &self.claim)
+            .or_else(|| self.record_anonymous.then(|| ANONYMOUS_ACTOR.to_string()))
+    }
+}
+
+/// Distinct operation names labelling the extraction metrics; later ones are
+/// labelled `other`, to keep the metrics' cardinality bounded.
+const MAX_OPERATION_LABELS: usize = 100;
+
+/// Operation names seen so far as metric labels, up to [`MAX_OPERATION_LABELS`].
+#[derive(Default)]
+struct OperationLabels {
+    seen: std::sync::Mutex<HashSet<String>>,
+}
+
+impl OperationLabels {
+    fn label(&self, operation_name: Option<&str>) -> String {
+        let Some(name) = operation_name else {
+            return "anonymous".to_string();
+        };
+        let mut seen = self
+            .seen
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if seen.contains(name) || seen.len() < MAX_OPERATION_LABELS {
+            seen.insert(name.to_string());
+            name.to_string()
+        } else {
+            "other".to_string()
+        }
+    }
 
-fn default_message() -> String {
-    "starting my plugin".to_string()
+    /// Records how many calls a request yielded: the `kurrent_mutations_per_request`
+    /// histogram, or `kurrent_requests_without_mutations_total` for none.
+    fn record(&self, operation_name: Option<&str>, count: usize) {
+        let operation = self.label(operation_name);
+        if count == 0 {
+            tracing::debug!(
+                monotonic_counter.kurrent_requests_without_mutations_total = 1u64,
+                operation = %operation,
+                "No mutations in request"
+            );
+        } else {
+            tracing::debug!(
+                histogram.kurrent_mutations_per_request = count as u64,
+                operation = %operation,
+                "Extracted mutations from request"
+            );
+        }
+    }
+}
+
+/// Which request header names the tenant, and the tenant assumed when it is missing.
+struct TenantCapture {
+    header: String,
+    default: Option<String>,
+}
+
+impl TenantCapture {
+    fn resolve(&self, headers: &HeaderMap) -> Option<String> {
+        headers
+            .get(self.header.as_str())
+            .and_then(|value| value.to_str().ok())
+            .filter(|tenant| !tenant.is_empty())
+            .map(str::to_string)
+            .or_else(|| self.default.clone())
+    }
+}
+
+/// Where the application-level idempotency key of a call comes from: an argument
+/// path, else a request header.
+struct IdempotencyKeyCapture {
+    header: Option<String>,
+    argument: Option<String>,
+}
+
+impl IdempotencyKeyCapture {
+    fn resolve(&self, call: &MutationCall, headers: &HeaderMap) -> Option<String> {
+        self.argument
+            .as_deref()
+            .and_then(|argument| resolve_call_value(call, argument))
+            .or_else(|| {
+                headers
+                    .get(self.header.as_deref()?)
+                    .and_then(|value| value.to_str().ok())
+                    .filter(|key| !key.is_empty())
+                    .map(str::to_string)
+            })
+    }
+}
+
+/// The `names` headers present in `headers`, keyed by lowercase name. Repeated
+/// headers are joined with `, `; values that are not valid UTF-8 are skipped.
+fn response_headers_metadata(headers: &HeaderMap, names: &[String]) -> Option<Value> {
+    let captured: serde_json::Map<String, Value> = names
+        .iter()
+        .filter_map(|name| {
+            let values: Vec<&str> = headers
+                .get_all(name.as_str())
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect();
+            (!values.is_empty())
+                .then(|| (name.to_ascii_lowercase(), Value::from(values.join(", "))))
+        })
+        .collect();
+    (!captured.is_empty()).then_some(Value::Object(captured))
+}
+
+/// Whether `header` is present with a truthy value (`true`, `1` or `yes`).
+fn header_is_truthy(headers: &HeaderMap, header: &str) -> bool {
+    headers
+        .get(header)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            let value = value.trim();
+            ["true", "1", "yes"]
+                .iter()
+                .any(|truthy| value.eq_ignore_ascii_case(truthy))
+        })
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct PluginConfig {
-    #[serde(default = "default_message")]
-    pub message: String,
+    /// Reject mutation requests with a 503 while KurrentDB is unreachable, so the
+    /// router never acknowledges writes it cannot persist.
+    #[serde(default)]
+    pub fail_readiness_on_disconnect: bool,
+    /// Record the mutation's schema coordinate (e.g. `Mutation.recordCreditChecked`)
+    /// in the event metadata.
+    #[serde(default)]
+    pub persist_schema_coordinate: bool,
+    /// Record the subgraph resolving each root field, per the supergraph's join
+    /// directives, as `owning_subgraph` in the event metadata.
+    #[serde(default)]
+    pub record_owning_subgraph: bool,
+    #[serde(default)]
+    pub persist_mode: PersistMode,
+    /// Operation types to capture: `mutation`, and optionally `query` for read
+    /// auditing. Query fields are written as `GraphQL.Query.*` events to
+    /// `query_stream_prefix` streams.
+    #[serde(default = "default_operation_types")]
+    pub operation_types: Vec<OperationType>,
+    /// Query fields (glob or `/regex/` patterns) captured when `operation_types`
+    /// includes `query`; every query field when unset.
+    #[serde(default)]
+    #[schemars(with = "Option<Vec<String>>")]
+    pub query_fields: Option<FieldPatterns>,
+    #[serde(default)]
+    pub duplicate_operations: DuplicateOperations,
+    /// Persist enum values as `{ "__enum": "ACTIVE" }` instead of plain strings,
+    /// including enums passed through variables (resolved against the supergraph).
+    #[serde(default)]
+    pub tag_enums: bool,
+    /// Persist integers beyond ±2^53 - 1 as strings so consumers parsing JSON into
+    /// doubles do not lose precision.
+    #[serde(default)]
+    pub large_integers: LargeIntegers,
+    /// Leave arguments that resolve to `null` (including absent optional
+    /// variables) out of the persisted payload.
+    #[serde(default)]
+    pub skip_null_args: bool,
+    /// Arguments a call must carry to be persisted, per field name, e.g.
+    /// `recordCreditChecked: [input.Score]`. Checked after variables are resolved.
+    #[serde(default)]
+    pub require_args: HashMap<String, Vec<String>>,
+    /// Normalization of custom scalar arguments, by dotted argument path, e.g.
+    /// `input.CreditCheckedTimestamp: iso8601`.
+    #[serde(default)]
+    pub scalar_coercions: HashMap<String, ScalarCoercion>,
+    /// What happens to argument values their scalar coercion rejects.
+    #[serde(default)]
+    pub invalid_scalars: InvalidScalarPolicy,
+    /// Argument names that wrap the mutation payload (e.g. `input`, `data`,
+    /// `payload`), tried in order when extracting the aggregate id.
+    #[serde(default = "default_input_argument_names")]
+    pub input_argument_names: Vec<String>,
+    /// Copy the authorization scopes from the authenticated JWT into the event
+    /// metadata as `scopes`.
+    #[serde(default)]
+    pub capture_scopes: bool,
+    /// JWT claim holding the scopes, either a space-separated string or a list.
+    #[serde(default = "default_scopes_claim")]
+    pub scopes_claim: String,
+    /// Store the operation text and variables as `raw_query` / `raw_variables` in
+    /// the event metadata, for debugging and replay.
+    #[serde(default)]
+    pub store_raw_query: bool,
+    /// Queries longer than this are truncated (`raw_query_truncated: true`);
+    /// variables longer than this are not stored.
+    #[serde(default = "default_raw_query_max_bytes")]
+    pub raw_query_max_bytes: usize,
+    /// Store the request's variables as `variables` in the event metadata, so
+    /// variable-provided values can be told apart from literals. Skipped when
+    /// larger than `max_event_bytes`.
+    #[serde(default)]
+    pub store_variables: bool,
+    /// Record in the event metadata whether the originating trace was sampled.
+    #[serde(default)]
+    pub record_sampled: bool,
+    /// Record the request's W3C `traceparent` in the event metadata.
+    #[serde(default)]
+    pub traceparent: TraceparentMode,
+    /// JWT claim (e.g. `sub` or `email`) recorded as the mutation's `actor`.
+    #[serde(default)]
+    pub actor_claim: Option<String>,
+    /// Record `"anonymous"` as the actor when the claim is absent, instead of
+    /// leaving it unset.
+    #[serde(default)]
+    pub record_anonymous_actor: bool,
+    /// Request header (e.g. `x-tenant-id`) naming the tenant whose streams the
+    /// mutations are written to.
+    #[serde(default)]
+    pub tenant_header: Option<String>,
+    /// Tenant used when `tenant_header` is missing. Without one, such requests are
+    /// not persisted.
+    #[serde(default)]
+    pub default_tenant: Option<String>,
+    /// Request header (e.g. `idempotency-key`) carrying a client-chosen idempotency
+    /// key, recorded as `idempotency_key` in the event metadata.
+    #[serde(default)]
+    pub idempotency_key_header: Option<String>,
+    /// Argument path (e.g. `input.requestId`) of the idempotency key; takes
+    /// precedence over `idempotency_key_header`.
+    #[serde(default)]
+    pub idempotency_key_argument: Option<String>,
+    /// Request header (e.g. `x-causation-id`) carrying the id of the command or
+    /// event that triggered the request, recorded as the `$causationId` of its
+    /// first mutation. Without it, the first mutation is caused by the request's
+    /// correlation id.
+    #[serde(default)]
+    pub causation_id_header: Option<String>,
+    /// Request header (e.g. `x-kurrent-skip`) with which a client opts a request out
+    /// of persistence, e.g. when replaying traffic.
+    #[serde(default)]
+    pub skip_header: Option<String>,
+    /// Response conditions under which a mutation is not persisted.
+    #[serde(default)]
+    pub skip_when: Vec<SkipCondition>,
+    /// Mutations (glob or `/regex/` patterns) whose scalar response is the new
+    /// aggregate id, recorded as the call's `loan_id`.
+    #[serde(default = "default_id_from_scalar_response")]
+    #[schemars(with = "Vec<String>")]
+    pub id_from_scalar_response: FieldPatterns,
+    /// Dotted path to the new aggregate id within a mutation's response object,
+    /// per mutation field, e.g. `createLoan: loan.id`.
+    #[serde(default)]
+    pub id_from_response: HashMap<String, String>,
+    /// Collapse calls of one operation with identical field, arguments (including
+    /// the response data) and aggregate id into a single event.
+    #[serde(default)]
+    pub dedup_within_batch: bool,
+    /// Persist the calls as extracted (no response enrichment) when a response
+    /// carries neither data nor errors, instead of skipping them.
+    #[serde(default)]
+    pub persist_without_data: bool,
+    /// What to persist when a response carries both `data` and `errors`.
+    #[serde(default)]
+    pub partial_success_policy: PartialSuccessPolicy,
+    /// Add each mutation's response value to its arguments as `responseData`.
+    #[serde(default = "default_persist_response_data")]
+    pub persist_response_data: bool,
+    /// Response `extensions` keys to persist with each mutation, or `["*"]` for
+    /// all of them.
+    #[serde(default)]
+    pub capture_extensions: Vec<String>,
+    /// Subgraphs whose mutation fetches are also persisted, as
+    /// `GraphQL.Subgraph.<subgraph>.<field>` events with the subgraph's response.
+    #[serde(default)]
+    pub capture_subgraphs: HashSet<String>,
+    /// Response headers (e.g. `etag`) recorded as `response_headers` in the event
+    /// metadata. Headers missing from a response are left out.
+    #[serde(default)]
+    pub capture_response_headers: Vec<String>,
+    /// Operational kill-switch. When `false`, mutations are still detected, logged
+    /// and counted, but nothing is written to any sink and no KurrentDB connection
+    /// is made.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// `kurrent`, or `memory` to record mutations in memory (and the log) only.
+    #[serde(default)]
+    pub sink: PrimarySink,
+    /// Sinks every batch is also written to, e.g. a second cluster during a
+    /// migration. Their failures never affect the primary connection.
+    #[serde(default)]
+    pub additional_sinks: Vec<SinkDefinition>,
+    /// Values masked or replaced by a salted hash before any sink sees them.
+    #[serde(default)]
+    pub redaction: RedactionConfig,
+    /// Fraction (0.0–1.0) of mutations persisted, e.g. to keep load tests from
+    /// flooding KurrentDB.
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: f64,
+    /// Sample whole requests by correlation id instead of individual mutations.
+    #[serde(default)]
+    pub sample_by_correlation_id: bool,
+    /// Await the append before the response is forwarded to the client, so a
+    /// client that saw the mutation succeed can read its event.
+    #[serde(default)]
+    pub persist_before_response: bool,
+    /// Forward the response anyway when the append takes longer than this; it
+    /// still completes in the background. Unset waits for the append.
+    #[serde(default)]
+    pub persist_before_response_timeout_ms: Option<u64>,
     #[serde(flatten)]
     pub kurrent: KurrentConfig,
 }
 
+impl PluginConfig {
+    /// The options `extract_mutations` runs with for this configuration.
+    pub fn extraction_options(&self, supergraph_sdl: &str) -> ExtractionOptions {
+        ExtractionOptions {
+            persist_mode: self.persist_mode,
+            input_argument_names: self.input_argument_names.clone(),
+            duplicate_operations: self.duplicate_operations,
+            enum_tagging: self
+                .tag_enums
+                .then(|| Arc::new(EnumTypes::from_sdl(supergraph_sdl))),
+            input_defaults: Some(Arc::new(InputDefaults::from_sdl(
+                supergraph_sdl,
+                self.tag_enums,
+            ))),
+            large_integers: self.large_integers,
+            skip_null_args: self.skip_null_args,
+            operation_types: self.operation_types.clone(),
+            query_fields: self.query_fields.clone(),
+            require_args: self.require_args.clone(),
+            scalar_coercions: ScalarCoercions {
+                coercions: self.scalar_coercions.clone(),
+                on_invalid: self.invalid_scalars,
+            },
+        }
+    }
+}
+
 pub struct MutationInterceptor {
     mutation_sink: Arc<dyn MutationSink>,
+    fail_readiness_on_disconnect: bool,
+    mutation_schema: Option<Arc<MutationSchema>>,
+    subgraph_owners: Option<Arc<SubgraphOwners>>,
+    operation_labels: Arc<OperationLabels>,
+    extraction: Arc<ExtractionOptions>,
+    scopes_claim: Option<String>,
+    actor: Option<Arc<ActorCapture>>,
+    tenant: Option<Arc<TenantCapture>>,
+    idempotency_key: Option<Arc<IdempotencyKeyCapture>>,
+    causation_id_header: Option<String>,
+    skip_header: Option<String>,
+    record_sampled: bool,
+    traceparent: TraceparentMode,
+    raw_query_max_bytes: Option<usize>,
+    /// `Some(max_event_bytes)` when `store_variables` is set.
+    variables_max_bytes: Option<Option<usize>>,
+    enrichment: Arc<EnrichmentOptions>,
+    capture_subgraphs: HashSet<String>,
+    capture_response_headers: Arc<Vec<String>>,
+    /// `Some(timeout)` when `persist_before_response` is set.
+    persist_before_response: Option<Option<Duration>>,
 }
 
 #[async_trait::async_trait]
@@ -42,32 +621,210 @@ impl Plugin for MutationInterceptor {
     where
         Self: Sized,
     {
-        let service = Arc::new(KurrentService::new(init.config.kurrent).await?);
-        let sink: Arc<dyn MutationSink> = service;
+        if !init.config.enabled {
+            tracing::warn!(
+                plugin = PLUGIN_NAME,
+                "Persistence is disabled; mutations are detected and counted, nothing is written"
+            );
+            return Ok(Self::from_parts(
+                Arc::new(DisabledSink),
+                &init.config,
+                &init.supergraph_sdl,
+            ));
+        }
+
+        let (name, primary): (&str, Arc<dyn MutationSink>) = match init.config.sink {
+            PrimarySink::Kurrent => (
+                "kurrent",
+                Arc::new(KurrentService::new(init.config.kurrent.clone()).await?),
+            ),
+            PrimarySink::Memory => {
+                tracing::warn!(
+                    "Recording mutations in memory only, nothing is persisted to KurrentDB"
+                );
+                ("memory", MemorySink::shared())
+            }
+        };
+        let sink: Arc<dyn MutationSink> = if init.config.additional_sinks.is_empty() {
+            primary
+        } else {
+            let mut sinks: Vec<(String, Arc<dyn MutationSink>)> = vec![(name.to_string(), primary)];
+            for definition in &init.config.additional_sinks {
+                sinks.push((definition.name(), definition.build().await?));
+            }
+            Arc::new(CompositeSink::new(sinks))
+        };
 
-        tracing::info!(message = %init.config.message, "starstuff.mutation_plugin initialized with KurrentService");
+        tracing::info!(plugin = PLUGIN_NAME, sink = name, "Plugin initialized");
 
-        Ok(Self {
-            mutation_sink: sink,
-        })
+        Ok(Self::from_parts(sink, &init.config, &init.supergraph_sdl))
     }
 
     fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
         let mutation_sink = self.mutation_sink.clone();
+        let readiness_sink = self.mutation_sink.clone();
+        let fail_readiness_on_disconnect = self.fail_readiness_on_disconnect;
+        let mutation_schema = self.mutation_schema.clone();
+        let subgraph_owners = self.subgraph_owners.clone();
+        let operation_labels = self.operation_labels.clone();
+        let extraction = self.extraction.clone();
+        let scopes_claim = self.scopes_claim.clone();
+        let actor = self.actor.clone();
+        let tenant = self.tenant.clone();
+        let idempotency_key = self.idempotency_key.clone();
+        let causation_id_header = self.causation_id_header.clone();
+        let skip_header = self.skip_header.clone();
+        let record_sampled = self.record_sampled;
+        let traceparent_mode = self.traceparent;
+        let raw_query_max_bytes = self.raw_query_max_bytes;
+        let variables_max_bytes = self.variables_max_bytes;
+        let enrichment = self.enrichment.clone();
+        let persist_before_response = self.persist_before_response;
+        let capture_response_headers = self.capture_response_headers.clone();
 
         ServiceBuilder::new()
-            .map_request(move |req: supergraph::Request| {
+            .instrument(|_: &supergraph::Request| tracing::info_span!("mutation_plugin", plugin = PLUGIN_NAME))
+            .checkpoint(move |req: supergraph::Request| {
+                if let Some(header) = skip_header.as_deref()
+                    && header_is_truthy(req.supergraph_request.headers(), header)
+                {
+                    tracing::debug!(header = %header, "Request opted out of persistence");
+                    return Ok(ControlFlow::Continue(req));
+                }
+
                 let gql_req = req.supergraph_request.body();
 
                 if let Some(query) = gql_req.query.as_ref() {
-                    let calls = extract_mutations(query, &gql_req.variables);
+                    let document = parse_operation(query);
+
+                    if extraction.duplicate_operations == DuplicateOperations::Reject {
+                        let duplicates = duplicate_operation_names_in(&document);
+                        if !duplicates.is_empty() {
+                            tracing::warn!(operations = ?duplicates, "Rejecting document with duplicate operation names");
+                            return Ok(ControlFlow::Break(duplicate_operation_response(req.context, &duplicates)?));
+                        }
+                    }
+
+                    let mut calls = extract_document_mutations(
+                        &document,
+                        gql_req.operation_name.as_deref(),
+                        &gql_req.variables,
+                        &extraction,
+                    );
+                    operation_labels.record(
+                        calls
+                            .first()
+                            .and_then(|call| call.operation_name.as_deref())
+                            .or(gql_req.operation_name.as_deref()),
+                        calls.len(),
+                    );
                     if !calls.is_empty() {
+                        if let Some(schema) = mutation_schema.as_ref() {
+                            calls
+                                .iter_mut()
+                                .filter(|call| call.operation_type.is_mutation())
+                                .for_each(|call| schema.annotate(call));
+                        }
+
+                        if let Some(owners) = subgraph_owners.as_ref() {
+                            for call in calls.iter_mut() {
+                                match owners.owner(call.operation_type, &call.field_name) {
+                                    Some(owner) => {
+                                        call.metadata.insert("owning_subgraph".to_string(), Value::from(owner));
+                                    }
+                                    None => tracing::debug!(field = %call.field_name, "No single subgraph owns this root field"),
+                                }
+                            }
+                        }
+
+                        if let Some(claim) = scopes_claim.as_deref() {
+                            match scopes_from_context(&req.context, claim) {
+                                Some(scopes) => calls.iter_mut().for_each(|call| {
+                                    call.metadata.insert("scopes".to_string(), Value::from(scopes.clone()));
+                                }),
+                                None => tracing::debug!(claim = %claim, "No authorization scopes found in request context"),
+                            }
+                        }
+
+                        if let Some(actor) = actor.as_deref() {
+                            let resolved = actor.resolve(&req.context);
+                            calls.iter_mut().for_each(|call| call.actor = resolved.clone());
+                        }
+
+                        if let Some(tenant) = tenant.as_deref() {
+                            match tenant.resolve(req.supergraph_request.headers()) {
+                                Some(resolved) => calls.iter_mut().for_each(|call| call.tenant = Some(resolved.clone())),
+                                None => {
+                                    tracing::warn!(header = %tenant.header, count = calls.len(), "Request has no tenant, not persisting mutation(s)");
+                                    return Ok(ControlFlow::Continue(req));
+                                }
+                            }
+                        }
+
+                        if let Some(capture) = idempotency_key.as_deref() {
+                            let headers = req.supergraph_request.headers();
+                            for call in calls.iter_mut() {
+                                if let Some(key) = capture.resolve(call, headers) {
+                                    call.metadata.insert(IDEMPOTENCY_KEY_METADATA_KEY.to_string(), Value::from(key));
+                                }
+                            }
+                        }
+
+                        if record_sampled && let Some(sampled) = trace_sampled(req.supergraph_request.headers()) {
+                            calls.iter_mut().for_each(|call| {
+                                call.metadata.insert("sampled".to_string(), Value::Bool(sampled));
+                            });
+                        }
+
+                        if let Some(traceparent) = traceparent(req.supergraph_request.headers(), traceparent_mode) {
+                            calls.iter_mut().for_each(|call| {
+                                call.metadata.insert("traceparent".to_string(), Value::from(traceparent.clone()));
+                            });
+                        }
+
+                        if let Some(max_bytes) = raw_query_max_bytes {
+                            let raw = raw_query_metadata(query, &gql_req.variables, max_bytes);
+                            calls.iter_mut().for_each(|call| call.metadata.extend(raw.clone()));
+                        }
+
+                        if let Some(max_bytes) = variables_max_bytes
+                            && let Some(variables) = variables_metadata(&gql_req.variables, max_bytes)
+                        {
+                            calls.iter_mut().for_each(|call| {
+                                call.metadata.insert("variables".to_string(), variables.clone());
+                            });
+                        }
+
+                        let correlation_id = Uuid::new_v4().to_string();
+                        let total = calls.len();
+                        calls.iter_mut().for_each(|call| {
+                            call.metadata.insert("total".to_string(), Value::from(total));
+                        });
+                        let causation_id = causation_id_header
+                            .as_deref()
+                            .and_then(|header| req.supergraph_request.headers().get(header))
+                            .and_then(|value| value.to_str().ok())
+                            .filter(|value| !value.is_empty());
+                        chain_causation(&mut calls, &correlation_id, causation_id);
+                        stash_correlation_id(&req.context, correlation_id);
+
                         tracing::info!(mutations = ?calls, count = calls.len(), "Detected GraphQL mutation(s) in request");
-                        req.context.insert("pending_mutations", calls).unwrap();
+
+                        if fail_readiness_on_disconnect && !readiness_sink.is_healthy() {
+                            tracing::warn!(count = calls.len(), "Rejecting mutation(s) because KurrentDB is unreachable");
+                            return Ok(ControlFlow::Break(kurrent_unavailable_response(req.context)?));
+                        }
+
+                        if !readiness_sink.has_capacity() {
+                            tracing::warn!(count = calls.len(), "Rejecting mutation(s) because the persist queue is full");
+                            return Ok(ControlFlow::Break(queue_full_response(req.context)?));
+                        }
+
+                        stash_pending_mutations(&req.context, calls);
                     }
                 }
 
-                req
+                Ok(ControlFlow::Continue(req))
             })
             .map_future_with_request_data(
                 |req: &supergraph::Request| {
@@ -81,30 +838,39 @@ impl Plugin for MutationInterceptor {
                 },
                 move |pending_calls: Option<Vec<MutationCall>>, fut| {
                     let mutation_sink = mutation_sink.clone();
+                    let enrichment = enrichment.clone();
+                    let capture_response_headers = capture_response_headers.clone();
                     async move {
                         let mut res: supergraph::Response = fut.await?;
 
-                        if let Some(calls) = pending_calls {
+                        if let Some(mut calls) = pending_calls {
+                            if let Some(headers) = response_headers_metadata(res.response.headers(), &capture_response_headers) {
+                                calls.iter_mut().for_each(|call| {
+                                    call.metadata.insert("response_headers".to_string(), headers.clone());
+                                });
+                            }
+
                             let old_body = std::mem::replace(
                                 res.response.body_mut(),
                                 Box::pin(futures::stream::empty())
                             );
 
-                            let mapped_stream = old_body.map(move |graphql_response| {
-                                if let Some(data) = graphql_response.data.as_ref() {
-                                    let enriched_calls = enrich_mutations_with_response(calls.clone(), data);
-
-                                    tracing::info!(
-                                        mutations = ?enriched_calls,
-                                        count = enriched_calls.len(),
-                                        "Persisting successful mutation(s) with response data"
-                                    );
-
-                                    mutation_sink.persist_mutations(enriched_calls);
-                                } else if graphql_response.errors.is_empty() {
-                                    tracing::warn!("Mutation completed but no data in response, skipping persistence");
+                            let mut deferred = DeferredResponse::default();
+                            let mapped_stream = old_body.then(move |graphql_response| {
+                                let persisting = deferred
+                                    .complete(&graphql_response)
+                                    .and_then(|complete| calls_to_persist(&calls, &complete, &enrichment));
+                                let mutation_sink = mutation_sink.clone();
+                                async move {
+                                    match (persisting, persist_before_response) {
+                                        (Some(calls), Some(timeout)) => {
+                                            persist_before_forwarding(mutation_sink, calls, timeout).await;
+                                        }
+                                        (Some(calls), None) => mutation_sink.persist_mutations(calls),
+                                        (None, _) => {}
+                                    }
+                                    graphql_response
                                 }
-                                graphql_response
                             });
 
                             *res.response.body_mut() = Box::pin(mapped_stream);
@@ -118,536 +884,3771 @@ impl Plugin for MutationInterceptor {
             .boxed()
     }
 
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        if !self.capture_subgraphs.contains(name) {
+            return service;
+        }
+
+        let subgraph_name = name.to_string();
+        let mutation_sink = self.mutation_sink.clone();
+        let extraction = self.extraction.clone();
+        let enrichment = self.enrichment.clone();
+
+        ServiceBuilder::new()
+            .map_future_with_request_data(
+                move |req: &subgraph::Request| subgraph_calls(req, &subgraph_name, &extraction),
+                move |calls: Vec<MutationCall>, fut| {
+                    let mutation_sink = mutation_sink.clone();
+                    let enrichment = enrichment.clone();
+                    async move {
+                        let res: subgraph::Response = fut.await?;
+
+                        if !calls.is_empty() {
+                            let body = res.response.body();
+                            match body.data.as_ref() {
+                                Some(data) => {
+                                    let calls = enrich_mutations_with_response(
+                                        calls,
+                                        data,
+                                        &body.extensions,
+                                        &enrichment,
+                                    );
+                                    if !calls.is_empty() {
+                                        tracing::info!(
+                                            count = calls.len(),
+                                            "Persisting subgraph mutation(s) with response data"
+                                        );
+                                        mutation_sink.persist_mutations(calls);
+                                    }
+                                }
+                                None => tracing::warn!(
+                                    count = calls.len(),
+                                    "Subgraph mutation returned no data, skipping persistence"
+                                ),
+                            }
+                        }
+
+                        Ok(res)
+                    }
+                },
+            )
+            .service(service)
+            .boxed()
+    }
+
     fn name(&self) -> &'static str
     where
         Self: Sized,
     {
-        "hello_world"
+        PLUGIN_NAME
     }
 }
 
 impl MutationInterceptor {
-    #[cfg(test)]
-    pub fn with_sink(sink: Arc<dyn MutationSink>) -> Self {
+    fn from_parts(
+        sink: Arc<dyn MutationSink>,
+        config: &PluginConfig,
+        supergraph_sdl: &str,
+    ) -> Self {
+        let sink: Arc<dyn MutationSink> = if config.enabled {
+            sink
+        } else {
+            Arc::new(DisabledSink)
+        };
+        let sink: Arc<dyn MutationSink> = if config.redaction.is_empty() {
+            sink
+        } else {
+            Arc::new(RedactingSink::new(sink, config.redaction.clone()))
+        };
+        let sink: Arc<dyn MutationSink> = if config.sample_rate < 1.0 {
+            let sampler = Sampler::new(config.sample_rate, config.sample_by_correlation_id);
+            Arc::new(SampledSink::new(sink, sampler))
+        } else {
+            sink
+        };
+
         Self {
             mutation_sink: sink,
+            fail_readiness_on_disconnect: config.fail_readiness_on_disconnect,
+            mutation_schema: config
+                .persist_schema_coordinate
+                .then(|| Arc::new(MutationSchema::from_sdl(supergraph_sdl))),
+            subgraph_owners: config
+                .record_owning_subgraph
+                .then(|| Arc::new(SubgraphOwners::from_sdl(supergraph_sdl))),
+            operation_labels: Arc::default(),
+            extraction: Arc::new(config.extraction_options(supergraph_sdl)),
+            scopes_claim: config.capture_scopes.then(|| config.scopes_claim.clone()),
+            actor: config.actor_claim.as_ref().map(|claim| {
+                Arc::new(ActorCapture {
+                    claim: claim.clone(),
+                    record_anonymous: config.record_anonymous_actor,
+                })
+            }),
+            tenant: config.tenant_header.as_ref().map(|header| {
+                Arc::new(TenantCapture {
+                    header: header.clone(),
+                    default: config.default_tenant.clone(),
+                })
+            }),
+            idempotency_key: (config.idempotency_key_header.is_some()
+                || config.idempotency_key_argument.is_some())
+            .then(|| {
+                Arc::new(IdempotencyKeyCapture {
+                    header: config.idempotency_key_header.clone(),
+                    argument: config.idempotency_key_argument.clone(),
+                })
+            }),
+            causation_id_header: config.causation_id_header.clone(),
+            skip_header: config.skip_header.clone(),
+            record_sampled: config.record_sampled,
+            traceparent: config.traceparent,
+            raw_query_max_bytes: config.store_raw_query.then_some(config.raw_query_max_bytes),
+            variables_max_bytes: config
+                .store_variables
+                .then_some(config.kurrent.max_event_bytes),
+            enrichment: Arc::new(EnrichmentOptions {
+                skip_when: config.skip_when.clone(),
+                enrichers: vec![Arc::new(ResponseIdEnricher {
+                    id_from_scalar_response: config.id_from_scalar_response.clone(),
+                    id_from_response: config.id_from_response.clone(),
+                })],
+                id_from_scalar_response: config.id_from_scalar_response.clone(),
+                dedup_within_batch: config.dedup_within_batch,
+                persist_without_data: config.persist_without_data,
+                partial_success_policy: config.partial_success_policy,
+                omit_response_data: !config.persist_response_data,
+                capture_extensions: config.capture_extensions.clone(),
+            }),
+            capture_subgraphs: config.capture_subgraphs.clone(),
+            capture_response_headers: Arc::new(config.capture_response_headers.clone()),
+            persist_before_response: config.persist_before_response.then(|| {
+                config
+                    .persist_before_response_timeout_ms
+                    .map(Duration::from_millis)
+            }),
         }
     }
-}
-
-use serde_json::Value;
-use serde_json_bytes::{ByteString, Map as BytesMap, Value as BytesValue};
 
-fn ast_value_to_json(value: &ASTValue, vars: &BytesMap<ByteString, BytesValue>) -> Option<Value> {
-    match value {
-        StringValue(s) => serde_json::from_str(&s.syntax().text().to_string()).ok(),
-        IntValue(i) => serde_json::from_str(&i.syntax().text().to_string()).ok(),
-        FloatValue(f) => serde_json::from_str(&f.syntax().text().to_string()).ok(),
-        BooleanValue(b) => serde_json::from_str(&b.syntax().text().to_string()).ok(),
-        NullValue(_) => Some(Value::Null),
-        EnumValue(e) => Some(Value::String(e.syntax().text().to_string())),
-        Variable(var) => {
-            let name = var.name()?.text();
-            let v = vars.get(name.as_str())?;
-            Some(serde_json::to_value(v.clone()).unwrap())
-        }
-        ListValue(list) => {
-            let mut arr = Vec::new();
-            for v in list.values() {
-                arr.push(ast_value_to_json(&v, vars).unwrap_or(Value::Null));
-            }
-            Some(Value::Array(arr))
-        }
-        ObjectValue(obj) => {
-            let mut map = serde_json::Map::new();
-            for field in obj.object_fields() {
-                let name = field.name()?.text().to_string();
-                let val = field.value()?;
-                map.insert(name, ast_value_to_json(&val, vars).unwrap_or(Value::Null));
-            }
-            Some(Value::Object(map))
-        }
+    /// Adds an enricher, run after the ones already registered (the built-in
+    /// [`ResponseIdEnricher`] first).
+    pub fn with_enricher(mut self, enricher: Arc<dyn Enricher>) -> Self {
+        Arc::make_mut(&mut self.enrichment).enrichers.push(enricher);
+        self
     }
-}
 
-fn collect_top_level_response_field_names(selection_set: Option<SelectionSet>) -> Vec<String> {
-    let mut out = Vec::new();
-    if let Some(selections) = selection_set {
-        for selection in selections.selections() {
-            if let Selection::Field(field) = selection {
-                let name = field
-                    .alias()
-                    .and_then(|a| a.name().map(|n| n.text().to_string()))
-                    .or_else(|| field.name().map(|n| n.text().to_string()));
-                if let Some(n) = name {
-                    out.push(n);
-                }
-            }
-        }
+    #[cfg(test)]
+    pub fn with_sink(sink: Arc<dyn MutationSink>) -> Self {
+        Self::with_sink_and_config(sink, serde_json::from_value(serde_json::json!({})).unwrap())
     }
-    out
-}
 
-fn collect_args(
-    field: &apollo_parser::cst::Field,
-    vars: &BytesMap<ByteString, BytesValue>,
-) -> Vec<MutationArg> {
-    let mut args = Vec::new();
-    if let Some(arguments) = field.arguments() {
-        for a in arguments.arguments() {
-            let name = a.name().map(|n| n.text().to_string()).unwrap_or_default();
-            let val = a
-                .value()
-                .and_then(|v| ast_value_to_json(&v, vars))
-                .unwrap_or(Value::Null);
-            args.push(MutationArg { name, value: val });
-        }
+    #[cfg(test)]
+    pub fn with_sink_and_config(sink: Arc<dyn MutationSink>, config: PluginConfig) -> Self {
+        Self::from_parts(
+            sink,
+            &config,
+            include_str!("../../supergraph-schema.graphql"),
+        )
     }
-    args
 }
 
-fn extract_loan_id_from_args(arguments: &[MutationArg]) -> Option<String> {
-    // Look for an "input" argument
-    arguments
-        .iter()
-        .find(|arg| arg.name == "input")
-        .and_then(|input_arg| {
-            // Check if the input value is an object with a "loanId" field
-            input_arg
-                .value
-                .get("loanId")
-                .and_then(|loan_id_value| loan_id_value.as_str().map(|s| s.to_string()))
-        })
+/// Assigns each of a request's calls, in document order, its event id and
+/// records KurrentDB's `$correlationId` and `$causationId`: every call is caused
+/// by the one before it, the first by `causation_id` or else the correlation id.
+fn chain_causation(calls: &mut [MutationCall], correlation_id: &str, causation_id: Option<&str>) {
+    let mut cause = causation_id.unwrap_or(correlation_id).to_string();
+    for call in calls.iter_mut() {
+        let event_id = Uuid::new_v4().to_string();
+        call.metadata.insert(
+            CORRELATION_ID_METADATA_KEY.to_string(),
+            Value::from(correlation_id),
+        );
+        call.metadata.insert(
+            CAUSATION_ID_METADATA_KEY.to_string(),
+            Value::from(std::mem::replace(&mut cause, event_id.clone())),
+        );
+        call.metadata
+            .insert(EVENT_ID_METADATA_KEY.to_string(), Value::from(event_id));
+    }
 }
 
-fn enrich_mutations_with_response(
-    mut calls: Vec<MutationCall>,
-    response_data: &serde_json_bytes::Value,
+/// Mutations of a subgraph fetch, tagged with the subgraph and the correlation id
+/// of the client request that caused it.
+fn subgraph_calls(
+    req: &subgraph::Request,
+    subgraph_name: &str,
+    extraction: &ExtractionOptions,
 ) -> Vec<MutationCall> {
-    let data_json = match serde_json::to_value(response_data) {
-        Ok(v) => v,
-        Err(e) => {
-            tracing::error!(error = %e, "Failed to convert response data to JSON");
-            return calls;
-        }
+    let body = req.subgraph_request.body();
+    let Some(query) = body.query.as_deref() else {
+        return Vec::new();
     };
 
+    let mut calls = extract_operation_mutations(
+        query,
+        body.operation_name.as_deref(),
+        &body.variables,
+        extraction,
+    );
+    let correlation_id = req
+        .context
+        .get::<_, String>(CORRELATION_ID_CONTEXT_KEY)
+        .ok()
+        .flatten();
     for call in calls.iter_mut() {
-        let response_value = if let Some(alias) = &call.alias {
-            data_json.get(alias)
-        } else {
-            data_json.get(&call.field_name)
-        };
-
-        if let Some(value) = response_value {
-            if call.field_name == "recordLoanRequested" {
-                if let Some(loan_id) = value.as_str() {
-                    call.loan_id = Some(loan_id.to_string());
-                    tracing::debug!(loan_id = %loan_id, mutation = %call.field_name, "Extracted loanId from response");
-                }
-            } else {
-                call.arguments.push(MutationArg {
-                    name: "responseData".to_string(),
-                    value: value.clone(),
-                });
-            }
+        call.subgraph = Some(subgraph_name.to_string());
+        if let Some(correlation_id) = &correlation_id {
+            call.metadata.insert(
+                CORRELATION_ID_METADATA_KEY.to_string(),
+                Value::from(correlation_id.clone()),
+            );
         }
     }
-
     calls
 }
 
-pub fn extract_mutations(
+fn scopes_from_context(context: &Context, claim: &str) -> Option<Vec<String>> {
+    let claims = context
+        .get::<_, Value>(JWT_CLAIMS_CONTEXT_KEY)
+        .ok()
+        .flatten()?;
+
+    match claims.get(claim)? {
+        Value::String(scopes) => Some(scopes.split_whitespace().map(str::to_string).collect()),
+        Value::Array(scopes) => Some(
+            scopes
+                .iter()
+                .filter_map(|scope| scope.as_str().map(str::to_string))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+fn actor_from_context(context: &Context, claim: &str) -> Option<String> {
+    let claims = context
+        .get::<_, Value>(JWT_CLAIMS_CONTEXT_KEY)
+        .ok()
+        .flatten()?;
+
+    match claims.get(claim)? {
+        Value::String(actor) => Some(actor.clone()),
+        Value::Number(actor) => Some(actor.to_string()),
+        _ => None,
+    }
+}
+
+/// Operation text and variables as sent by the client, bounded by `max_bytes`.
+fn raw_query_metadata(
     query: &str,
     variables: &BytesMap<ByteString, BytesValue>,
-) -> Vec<MutationCall> {
-    let ast = Parser::new(query).parse();
-    let doc = ast.document();
-    let mut calls = Vec::new();
+    max_bytes: usize,
+) -> serde_json::Map<String, Value> {
+    let mut metadata = serde_json::Map::new();
 
-    for def in doc.definitions() {
-        if let Definition::OperationDefinition(op) = def {
-            if let Some(op_type) = op.operation_type() {
-                if op_type.mutation_token().is_some() {
-                    let op_name = op.name().map(|n| n.text().to_string());
-                    if let Some(sel_set) = op.selection_set() {
-                        for selection in sel_set.selections() {
-                            if let Selection::Field(field) = selection {
-                                let field_name = field
-                                    .name()
-                                    .map(|n| n.text().to_string())
-                                    .unwrap_or_default();
-                                let alias = field
-                                    .alias()
-                                    .and_then(|a| a.name().map(|n| n.text().to_string()));
-                                let arguments = collect_args(&field, variables);
-
-                                // Extract loanId from input arguments if present
-                                let loan_id = extract_loan_id_from_args(&arguments);
-
-                                let selected_fields =
-                                    collect_top_level_response_field_names(field.selection_set());
-                                calls.push(MutationCall {
-                                    operation_name: op_name.clone(),
-                                    field_name,
-                                    loan_id,
-                                    alias,
-                                    arguments,
-                                    selected_fields,
-                                });
-                            }
-                        }
-                    }
-                }
+    if query.len() > max_bytes {
+        let mut end = max_bytes;
+        while !query.is_char_boundary(end) {
+            end -= 1;
+        }
+        metadata.insert("raw_query".to_string(), Value::from(&query[..end]));
+        metadata.insert("raw_query_truncated".to_string(), Value::Bool(true));
+    } else {
+        metadata.insert("raw_query".to_string(), Value::from(query));
+    }
+
+    if !variables.is_empty() {
+        match serde_json::to_value(variables) {
+            Ok(raw) if raw.to_string().len() <= max_bytes => {
+                metadata.insert("raw_variables".to_string(), raw);
             }
+            Ok(_) => tracing::debug!(
+                max_bytes,
+                "Variables exceed raw_query_max_bytes, not storing them"
+            ),
+            Err(e) => tracing::warn!(error = %e, "Failed to serialize variables for raw_variables"),
         }
     }
 
-    calls
+    metadata
 }
-apollo_router::register_plugin!("starstuff", "mutation_plugin", MutationInterceptor);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use apollo_router::plugin::{Plugin, test};
-    use apollo_router::services::supergraph;
-    use serde_json::json;
-    use serde_json_bytes::{ByteString, Map as BytesMap};
-    use std::sync::{Arc as StdArc, Mutex};
+/// Sampling decision of the active span, falling back to the flags of the incoming
+/// W3C `traceparent` header when no span context is active.
+fn trace_sampled(headers: &HeaderMap) -> Option<bool> {
+    let context = opentelemetry::Context::current();
+    let span_context = context.span().span_context().clone();
+    if span_context.is_valid() {
+        return Some(span_context.is_sampled());
+    }
 
-    #[derive(Default)]
-    struct MockMutationSink {
-        calls: StdArc<Mutex<Vec<Vec<MutationCall>>>>,
+    let traceparent = headers.get("traceparent")?.to_str().ok()?;
+    let flags = traceparent.split('-').nth(3)?;
+    let flags = u8::from_str_radix(flags, 16).ok()?;
+    Some(flags & 0x01 == 0x01)
+}
+
+/// Hands the detected calls to the response side through the context. A failure
+/// is logged and counted, and the request proceeds without persistence.
+fn stash_pending_mutations<T: Serialize>(context: &Context, calls: T) -> bool {
+    match context.insert("pending_mutations", calls) {
+        Ok(_) => true,
+        Err(error) => {
+            tracing::error!(
+                monotonic_counter.kurrent_context_insert_errors_total = 1u64,
+                plugin = PLUGIN_NAME,
+                error = %error,
+                "Failed to store pending mutations in the request context, not persisting them"
+            );
+            false
+        }
     }
+}
 
-    impl MockMutationSink {
-        fn recorded(&self) -> Vec<Vec<MutationCall>> {
-            self.calls.lock().unwrap().clone()
+/// Hands the request's correlation id to the subgraph layer through the context. A
+/// failure is logged and counted, and the request proceeds; its calls still carry
+/// the id in their metadata.
+fn stash_correlation_id(context: &Context, correlation_id: String) -> bool {
+    match context.insert(CORRELATION_ID_CONTEXT_KEY, correlation_id) {
+        Ok(_) => true,
+        Err(error) => {
+            tracing::error!(
+                monotonic_counter.kurrent_context_insert_errors_total = 1u64,
+                plugin = PLUGIN_NAME,
+                error = %error,
+                "Failed to store the correlation id in the request context"
+            );
+            false
         }
     }
+}
 
-    impl MutationSink for MockMutationSink {
-        fn persist_mutations(&self, calls: Vec<MutationCall>) {
-            self.calls.lock().unwrap().push(calls);
+/// The request's variables, unless there are none or they serialize to more than
+/// `max_bytes`.
+fn variables_metadata(
+    variables: &BytesMap<ByteString, BytesValue>,
+    max_bytes: Option<usize>,
+) -> Option<Value> {
+    if variables.is_empty() {
+        return None;
+    }
+
+    let stored = match serde_json::to_value(variables) {
+        Ok(stored) => stored,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to serialize variables for the event metadata");
+            return None;
         }
+    };
+    let size = stored.to_string().len();
+    if let Some(max_bytes) = max_bytes
+        && size > max_bytes
+    {
+        tracing::debug!(
+            size,
+            max_bytes,
+            "Variables exceed max_event_bytes, not storing them"
+        );
+        return None;
     }
+    Some(stored)
+}
 
-    fn build_supergraph_request(query: &str, variables: serde_json::Value) -> supergraph::Request {
-        let vars: serde_json::Map<String, serde_json::Value> =
-            serde_json::from_value(variables).unwrap();
-        let mut bytes_map = BytesMap::new();
-        for (key, value) in vars {
-            bytes_map.insert(
-                ByteString::from(key),
-                serde_json_bytes::to_value(value).unwrap(),
+/// W3C `traceparent` of the active span, falling back to a well-formed incoming
+/// header, and to a generated one in `generate` mode.
+fn traceparent(headers: &HeaderMap, mode: TraceparentMode) -> Option<String> {
+    if mode == TraceparentMode::Off {
+        return None;
+    }
+
+    let context = opentelemetry::Context::current();
+    let span_context = context.span().span_context().clone();
+    if span_context.is_valid() {
+        return Some(format!(
+            "00-{}-{}-{:02x}",
+            span_context.trace_id(),
+            span_context.span_id(),
+            span_context.trace_flags().to_u8()
+        ));
+    }
+
+    let incoming = headers
+        .get("traceparent")
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| is_valid_traceparent(value));
+    match (incoming, mode) {
+        (Some(incoming), _) => Some(incoming.to_string()),
+        (None, TraceparentMode::Generate) => {
+            let span_id = Uuid::new_v4().simple().to_string();
+            Some(format!(
+                "00-{}-{}-00",
+                Uuid::new_v4().simple(),
+                &span_id[..16]
+            ))
+        }
+        (None, _) => None,
+    }
+}
+
+fn is_valid_traceparent(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+    let is_hex = |part: &str, len: usize| {
+        part.len() == len && part.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    };
+    let is_non_zero = |part: &str| part.bytes().any(|b| b != b'0');
+
+    parts.len() == 4
+        && is_hex(parts[0], 2)
+        && parts[0] != "ff"
+        && is_hex(parts[1], 32)
+        && is_non_zero(parts[1])
+        && is_hex(parts[2], 16)
+        && is_non_zero(parts[2])
+        && is_hex(parts[3], 2)
+}
+
+/// The mutation root type of the supergraph and the fields it declares.
+struct MutationSchema {
+    type_name: String,
+    fields: HashSet<String>,
+}
+
+impl MutationSchema {
+    fn from_sdl(sdl: &str) -> Self {
+        let ast = Parser::new(sdl).parse();
+        let doc = ast.document();
+
+        let type_name = doc
+            .definitions()
+            .find_map(|def| match def {
+                Definition::SchemaDefinition(schema) => schema
+                    .root_operation_type_definitions()
+                    .find(|root| {
+                        root.operation_type()
+                            .is_some_and(|op| op.mutation_token().is_some())
+                    })
+                    .and_then(|root| root.named_type()?.name())
+                    .map(|name| name.text().to_string()),
+                _ => None,
+            })
+            .unwrap_or_else(|| "Mutation".to_string());
+
+        let mut fields = HashSet::new();
+        for def in doc.definitions() {
+            let fields_definition = match def {
+                Definition::ObjectTypeDefinition(object)
+                    if object
+                        .name()
+                        .is_some_and(|n| n.text().as_str() == type_name) =>
+                {
+                    object.fields_definition()
+                }
+                Definition::ObjectTypeExtension(object)
+                    if object
+                        .name()
+                        .is_some_and(|n| n.text().as_str() == type_name) =>
+                {
+                    object.fields_definition()
+                }
+                _ => None,
+            };
+            if let Some(fields_definition) = fields_definition {
+                fields.extend(
+                    fields_definition
+                        .field_definitions()
+                        .filter_map(|field| field.name().map(|n| n.text().to_string())),
+                );
+            }
+        }
+
+        Self { type_name, fields }
+    }
+
+    fn annotate(&self, call: &mut MutationCall) {
+        if self.fields.contains(&call.field_name) {
+            call.metadata.insert(
+                "schema_coordinate".to_string(),
+                Value::String(format!("{}.{}", self.type_name, call.field_name)),
             );
+        } else {
+            tracing::debug!(mutation = %call.field_name, mutation_type = %self.type_name, "Mutation field not found in supergraph schema");
         }
+    }
+}
 
-        supergraph::Request::fake_builder()
-            .query(query.to_string())
-            .variables(bytes_map)
-            .build()
-            .unwrap()
+/// Accumulates the chunks of a deferred (`@defer`) response, so its calls are
+/// persisted once, with the complete data.
+#[derive(Default)]
+struct DeferredResponse {
+    primary: Option<graphql::Response>,
+}
+
+impl DeferredResponse {
+    /// The response merged from all chunks so far once `chunk` is the last one
+    /// (`hasNext` is not `true`); `None` while more are to come. A response that is
+    /// not deferred is complete with its first chunk.
+    fn complete(&mut self, chunk: &graphql::Response) -> Option<graphql::Response> {
+        let accumulated = match self.primary.as_mut() {
+            None => self.primary.insert(chunk.clone()),
+            Some(primary) => {
+                primary.errors.extend(chunk.errors.iter().cloned());
+                primary.extensions.extend(chunk.extensions.clone());
+                primary
+            }
+        };
+        for incremental in &chunk.incremental {
+            accumulated
+                .errors
+                .extend(incremental.errors.iter().cloned());
+            let (Some(data), Some(target)) = (incremental.data.as_ref(), accumulated.data.as_mut())
+            else {
+                continue;
+            };
+            let path = serde_json::to_value(&incremental.path).unwrap_or_default();
+            match incremental_target(target, &path) {
+                Some(target) => deep_merge(target, data),
+                None => {
+                    tracing::debug!("Deferred payload path not found in the response, ignoring it")
+                }
+            }
+        }
+
+        if chunk.has_next == Some(true) {
+            return None;
+        }
+        let mut complete = self.primary.take()?;
+        complete.incremental.clear();
+        Some(complete)
     }
+}
 
-    #[tokio::test]
-    async fn detects_mutations_and_invokes_sink() {
-        let sink = StdArc::new(MockMutationSink::default());
-        let interceptor = MutationInterceptor::with_sink(sink.clone());
+/// The value an incremental payload's serialized `path` (keys and list indices)
+/// points to; the root for a payload without a path.
+fn incremental_target<'a>(data: &'a mut BytesValue, path: &Value) -> Option<&'a mut BytesValue> {
+    if path.is_null() {
+        return Some(data);
+    }
+    path.as_array()?
+        .iter()
+        .try_fold(data, |current, segment| match segment {
+            Value::String(key) => current.as_object_mut()?.get_mut(key.as_str()),
+            Value::Number(index) => current
+                .as_array_mut()?
+                .get_mut(usize::try_from(index.as_u64()?).ok()?),
+            _ => None,
+        })
+}
 
-        let mut mock_service = test::MockSupergraphService::new();
-        mock_service
-            .expect_call()
-            .returning(|req: supergraph::Request| {
-                // Return a response with data
-                let data = json!({
-                    "recordAutomatedSummary": {
-                        "LoanRequestID": "test-loan-123",
-                        "CreditScoreSummary": "credit score summary"
-                    }
-                });
-                Ok(supergraph::Response::fake_builder()
-                    .context(req.context)
-                    .data(serde_json_bytes::to_value(data).unwrap())
-                    .build()
-                    .unwrap())
-            });
-        mock_service.expect_clone().return_once(|| {
-            let mut inner = test::MockSupergraphService::new();
-            inner.expect_call().returning(|req: supergraph::Request| {
-                let data = json!({
-                    "recordAutomatedSummary": {
-                        "LoanRequestID": "test-loan-123",
-                        "CreditScoreSummary": "credit score summary"
+fn deep_merge(target: &mut BytesValue, source: &BytesValue) {
+    match (target, source) {
+        (BytesValue::Object(target), BytesValue::Object(source)) => {
+            for (key, value) in source.iter() {
+                match target.get_mut(key.as_str()) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => {
+                        target.insert(key.clone(), value.clone());
                     }
-                });
-                Ok(supergraph::Response::fake_builder()
-                    .context(req.context)
-                    .data(serde_json_bytes::to_value(data).unwrap())
-                    .build()
-                    .unwrap())
+                }
+            }
+        }
+        (target, source) => *target = source.clone(),
+    }
+}
+
+/// The calls to persist for one response of the body stream, enriched with its
+/// data; `None` when the response gives nothing to persist.
+pub fn calls_to_persist(
+    calls: &[MutationCall],
+    graphql_response: &graphql::Response,
+    enrichment: &EnrichmentOptions,
+) -> Option<Vec<MutationCall>> {
+    if let Some(data) = graphql_response.data.as_ref() {
+        let calls = without_errored_calls(calls, &graphql_response.errors, enrichment)?;
+        let enriched_calls =
+            enrich_mutations_with_response(calls, data, &graphql_response.extensions, enrichment);
+        if enriched_calls.is_empty() {
+            tracing::info!("All mutation(s) matched a skip condition, skipping persistence");
+            return None;
+        }
+
+        tracing::info!(
+            mutations = ?enriched_calls,
+            count = enriched_calls.len(),
+            "Persisting successful mutation(s) with response data"
+        );
+        Some(enriched_calls)
+    } else if graphql_response.errors.is_empty() {
+        if enrichment.persist_without_data {
+            tracing::info!(
+                count = calls.len(),
+                "Mutation completed without data, persisting extracted mutation(s)"
+            );
+            let mut calls = calls.to_vec();
+            capture_extensions(
+                &mut calls,
+                &graphql_response.extensions,
+                &enrichment.capture_extensions,
+            );
+            Some(calls)
+        } else {
+            tracing::warn!("Mutation completed but no data in response, skipping persistence");
+            None
+        }
+    } else {
+        None
+    }
+}
+
+/// Persists `calls` and waits for the outcome, or for `timeout`, before the
+/// response is forwarded. A timed-out append carries on in the background.
+async fn persist_before_forwarding(
+    mutation_sink: Arc<dyn MutationSink>,
+    calls: Vec<MutationCall>,
+    timeout: Option<Duration>,
+) {
+    let persisting = tokio::spawn(mutation_sink.persist_and_wait(calls));
+    let result = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, persisting).await {
+            Ok(result) => result,
+            Err(_) => {
+                tracing::warn!(
+                    timeout_ms = timeout.as_millis() as u64,
+                    "Append not acknowledged within persist_before_response_timeout_ms, forwarding the response"
+                );
+                return;
+            }
+        },
+        None => persisting.await,
+    };
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => {
+            tracing::error!(error = %error, kind = error.kind(), "Failed to persist mutation(s) before the response");
+        }
+        Err(error) => {
+            tracing::error!(error = %error, "Persisting mutation(s) before the response panicked")
+        }
+    }
+}
+
+/// The calls of a response with `data`, minus those `partial_success_policy` leaves
+/// out when it also carries `errors`; `None` when none remain.
+fn without_errored_calls(
+    calls: &[MutationCall],
+    errors: &[graphql::Error],
+    enrichment: &EnrichmentOptions,
+) -> Option<Vec<MutationCall>> {
+    if errors.is_empty() {
+        return Some(calls.to_vec());
+    }
+
+    match enrichment.partial_success_policy {
+        PartialSuccessPolicy::PersistAll => Some(calls.to_vec()),
+        PartialSuccessPolicy::Skip => {
+            tracing::warn!(
+                count = calls.len(),
+                errors = errors.len(),
+                "Mutation response carries errors, skipping persistence"
+            );
+            None
+        }
+        PartialSuccessPolicy::PersistWithoutErroredFields => {
+            let errored = errored_response_keys(errors);
+            let (failed, succeeded): (Vec<_>, Vec<_>) = calls.iter().cloned().partition(|call| {
+                errored.contains(call.alias.as_ref().unwrap_or(&call.field_name))
             });
-            inner
-        });
+            for call in &failed {
+                tracing::info!(mutation = %call.field_name, alias = ?call.alias, "Mutation errored, not persisting");
+            }
+            (!succeeded.is_empty()).then_some(succeeded)
+        }
+    }
+}
 
-        let service = interceptor.supergraph_service(mock_service.boxed());
+/// Root response keys the errors' `path`s start with.
+fn errored_response_keys(errors: &[graphql::Error]) -> HashSet<String> {
+    errors
+        .iter()
+        .filter_map(|error| {
+            let path = serde_json::to_value(error.path.as_ref()?).ok()?;
+            path.get(0)?.as_str().map(str::to_string)
+        })
+        .collect()
+}
 
-        let mutation = r#"
-            mutation RecordSummary {
-              recordAutomatedSummary(
-                input: {
-                  loanId: "test-loan-123"
-                  CreditScoreSummary: "credit score summary"
-                  IncomeAndEmploymentSummary: "income"
-                  LoanToIncomeSummary: "ratio"
-                  MaritalStatusAndDependentsSummary: "status"
-                  RecommendedFurtherInvestigation: "none"
-                  SummarizedBy: "Analyst"
-                  SummarizedAt: "2024-09-29T00:00:00Z"
-                }
-              ) {
+fn kurrent_unavailable_response(context: Context) -> Result<supergraph::Response, BoxError> {
+    supergraph::Response::error_builder()
+        .error(
+            graphql::Error::builder()
+                .message("KurrentDB is unreachable; mutations cannot be persisted")
+                .extension_code("KURRENTDB_UNAVAILABLE")
+                .build(),
+        )
+        .status_code(StatusCode::SERVICE_UNAVAILABLE)
+        .context(context)
+        .build()
+}
+
+fn queue_full_response(context: Context) -> Result<supergraph::Response, BoxError> {
+    supergraph::Response::error_builder()
+        .error(
+            graphql::Error::builder()
+                .message("The KurrentDB persist queue is full; retry the mutation later")
+                .extension_code("KURRENTDB_QUEUE_FULL")
+                .build(),
+        )
+        .status_code(StatusCode::SERVICE_UNAVAILABLE)
+        .context(context)
+        .build()
+}
+
+fn duplicate_operation_response(
+    context: Context,
+    names: &[String],
+) -> Result<supergraph::Response, BoxError> {
+    supergraph::Response::error_builder()
+        .error(
+            graphql::Error::builder()
+                .message(format!(
+                    "Operation names must be unique; duplicated: {}",
+                    names.join(", ")
+                ))
+                .extension_code("DUPLICATE_OPERATION_NAME")
+                .build(),
+        )
+        .status_code(StatusCode::BAD_REQUEST)
+        .context(context)
+        .build()
+}
+
+use serde_json::Value;
+use serde_json_bytes::{ByteString, Map as BytesMap, Value as BytesValue};
+
+/// Representation choices applied while converting argument values to JSON.
+#[derive(Debug, Clone, Copy, Default)]
+struct ValueConversion {
+    tag_enums: bool,
+    large_integers: LargeIntegers,
+}
+
+/// Largest integer an IEEE 754 double represents exactly.
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+fn is_safe_integer(number: &serde_json::Number) -> bool {
+    number
+        .as_i64()
+        .map(|i| i.unsigned_abs())
+        .or_else(|| number.as_u64())
+        .is_some_and(|n| n <= MAX_SAFE_INTEGER)
+}
+
+fn int_to_json(literal: &str, large_integers: LargeIntegers) -> Option<Value> {
+    let value: Value = serde_json::from_str(literal).ok()?;
+    match (&value, large_integers) {
+        (Value::Number(n), LargeIntegers::String) if !is_safe_integer(n) => {
+            Some(Value::String(literal.to_string()))
+        }
+        _ => Some(value),
+    }
+}
+
+/// Replaces integers beyond the safe range, at any depth, with their digits.
+fn stringify_large_integers(value: &mut Value) {
+    match value {
+        Value::Number(n) if (n.is_i64() || n.is_u64()) && !is_safe_integer(n) => {
+            *value = Value::String(n.to_string());
+        }
+        Value::Array(items) => items.iter_mut().for_each(stringify_large_integers),
+        Value::Object(fields) => fields.values_mut().for_each(stringify_large_integers),
+        _ => {}
+    }
+}
+
+/// Converts a router (`serde_json_bytes`) value to the `serde_json` form calls are
+/// persisted in, walking it directly rather than through a serializer round-trip.
+fn bytes_to_json(value: &BytesValue) -> Value {
+    match value {
+        BytesValue::Null => Value::Null,
+        BytesValue::Bool(b) => Value::Bool(*b),
+        BytesValue::Number(n) => Value::Number(n.clone()),
+        BytesValue::String(s) => Value::String(s.as_str().to_string()),
+        BytesValue::Array(items) => Value::Array(items.iter().map(bytes_to_json).collect()),
+        BytesValue::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.as_str().to_string(), bytes_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn ast_value_to_json(
+    value: &ASTValue,
+    vars: &BytesMap<ByteString, BytesValue>,
+    conversion: ValueConversion,
+) -> Option<Value> {
+    match value {
+        StringValue(s) => serde_json::from_str(&s.syntax().text().to_string()).ok(),
+        IntValue(i) => int_to_json(&i.syntax().text().to_string(), conversion.large_integers),
+        FloatValue(f) => serde_json::from_str(&f.syntax().text().to_string()).ok(),
+        BooleanValue(b) => serde_json::from_str(&b.syntax().text().to_string()).ok(),
+        NullValue(_) => Some(Value::Null),
+        EnumValue(e) if conversion.tag_enums => Some(tag_enum(&e.syntax().text().to_string())),
+        EnumValue(e) => Some(Value::String(e.syntax().text().to_string())),
+        Variable(var) => {
+            let name = var.name()?.text();
+            let mut value = bytes_to_json(vars.get(name.as_str())?);
+            if conversion.large_integers == LargeIntegers::String {
+                stringify_large_integers(&mut value);
+            }
+            Some(value)
+        }
+        ListValue(list) => {
+            let mut arr = Vec::new();
+            for v in list.values() {
+                arr.push(ast_value_to_json(&v, vars, conversion).unwrap_or(Value::Null));
+            }
+            Some(Value::Array(arr))
+        }
+        ObjectValue(obj) => {
+            let mut map = serde_json::Map::new();
+            for field in obj.object_fields() {
+                let name = field.name()?.text().to_string();
+                let val = field.value()?;
+                let value = match ast_value_to_json(&val, vars, conversion) {
+                    Some(value) => value,
+                    // As in GraphQL input coercion, a field set to an absent variable
+                    // is absent (and may take its default), unlike an explicit null.
+                    None if matches!(val, Variable(_)) => continue,
+                    None => Value::Null,
+                };
+                map.insert(name, value);
+            }
+            Some(Value::Object(map))
+        }
+    }
+}
+
+fn collect_top_level_response_fields(selection_set: Option<SelectionSet>) -> Vec<SelectedField> {
+    let mut out = Vec::new();
+    if let Some(selections) = selection_set {
+        for selection in selections.selections() {
+            if let Selection::Field(field) = selection
+                && let Some(name) = field.name().map(|n| n.text().to_string())
+            {
+                let response_key = field
+                    .alias()
+                    .and_then(|a| a.name().map(|n| n.text().to_string()))
+                    .unwrap_or_else(|| name.clone());
+                out.push(SelectedField::new(response_key, name));
+            }
+        }
+    }
+    out
+}
+
+fn collect_args(
+    field: &apollo_parser::cst::Field,
+    vars: &BytesMap<ByteString, BytesValue>,
+    conversion: ValueConversion,
+) -> Vec<MutationArg> {
+    let mut args = Vec::new();
+    if let Some(arguments) = field.arguments() {
+        for a in arguments.arguments() {
+            let name = a.name().map(|n| n.text().to_string()).unwrap_or_default();
+            let val = a
+                .value()
+                .and_then(|v| ast_value_to_json(&v, vars, conversion))
+                .unwrap_or(Value::Null);
+            args.push(MutationArg { name, value: val });
+        }
+    }
+    args
+}
+
+fn extract_loan_id_from_args(
+    arguments: &[MutationArg],
+    input_argument_names: &[String],
+) -> Option<String> {
+    // Look for the first configured input argument that is present
+    input_argument_names
+        .iter()
+        .find_map(|input_name| arguments.iter().find(|arg| &arg.name == input_name))
+        .and_then(|input_arg| {
+            // Check if the input value is an object with a "loanId" field
+            input_arg
+                .value
+                .get("loanId")
+                .and_then(|loan_id_value| loan_id_value.as_str().map(|s| s.to_string()))
+        })
+}
+
+/// A mutation whose input argument is a list (a batch create) becomes one call per
+/// element, each with that element as its input and its own aggregate id, so every
+/// aggregate lands in its own stream. The element's position is recorded as
+/// `input_index`. Empty lists leave the call as it is.
+fn split_list_input(call: MutationCall, input_argument_names: &[String]) -> Vec<MutationCall> {
+    let Some(index) = input_argument_names
+        .iter()
+        .find_map(|name| call.arguments.iter().position(|arg| &arg.name == name))
+    else {
+        return vec![call];
+    };
+    let elements = match &call.arguments[index].value {
+        Value::Array(elements) if !elements.is_empty() => elements.clone(),
+        _ => return vec![call],
+    };
+
+    elements
+        .into_iter()
+        .enumerate()
+        .map(|(position, element)| {
+            let mut element_call = call.clone();
+            element_call.arguments[index].value = element;
+            element_call.loan_id =
+                extract_loan_id_from_args(&element_call.arguments, input_argument_names);
+            element_call
+                .metadata
+                .insert("input_index".to_string(), Value::from(position));
+            element_call
+        })
+        .collect()
+}
+
+fn response_value_for<'a>(call: &MutationCall, data: &'a BytesValue) -> Option<&'a BytesValue> {
+    let key = call.alias.as_ref().unwrap_or(&call.field_name);
+    data.as_object()?.get(key.as_str())
+}
+
+/// Runs the enrichers and adds `responseData` on each call's response value, and
+/// drops the calls a `skip_when` condition matches. Only the calls' own response values are
+/// converted to JSON, once each, however large the rest of the response is.
+fn enrich_mutations_with_response(
+    calls: Vec<MutationCall>,
+    response_data: &BytesValue,
+    extensions: &BytesMap<ByteString, BytesValue>,
+    options: &EnrichmentOptions,
+) -> Vec<MutationCall> {
+    let mut enriched = Vec::with_capacity(calls.len());
+    for mut call in calls {
+        let response_value = response_value_for(&call, response_data).map(bytes_to_json);
+
+        if let Some(value) = response_value {
+            if options
+                .skip_when
+                .iter()
+                .any(|condition| condition.matches(&call, &value))
+            {
+                tracing::info!(mutation = %call.field_name, "Response matched a skip_when condition, not persisting");
+                continue;
+            }
+
+            for enricher in &options.enrichers {
+                enricher.enrich(&mut call, &value);
+            }
+
+            if !options.omit_response_data
+                && !options.id_from_scalar_response.matches(&call.field_name)
+            {
+                call.arguments.push(MutationArg {
+                    name: "responseData".to_string(),
+                    value,
+                });
+            }
+        }
+        enriched.push(call);
+    }
+    let mut calls = enriched;
+
+    capture_extensions(&mut calls, extensions, &options.capture_extensions);
+
+    if options.dedup_within_batch {
+        calls = dedup_calls(calls);
+    }
+
+    calls
+}
+
+/// Copies the selected response extensions onto every call. Responses without
+/// extensions, or without the selected keys, leave the calls unchanged.
+fn capture_extensions(
+    calls: &mut [MutationCall],
+    extensions: &BytesMap<ByteString, BytesValue>,
+    keys: &[String],
+) {
+    if keys.is_empty() || extensions.is_empty() {
+        return;
+    }
+
+    let capture_all = keys.iter().any(|key| key == "*");
+    let captured: serde_json::Map<String, Value> = extensions
+        .iter()
+        .filter(|(key, _)| capture_all || keys.iter().any(|k| k == key.as_str()))
+        .map(|(key, value)| (key.as_str().to_string(), bytes_to_json(value)))
+        .collect();
+
+    if captured.is_empty() {
+        return;
+    }
+    for call in calls.iter_mut() {
+        call.extensions = captured.clone();
+    }
+}
+
+/// Keeps the first of each group of calls with the same field, arguments and
+/// aggregate id. Runs after enrichment, so aliases whose responses differ stay
+/// distinct.
+fn dedup_calls(calls: Vec<MutationCall>) -> Vec<MutationCall> {
+    let mut seen = HashSet::new();
+    calls
+        .into_iter()
+        .filter(|call| {
+            let key = (
+                call.field_name.clone(),
+                Value::Object(call.arguments_map()).to_string(),
+                call.loan_id.clone(),
+            );
+            let first = seen.insert(key);
+            if !first {
+                tracing::info!(mutation = %call.field_name, alias = ?call.alias, "Dropping identical mutation within the same operation");
+            }
+            first
+        })
+        .collect()
+}
+
+/// Arguments of a `@persist` directive found on a mutation field.
+struct PersistDirective {
+    stream: Option<String>,
+}
+
+fn persist_directive(
+    field: &apollo_parser::cst::Field,
+    vars: &BytesMap<ByteString, BytesValue>,
+) -> Option<PersistDirective> {
+    let directive = field
+        .directives()?
+        .directives()
+        .find(|d| d.name().is_some_and(|n| n.text().as_str() == "persist"))?;
+
+    let stream = directive.arguments().and_then(|arguments| {
+        arguments
+            .arguments()
+            .find(|a| a.name().is_some_and(|n| n.text().as_str() == "stream"))
+            .and_then(|a| a.value())
+            .and_then(|v| ast_value_to_json(&v, vars, ValueConversion::default()))
+            .and_then(|v| v.as_str().map(|s| s.to_string()))
+    });
+
+    Some(PersistDirective { stream })
+}
+
+struct Extractor<'a> {
+    operation_type: OperationType,
+    variables: Cow<'a, BytesMap<ByteString, BytesValue>>,
+    fragments: &'a HashMap<String, FragmentDefinition>,
+    options: &'a ExtractionOptions,
+}
+
+impl Extractor<'_> {
+    /// Walks a mutation's top-level selection set, following fragment spreads and
+    /// inline fragments so fields defined in a (Relay-style) fragment are captured
+    /// exactly as if they had been written inline.
+    fn collect_calls(
+        &self,
+        selection_set: SelectionSet,
+        op_name: &Option<String>,
+        visiting: &mut Vec<String>,
+        calls: &mut Vec<MutationCall>,
+    ) {
+        for selection in selection_set.selections() {
+            match selection {
+                Selection::Field(field) => {
+                    let name = field
+                        .name()
+                        .map(|n| n.text().to_string())
+                        .unwrap_or_default();
+                    if name.is_empty() || name.starts_with("__") {
+                        // Introspection (`__typename`) or a field the parser could not
+                        // name; neither is a mutation worth a stream.
+                        tracing::debug!(field = %name, "Skipping introspection or unnamed mutation field");
+                        continue;
+                    }
+
+                    if self.operation_type == OperationType::Query
+                        && !self
+                            .options
+                            .query_fields
+                            .as_ref()
+                            .is_none_or(|fields| fields.matches(&name))
+                    {
+                        tracing::debug!(field = %name, "Skipping query field not listed in query_fields");
+                        continue;
+                    }
+
+                    let directive = persist_directive(&field, &self.variables);
+                    if self.options.persist_mode == PersistMode::Directive && directive.is_none() {
+                        tracing::debug!(
+                            mutation = ?field.name().map(|n| n.text().to_string()),
+                            "Skipping mutation field without @persist directive"
+                        );
+                        continue;
+                    }
+
+                    let mut call = self.mutation_call(&field, op_name);
+                    call.operation_type = self.operation_type;
+                    call.stream_override = directive.and_then(|d| d.stream);
+                    calls.extend(
+                        split_list_input(call, &self.options.input_argument_names)
+                            .into_iter()
+                            .filter(|call| self.has_required_args(call)),
+                    );
+                }
+                Selection::FragmentSpread(spread) => {
+                    let Some(name) = spread
+                        .fragment_name()
+                        .and_then(|f| f.name())
+                        .map(|n| n.text().to_string())
+                    else {
+                        continue;
+                    };
+                    if visiting.contains(&name) {
+                        tracing::warn!(fragment = %name, "Skipping cyclic fragment spread in mutation");
+                        continue;
+                    }
+                    match self.fragments.get(&name).and_then(|f| f.selection_set()) {
+                        Some(fragment_selection) => {
+                            visiting.push(name);
+                            self.collect_calls(fragment_selection, op_name, visiting, calls);
+                            visiting.pop();
+                        }
+                        None => {
+                            tracing::warn!(fragment = %name, "Mutation spreads an unknown fragment");
+                        }
+                    }
+                }
+                Selection::InlineFragment(inline) => {
+                    if let Some(inline_selection) = inline.selection_set() {
+                        self.collect_calls(inline_selection, op_name, visiting, calls);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether `call` resolves every argument `require_args` lists for its field.
+    fn has_required_args(&self, call: &MutationCall) -> bool {
+        let Some(required) = self.options.require_args.get(&call.field_name) else {
+            return true;
+        };
+        match required
+            .iter()
+            .find(|path| call.argument_value(path).is_none_or(Value::is_null))
+        {
+            Some(missing) => {
+                tracing::debug!(mutation = %call.field_name, argument = %missing, "Skipping call missing a required argument");
+                false
+            }
+            None => true,
+        }
+    }
+
+    fn mutation_call(
+        &self,
+        field: &apollo_parser::cst::Field,
+        op_name: &Option<String>,
+    ) -> MutationCall {
+        let field_name = field
+            .name()
+            .map(|n| n.text().to_string())
+            .unwrap_or_default();
+        let alias = field
+            .alias()
+            .and_then(|a| a.name().map(|n| n.text().to_string()));
+        let mut arguments = collect_args(field, &self.variables, self.options.value_conversion());
+        if let Some(defaults) = self.options.input_defaults.as_deref() {
+            defaults.apply(self.operation_type, &field_name, &mut arguments);
+        }
+        if self.options.skip_null_args {
+            arguments.retain(|arg| !arg.value.is_null());
+        }
+        if !self.options.scalar_coercions.is_empty() {
+            self.options
+                .scalar_coercions
+                .apply(&field_name, &mut arguments);
+        }
+
+        // Extract loanId from input arguments if present
+        let loan_id = extract_loan_id_from_args(&arguments, &self.options.input_argument_names);
+
+        let selected_fields = collect_top_level_response_fields(field.selection_set());
+        MutationCall {
+            operation_name: op_name.clone(),
+            field_name,
+            loan_id,
+            alias,
+            arguments,
+            selected_fields,
+            ..Default::default()
+        }
+    }
+}
+
+/// Parses an operation document, logging syntax errors so a malformed request is
+/// distinguishable from one without mutations. The (partial) document is still
+/// returned.
+fn parse_operation(query: &str) -> Document {
+    let ast = Parser::new(query).parse();
+    let errors = describe_parse_errors(&ast);
+    if !errors.is_empty() {
+        tracing::warn!(
+            monotonic_counter.kurrent_query_parse_errors_total = 1u64,
+            plugin = PLUGIN_NAME,
+            errors = ?errors,
+            "Failed to parse GraphQL operation; mutations may be missed"
+        );
+    }
+    ast.document()
+}
+
+fn describe_parse_errors(ast: &SyntaxTree) -> Vec<String> {
+    ast.errors()
+        .map(|error| {
+            format!(
+                "{} at offset {} (near `{}`)",
+                error.message(),
+                error.index(),
+                error.data()
+            )
+        })
+        .collect()
+}
+
+pub fn extract_mutations(
+    query: &str,
+    variables: &BytesMap<ByteString, BytesValue>,
+    options: &ExtractionOptions,
+) -> Vec<MutationCall> {
+    extract_operation_mutations(query, None, variables, options)
+}
+
+/// Like [`extract_mutations`], but when `operation_name` is given only that
+/// operation, the one the router executes, is considered. Only operations of the
+/// configured `operation_types` produce calls: mutations by default, and query
+/// fields matching `query_fields` when `query` is included. Subscriptions never do.
+pub fn extract_operation_mutations(
+    query: &str,
+    operation_name: Option<&str>,
+    variables: &BytesMap<ByteString, BytesValue>,
+    options: &ExtractionOptions,
+) -> Vec<MutationCall> {
+    let document = parse_operation(query);
+    extract_document_mutations(&document, operation_name, variables, options)
+}
+
+/// Like [`extract_operation_mutations`], for callers that already parsed the
+/// document, such as the router pipeline or a replay tool, and for tests against
+/// specific (including malformed) CST shapes. The plugin parses each request once
+/// and shares the document between the duplicate-operation check and extraction;
+/// the router's own validated document is an apollo-compiler `ExecutableDocument`,
+/// not this CST, so it cannot be walked here.
+pub fn extract_document_mutations(
+    doc: &Document,
+    operation_name: Option<&str>,
+    variables: &BytesMap<ByteString, BytesValue>,
+    options: &ExtractionOptions,
+) -> Vec<MutationCall> {
+    let mut calls = Vec::new();
+
+    let fragments: HashMap<String, FragmentDefinition> = doc
+        .definitions()
+        .filter_map(|def| match def {
+            Definition::FragmentDefinition(fragment) => {
+                let name = fragment.fragment_name()?.name()?.text().to_string();
+                Some((name, fragment))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let mut seen_operations = HashSet::new();
+    for def in doc.definitions() {
+        let Definition::OperationDefinition(op) = def else {
+            continue;
+        };
+        let op_name = op.name().map(|n| n.text().to_string());
+        if let Some(executed) = operation_name
+            && op_name.as_deref() != Some(executed)
+        {
+            continue;
+        }
+
+        // Operations without a type are query shorthand (`{ ... }`).
+        let operation_type = match op.operation_type() {
+            Some(op_type) if op_type.subscription_token().is_some() => {
+                tracing::debug!(operation = ?op_name, "Ignoring subscription operation");
+                continue;
+            }
+            Some(op_type) if op_type.mutation_token().is_some() => OperationType::Mutation,
+            _ => OperationType::Query,
+        };
+        if !options.operation_types.contains(&operation_type) {
+            continue;
+        }
+
+        if let Some(name) = op_name.as_ref()
+            && !seen_operations.insert(name.clone())
+        {
+            tracing::warn!(operation = %name, "Ignoring duplicate operation definition; the first one wins");
+            continue;
+        }
+        let variables = with_variable_defaults(&op, variables, options.large_integers);
+        let extractor = Extractor {
+            operation_type,
+            variables: match options.enum_tagging.as_deref() {
+                Some(enum_types) => Cow::Owned(enum_types.tag_variables(&op, &variables)),
+                None => variables,
+            },
+            fragments: &fragments,
+            options,
+        };
+        if let Some(sel_set) = op.selection_set() {
+            extractor.collect_calls(sel_set, &op_name, &mut Vec::new(), &mut calls);
+        }
+    }
+
+    for (sequence, call) in calls.iter_mut().enumerate() {
+        call.sequence = sequence;
+    }
+
+    calls
+}
+
+/// `variables` plus the defaults `op` declares for variables the request omits, as
+/// GraphQL input coercion applies them. Variables sent as `null` keep the `null`.
+fn with_variable_defaults<'a>(
+    op: &OperationDefinition,
+    variables: &'a BytesMap<ByteString, BytesValue>,
+    large_integers: LargeIntegers,
+) -> Cow<'a, BytesMap<ByteString, BytesValue>> {
+    // Enum defaults stay plain strings here; `tag_variables` tags them by type.
+    let conversion = ValueConversion {
+        tag_enums: false,
+        large_integers,
+    };
+    let mut with_defaults = Cow::Borrowed(variables);
+    let definitions = op
+        .variable_definitions()
+        .into_iter()
+        .flat_map(|definitions| definitions.variable_definitions());
+    for definition in definitions {
+        let Some(name) = definition.variable().and_then(|v| v.name()) else {
+            continue;
+        };
+        if variables.contains_key(name.text().as_str()) {
+            continue;
+        }
+        if let Some(default) = definition
+            .default_value()
+            .and_then(|default| default.value())
+            .and_then(|value| ast_value_to_json(&value, &BytesMap::new(), conversion))
+        {
+            with_defaults.to_mut().insert(
+                ByteString::from(name.text().to_string()),
+                BytesValue::from(default),
+            );
+        }
+    }
+    with_defaults
+}
+
+/// Operation names defined more than once in `query`, in document order.
+pub fn duplicate_operation_names(query: &str) -> Vec<String> {
+    duplicate_operation_names_in(&parse_operation(query))
+}
+
+pub fn duplicate_operation_names_in(document: &Document) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+
+    for def in document.definitions() {
+        if let Definition::OperationDefinition(op) = def
+            && let Some(name) = op.name()
+        {
+            let name = name.text().to_string();
+            if !seen.insert(name.clone()) && !duplicates.contains(&name) {
+                duplicates.push(name);
+            }
+        }
+    }
+
+    duplicates
+}
+
+// The macro only accepts literals; keep these in sync with `PLUGIN_NAME`.
+apollo_router::register_plugin!("starstuff", "mutation_plugin", MutationInterceptor);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::captured_logs::CapturedLogs;
+    use crate::plugins::kurrent_mapper::{KurrentError, event_type, stream_name};
+    use apollo_router::plugin::{Plugin, PluginInit, test};
+    use apollo_router::services::supergraph;
+    use serde_json::json;
+    use serde_json_bytes::{ByteString, Map as BytesMap};
+    use std::sync::{Arc as StdArc, Mutex};
+
+    #[derive(Default)]
+    struct MockMutationSink {
+        calls: StdArc<Mutex<Vec<Vec<MutationCall>>>>,
+        unhealthy: bool,
+        queue_full: bool,
+    }
+
+    impl MockMutationSink {
+        fn recorded(&self) -> Vec<Vec<MutationCall>> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    impl MutationSink for MockMutationSink {
+        fn persist_mutations(&self, calls: Vec<MutationCall>) {
+            self.calls.lock().unwrap().push(calls);
+        }
+
+        fn is_healthy(&self) -> bool {
+            !self.unhealthy
+        }
+
+        fn has_capacity(&self) -> bool {
+            !self.queue_full
+        }
+    }
+
+    fn plugin_config(config: serde_json::Value) -> PluginConfig {
+        serde_json::from_value(config).unwrap()
+    }
+
+    fn build_supergraph_request(query: &str, variables: serde_json::Value) -> supergraph::Request {
+        let vars: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_value(variables).unwrap();
+        let mut bytes_map = BytesMap::new();
+        for (key, value) in vars {
+            bytes_map.insert(
+                ByteString::from(key),
+                serde_json_bytes::to_value(value).unwrap(),
+            );
+        }
+
+        supergraph::Request::fake_builder()
+            .query(query.to_string())
+            .variables(bytes_map)
+            .build()
+            .unwrap()
+    }
+
+    const CHECK_CREDIT: &str = r#"
+        mutation CheckCredit {
+          recordCreditChecked(input: { loanId: "loan-1", Score: 700 }) {
+            Score
+          }
+        }
+    "#;
+
+    /// GraphQL response body answering [`CHECK_CREDIT`].
+    fn credit_checked() -> Value {
+        json!({ "data": { "recordCreditChecked": { "Score": 700 } } })
+    }
+
+    fn graphql_response(body: &Value) -> graphql::Response {
+        serde_json::from_value(body.clone()).unwrap()
+    }
+
+    /// Runs `query` through the plugin configured with `config`, against a
+    /// supergraph answering with the GraphQL `response` body, and returns the
+    /// batches that reached the sink. `setup` adjusts the request first, e.g. its
+    /// headers or context.
+    async fn run_mutation(
+        config: Value,
+        query: &str,
+        response: Value,
+        setup: impl FnOnce(&mut supergraph::Request),
+    ) -> Vec<Vec<MutationCall>> {
+        let sink = StdArc::new(MockMutationSink::default());
+        let interceptor =
+            MutationInterceptor::with_sink_and_config(sink.clone(), plugin_config(config));
+
+        let mut mock_service = test::MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .returning(move |req: supergraph::Request| {
+                let body = graphql_response(&response);
+                Ok(supergraph::Response {
+                    response: http::Response::new(Box::pin(futures::stream::iter([body]))),
+                    context: req.context,
+                })
+            });
+
+        let service = interceptor.supergraph_service(mock_service.boxed());
+        let mut request = build_supergraph_request(query, json!({}));
+        setup(&mut request);
+
+        let mut response = service.oneshot(request).await.unwrap();
+        while let Some(_) = response.response.body_mut().next().await {}
+
+        sink.recorded()
+    }
+
+    /// [`run_mutation`] for the subgraph stage: sends `query` to the subgraph
+    /// named `subgraph_name`, which answers with the GraphQL `response` body.
+    async fn run_subgraph_mutation(
+        config: Value,
+        subgraph_name: &str,
+        query: &str,
+        response: Value,
+        setup: impl FnOnce(&mut subgraph::Request),
+    ) -> Vec<Vec<MutationCall>> {
+        let sink = StdArc::new(MockMutationSink::default());
+        let interceptor =
+            MutationInterceptor::with_sink_and_config(sink.clone(), plugin_config(config));
+
+        let mut mock_service = test::MockSubgraphService::new();
+        mock_service
+            .expect_call()
+            .returning(move |req: subgraph::Request| {
+                let body = graphql_response(&response);
+                Ok(subgraph::Response::fake_builder()
+                    .context(req.context)
+                    .data(body.data.unwrap_or_default())
+                    .errors(body.errors)
+                    .extensions(body.extensions)
+                    .build())
+            });
+
+        let service = interceptor.subgraph_service(subgraph_name, mock_service.boxed());
+        let body = graphql::Request::builder().query(query).build();
+        let mut request = subgraph::Request::fake_builder()
+            .subgraph_request(http::Request::builder().body(body).unwrap())
+            .build();
+        setup(&mut request);
+
+        service.oneshot(request).await.unwrap();
+
+        sink.recorded()
+    }
+
+    #[tokio::test]
+    async fn detects_mutations_and_invokes_sink() {
+        let sink = StdArc::new(MockMutationSink::default());
+        let interceptor = MutationInterceptor::with_sink(sink.clone());
+
+        let mut mock_service = test::MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .returning(|req: supergraph::Request| {
+                // Return a response with data
+                let data = json!({
+                    "recordAutomatedSummary": {
+                        "LoanRequestID": "test-loan-123",
+                        "CreditScoreSummary": "credit score summary"
+                    }
+                });
+                Ok(supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .data(serde_json_bytes::to_value(data).unwrap())
+                    .build()
+                    .unwrap())
+            });
+        mock_service.expect_clone().return_once(|| {
+            let mut inner = test::MockSupergraphService::new();
+            inner.expect_call().returning(|req: supergraph::Request| {
+                let data = json!({
+                    "recordAutomatedSummary": {
+                        "LoanRequestID": "test-loan-123",
+                        "CreditScoreSummary": "credit score summary"
+                    }
+                });
+                Ok(supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .data(serde_json_bytes::to_value(data).unwrap())
+                    .build()
+                    .unwrap())
+            });
+            inner
+        });
+
+        let service = interceptor.supergraph_service(mock_service.boxed());
+
+        let mutation = r#"
+            mutation RecordSummary {
+              recordAutomatedSummary(
+                input: {
+                  loanId: "test-loan-123"
+                  CreditScoreSummary: "credit score summary"
+                  IncomeAndEmploymentSummary: "income"
+                  LoanToIncomeSummary: "ratio"
+                  MaritalStatusAndDependentsSummary: "status"
+                  RecommendedFurtherInvestigation: "none"
+                  SummarizedBy: "Analyst"
+                  SummarizedAt: "2024-09-29T00:00:00Z"
+                }
+              ) {
+                LoanRequestID
+                CreditScoreSummary
+              }
+            }
+        "#;
+
+        let request = build_supergraph_request(mutation, json!({}));
+
+        let mut response = service.oneshot(request).await.unwrap();
+        assert!(response.response.status().is_success());
+
+        // Consume the response stream to trigger the mutation persistence
+        while let Some(_) = response.response.body_mut().next().await {}
+
+        let recorded = sink.recorded();
+        assert_eq!(1, recorded.len());
+        let calls = &recorded[0];
+        assert_eq!(1, calls.len());
+        let call = &calls[0];
+        assert_eq!("recordAutomatedSummary", call.field_name);
+        let input = call
+            .arguments
+            .iter()
+            .find(|arg| arg.name == "input")
+            .expect("input argument");
+        assert_eq!(
+            json!("credit score summary"),
+            input.value["CreditScoreSummary"]
+        );
+        // Verify response data was added
+        let response_data = call.arguments.iter().find(|arg| arg.name == "responseData");
+        assert!(response_data.is_some(), "Response data should be present");
+    }
+
+    #[tokio::test]
+    async fn extracts_loan_id_from_record_loan_requested_response() {
+        let sink = StdArc::new(MockMutationSink::default());
+        let interceptor = MutationInterceptor::with_sink(sink.clone());
+
+        let mut mock_service = test::MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .returning(|req: supergraph::Request| {
+                // Return a UUID as the loanId
+                let data = json!({
+                    "recordLoanRequested": "550e8400-e29b-41d4-a716-446655440000"
+                });
+                Ok(supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .data(serde_json_bytes::to_value(data).unwrap())
+                    .build()
+                    .unwrap())
+            });
+        mock_service.expect_clone().return_once(|| {
+            let mut inner = test::MockSupergraphService::new();
+            inner.expect_call().returning(|req: supergraph::Request| {
+                let data = json!({
+                    "recordLoanRequested": "550e8400-e29b-41d4-a716-446655440000"
+                });
+                Ok(supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .data(serde_json_bytes::to_value(data).unwrap())
+                    .build()
+                    .unwrap())
+            });
+            inner
+        });
+
+        let service = interceptor.supergraph_service(mock_service.boxed());
+
+        let mutation = r#"
+            mutation RecordLoan {
+              recordLoanRequested(
+                input: {
+                  Amount: 50000.0
+                  NationalID: "123456789"
+                  Name: "John Doe"
+                  Gender: "Male"
+                  Age: 35
+                  MaritalStatus: "Married"
+                  Dependents: 2
+                  EducationLevel: "Bachelor"
+                  EmployerName: "Tech Corp"
+                  JobTitle: "Engineer"
+                  JobSeniority: 5.0
+                  Income: 85000.0
+                  Address: {
+                    Street: "123 Main St"
+                    City: "San Francisco"
+                    Region: "CA"
+                    Country: "USA"
+                    PostalCode: "94102"
+                  }
+                  LoanRequestedTimestamp: "2024-09-29T00:00:00Z"
+                }
+              )
+            }
+        "#;
+
+        let request = build_supergraph_request(mutation, json!({}));
+
+        let mut response = service.oneshot(request).await.unwrap();
+        assert!(response.response.status().is_success());
+
+        // Consume the response stream to trigger the mutation persistence
+        while let Some(_) = response.response.body_mut().next().await {}
+
+        let recorded = sink.recorded();
+        assert_eq!(1, recorded.len());
+        let calls = &recorded[0];
+        assert_eq!(1, calls.len());
+        let call = &calls[0];
+        assert_eq!("recordLoanRequested", call.field_name);
+
+        // Verify loanId was extracted from response and set at top level
+        assert!(
+            call.loan_id.is_some(),
+            "loanId should be extracted from response"
+        );
+        assert_eq!(
+            "550e8400-e29b-41d4-a716-446655440000",
+            call.loan_id.as_ref().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn extracts_loan_id_from_input_arguments() {
+        let sink = StdArc::new(MockMutationSink::default());
+        let interceptor = MutationInterceptor::with_sink(sink.clone());
+
+        let mut mock_service = test::MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .returning(|req: supergraph::Request| {
+                let data = json!({
+                    "recordCreditChecked": {
+                        "LoanRequestID": "test-loan-456",
+                        "NationalID": "123456789",
+                        "Score": 750,
+                        "CreditCheckedTimestamp": "2024-09-29T00:00:00Z"
+                    }
+                });
+                Ok(supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .data(serde_json_bytes::to_value(data).unwrap())
+                    .build()
+                    .unwrap())
+            });
+        mock_service.expect_clone().return_once(|| {
+            let mut inner = test::MockSupergraphService::new();
+            inner.expect_call().returning(|req: supergraph::Request| {
+                let data = json!({
+                    "recordCreditChecked": {
+                        "LoanRequestID": "test-loan-456",
+                        "NationalID": "123456789",
+                        "Score": 750,
+                        "CreditCheckedTimestamp": "2024-09-29T00:00:00Z"
+                    }
+                });
+                Ok(supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .data(serde_json_bytes::to_value(data).unwrap())
+                    .build()
+                    .unwrap())
+            });
+            inner
+        });
+
+        let service = interceptor.supergraph_service(mock_service.boxed());
+
+        let mutation = r#"
+            mutation CheckCredit {
+              recordCreditChecked(
+                input: {
+                  loanId: "test-loan-456"
+                  NationalID: "123456789"
+                  Score: 750
+                  CreditCheckedTimestamp: "2024-09-29T00:00:00Z"
+                }
+              ) {
+                LoanRequestID
+                Score
+              }
+            }
+        "#;
+
+        let request = build_supergraph_request(mutation, json!({}));
+
+        let mut response = service.oneshot(request).await.unwrap();
+        assert!(response.response.status().is_success());
+
+        // Consume the response stream to trigger the mutation persistence
+        while let Some(_) = response.response.body_mut().next().await {}
+
+        let recorded = sink.recorded();
+        assert_eq!(1, recorded.len());
+        let calls = &recorded[0];
+        assert_eq!(1, calls.len());
+        let call = &calls[0];
+        assert_eq!("recordCreditChecked", call.field_name);
+
+        // Verify loanId was extracted from input arguments and set at top level
+        assert!(
+            call.loan_id.is_some(),
+            "loanId should be extracted from input arguments"
+        );
+        assert_eq!("test-loan-456", call.loan_id.as_ref().unwrap());
+    }
+
+    #[tokio::test]
+    async fn ignores_non_mutation_operations() {
+        let sink = StdArc::new(MockMutationSink::default());
+        let interceptor = MutationInterceptor::with_sink(sink.clone());
+
+        let mut mock_service = test::MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .returning(|req: supergraph::Request| {
+                Ok(supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .build()
+                    .unwrap())
+            });
+        mock_service.expect_clone().return_once(|| {
+            let mut inner = test::MockSupergraphService::new();
+            inner.expect_call().returning(|req: supergraph::Request| {
+                Ok(supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .build()
+                    .unwrap())
+            });
+            inner
+        });
+
+        let service = interceptor.supergraph_service(mock_service.boxed());
+
+        let query = "query { __typename }";
+        let request = build_supergraph_request(query, json!({}));
+
+        let response = service.oneshot(request).await.unwrap();
+        assert!(response.response.status().is_success());
+        assert!(sink.recorded().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_mutations_when_kurrent_is_unreachable_and_readiness_gating_enabled() {
+        let sink = StdArc::new(MockMutationSink {
+            unhealthy: true,
+            ..Default::default()
+        });
+        let interceptor = MutationInterceptor::with_sink_and_config(
+            sink.clone(),
+            plugin_config(json!({ "fail_readiness_on_disconnect": true })),
+        );
+
+        let mock_service = test::MockSupergraphService::new();
+        let service = interceptor.supergraph_service(mock_service.boxed());
+
+        let mutation = r#"
+            mutation CheckCredit {
+              recordCreditChecked(input: { loanId: "test-loan-789", Score: 700 }) {
+                LoanRequestID
+              }
+            }
+        "#;
+        let request = build_supergraph_request(mutation, json!({}));
+
+        let mut response = service.oneshot(request).await.unwrap();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.response.status());
+
+        let body = response.response.body_mut().next().await.unwrap();
+        assert_eq!(
+            Some(&serde_json_bytes::Value::from("KURRENTDB_UNAVAILABLE")),
+            body.errors[0].extensions.get("code")
+        );
+        assert!(sink.recorded().is_empty());
+    }
+
+    #[test]
+    fn operation_labels_are_capped() {
+        let labels = OperationLabels::default();
+        for i in 0..MAX_OPERATION_LABELS {
+            assert_eq!(format!("Op{i}"), labels.label(Some(&format!("Op{i}"))));
+        }
+
+        assert_eq!("other", labels.label(Some("OneTooMany")));
+        assert_eq!("Op0", labels.label(Some("Op0")));
+        assert_eq!("anonymous", labels.label(None));
+    }
+
+    #[tokio::test]
+    async fn rejects_mutations_while_the_persist_queue_is_full() {
+        let sink = StdArc::new(MockMutationSink {
+            queue_full: true,
+            ..Default::default()
+        });
+        let interceptor = MutationInterceptor::with_sink(sink.clone());
+
+        let mock_service = test::MockSupergraphService::new();
+        let service = interceptor.supergraph_service(mock_service.boxed());
+
+        let request = build_supergraph_request(
+            r#"mutation { recordCreditChecked(input: { loanId: "loan-1", Score: 700 }) { Score } }"#,
+            json!({}),
+        );
+
+        let mut response = service.oneshot(request).await.unwrap();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.response.status());
+
+        let body = response.response.body_mut().next().await.unwrap();
+        assert_eq!(
+            Some(&serde_json_bytes::Value::from("KURRENTDB_QUEUE_FULL")),
+            body.errors[0].extensions.get("code")
+        );
+        assert!(sink.recorded().is_empty());
+    }
+
+    #[test]
+    fn extracts_mutation_fields_spread_from_a_named_fragment() {
+        let mutation = r#"
+            mutation RecordDecisions($loanId: String!) {
+              ...DecisionFields
+            }
+
+            fragment DecisionFields on Mutation {
+              approval: recordLoanManuallyApproved(
+                input: { loanId: $loanId, ApprovedBy: "Ann" }
+              ) {
+                LoanRequestID
+              }
+              ... on Mutation {
+                recordCreditChecked(input: { loanId: $loanId, Score: 700 }) {
+                  Score
+                }
+              }
+            }
+        "#;
+
+        let mut variables = BytesMap::new();
+        variables.insert(
+            ByteString::from("loanId"),
+            serde_json_bytes::Value::from("loan-42"),
+        );
+
+        let calls = extract_mutations(mutation, &variables, &ExtractionOptions::default());
+
+        assert_eq!(2, calls.len());
+
+        assert_eq!("recordLoanManuallyApproved", calls[0].field_name);
+        assert_eq!(Some("approval".to_string()), calls[0].alias);
+        assert_eq!(Some("RecordDecisions".to_string()), calls[0].operation_name);
+        assert_eq!(Some("loan-42".to_string()), calls[0].loan_id);
+        assert_eq!(json!("Ann"), calls[0].arguments[0].value["ApprovedBy"]);
+        assert_eq!(
+            vec![SelectedField::new("LoanRequestID", "LoanRequestID")],
+            calls[0].selected_fields
+        );
+
+        assert_eq!("recordCreditChecked", calls[1].field_name);
+        assert_eq!(None, calls[1].alias);
+        assert_eq!(
+            vec![0, 1],
+            calls.iter().map(|c| c.sequence).collect::<Vec<_>>()
+        );
+        assert_eq!(json!(700), calls[1].arguments[0].value["Score"]);
+    }
+
+    #[tokio::test]
+    async fn attaches_schema_coordinate_when_enabled() {
+        let recorded = run_mutation(
+            json!({ "persist_schema_coordinate": true }),
+            CHECK_CREDIT,
+            credit_checked(),
+            |_| {},
+        )
+        .await;
+
+        assert_eq!(1, recorded.len());
+        assert_eq!(
+            Some(&json!("Mutation.recordCreditChecked")),
+            recorded[0][0].metadata.get("schema_coordinate")
+        );
+    }
+
+    #[tokio::test]
+    async fn records_the_owning_subgraph_when_enabled() {
+        let mutation = r#"
+            mutation Decide {
+              recordCreditChecked(input: { loanId: "loan-1", Score: 700 }) { Score }
+              recordLoanManuallyApproved(input: { loanId: "loan-1", ApprovedBy: "Ann" }) {
+                LoanRequestID
+              }
+            }
+        "#;
+        let response = json!({
+            "data": {
+                "recordCreditChecked": { "Score": 700 },
+                "recordLoanManuallyApproved": { "LoanRequestID": "loan-1" }
+            }
+        });
+
+        let recorded = run_mutation(
+            json!({ "record_owning_subgraph": true }),
+            mutation,
+            response,
+            |_| {},
+        )
+        .await;
+
+        assert_eq!(
+            Some(&json!("loan-origination")),
+            recorded[0][0].metadata.get("owning_subgraph")
+        );
+        assert_eq!(
+            Some(&json!("loan-decisions")),
+            recorded[0][1].metadata.get("owning_subgraph")
+        );
+    }
+
+    #[test]
+    fn directive_mode_only_extracts_fields_marked_with_persist() {
+        let mutation = r#"
+            mutation RecordLoan {
+              recordLoanRequested(input: { Name: "Jane" }) @persist(stream: "loans")
+              recordCreditChecked(input: { loanId: "loan-1", Score: 640 }) {
+                Score
+              }
+              recordAutomatedSummary(input: { loanId: "loan-1" }) @persist {
+                CreditScoreSummary
+              }
+            }
+        "#;
+        let options = ExtractionOptions {
+            persist_mode: PersistMode::Directive,
+            ..Default::default()
+        };
+
+        let calls = extract_mutations(mutation, &BytesMap::new(), &options);
+
+        assert_eq!(2, calls.len());
+        assert_eq!("recordLoanRequested", calls[0].field_name);
+        assert_eq!(Some("loans".to_string()), calls[0].stream_override);
+        assert_eq!("recordAutomatedSummary", calls[1].field_name);
+        assert_eq!(None, calls[1].stream_override);
+
+        let all = extract_mutations(mutation, &BytesMap::new(), &ExtractionOptions::default());
+        assert_eq!(3, all.len());
+    }
+
+    #[tokio::test]
+    async fn captures_scopes_from_jwt_claims_when_enabled() {
+        let recorded = run_mutation(
+            json!({ "capture_scopes": true }),
+            CHECK_CREDIT,
+            credit_checked(),
+            |request| {
+                let claims = json!({ "sub": "analyst-7", "scope": "loans:write credit:check" });
+                request
+                    .context
+                    .insert(JWT_CLAIMS_CONTEXT_KEY, claims)
+                    .unwrap();
+            },
+        )
+        .await;
+
+        assert_eq!(1, recorded.len());
+        assert_eq!(
+            Some(&json!(["loans:write", "credit:check"])),
+            recorded[0][0].metadata.get("scopes")
+        );
+    }
+
+    #[tokio::test]
+    async fn stamps_the_actor_from_the_configured_claim() {
+        let recorded = run_mutation(
+            json!({ "actor_claim": "email" }),
+            CHECK_CREDIT,
+            credit_checked(),
+            |request| {
+                let claims = json!({ "sub": "analyst-7", "email": "analyst@example.com" });
+                request
+                    .context
+                    .insert(JWT_CLAIMS_CONTEXT_KEY, claims)
+                    .unwrap();
+            },
+        )
+        .await;
+
+        assert_eq!(
+            Some("analyst@example.com".to_string()),
+            recorded[0][0].actor
+        );
+        let payload = serde_json::to_value(&recorded[0][0]).unwrap();
+        assert_eq!(json!("analyst@example.com"), payload["actor"]);
+    }
+
+    #[tokio::test]
+    async fn missing_actor_claim_is_anonymous_or_unset_per_config() {
+        let anonymous = run_mutation(
+            json!({ "actor_claim": "sub", "record_anonymous_actor": true }),
+            CHECK_CREDIT,
+            credit_checked(),
+            |_| {},
+        )
+        .await;
+        let unset = run_mutation(
+            json!({ "actor_claim": "sub" }),
+            CHECK_CREDIT,
+            credit_checked(),
+            |_| {},
+        )
+        .await;
+
+        assert_eq!(Some("anonymous".to_string()), anonymous[0][0].actor);
+        assert_eq!(None, unset[0][0].actor);
+    }
+
+    #[test]
+    fn extracts_loan_id_from_a_configured_input_argument_name() {
+        let mutation = r#"
+            mutation CheckCredit {
+              recordCreditChecked(data: { loanId: "loan-data-1", Score: 610 }) {
+                Score
+              }
+            }
+        "#;
+        let options = ExtractionOptions {
+            input_argument_names: vec!["payload".to_string(), "data".to_string()],
+            ..Default::default()
+        };
+
+        let calls = extract_mutations(mutation, &BytesMap::new(), &options);
+        assert_eq!(Some("loan-data-1".to_string()), calls[0].loan_id);
+
+        let defaults = extract_mutations(mutation, &BytesMap::new(), &ExtractionOptions::default());
+        assert_eq!(None, defaults[0].loan_id);
+    }
+
+    #[tokio::test]
+    async fn skips_persistence_when_response_matches_skip_condition() {
+        let config = json!({
+            "skip_when": [
+                { "field": "recordCreditChecked", "path": "result.changed", "equals": false }
+            ]
+        });
+        let mutation = r#"
+            mutation CheckCredit {
+              recordCreditChecked(input: { loanId: "test-loan-456", Score: 700 }) {
+                result {
+                  changed
+                }
+              }
+            }
+        "#;
+        let response =
+            json!({ "data": { "recordCreditChecked": { "result": { "changed": false } } } });
+
+        let recorded = run_mutation(config, mutation, response, |_| {}).await;
+
+        assert!(recorded.is_empty());
+    }
+
+    #[tokio::test]
+    async fn mutations_from_one_request_share_a_correlation_id() {
+        let sink = StdArc::new(MockMutationSink::default());
+        let interceptor = MutationInterceptor::with_sink(sink.clone());
+
+        let mut mock_service = test::MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .returning(|req: supergraph::Request| {
+                let data = json!({
+                    "recordCreditChecked": { "Score": 700 },
+                    "recordAutomatedSummary": { "Summary": "ok" }
+                });
+                Ok(supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .data(serde_json_bytes::to_value(data).unwrap())
+                    .build()
+                    .unwrap())
+            });
+
+        let service = interceptor.supergraph_service(mock_service.boxed());
+
+        let mutation = r#"
+            mutation Assess {
+              recordCreditChecked(input: { loanId: "loan-1", Score: 700 }) {
+                Score
+              }
+              recordAutomatedSummary(input: { loanId: "loan-1", Summary: "ok" }) {
+                Summary
+              }
+            }
+        "#;
+        let request = build_supergraph_request(mutation, json!({}));
+
+        let mut response = service.oneshot(request).await.unwrap();
+        while let Some(_) = response.response.body_mut().next().await {}
+
+        let correlation_id = response
+            .context
+            .get::<_, String>(CORRELATION_ID_CONTEXT_KEY)
+            .unwrap()
+            .expect("correlation id in context");
+        let recorded = sink.recorded();
+        assert_eq!(2, recorded[0].len());
+        for (index, call) in recorded[0].iter().enumerate() {
+            assert_eq!(
+                Some(&json!(correlation_id)),
+                call.metadata.get(CORRELATION_ID_METADATA_KEY)
+            );
+            assert!(!call.metadata.contains_key("correlation_id"));
+            assert_eq!(Some(&json!(2)), call.metadata.get("total"));
+            assert_eq!(index, call.sequence);
+        }
+    }
+
+    #[tokio::test]
+    async fn mutations_are_chained_by_causation_id_in_document_order() {
+        let sink = StdArc::new(MockMutationSink::default());
+        let interceptor = MutationInterceptor::with_sink_and_config(
+            sink.clone(),
+            plugin_config(json!({ "causation_id_header": "x-causation-id" })),
+        );
+
+        let mut mock_service = test::MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .returning(|req: supergraph::Request| {
+                let data = json!({
+                    "recordCreditChecked": { "Score": 700 },
+                    "recordAutomatedSummary": { "Summary": "ok" }
+                });
+                Ok(supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .data(serde_json_bytes::to_value(data).unwrap())
+                    .build()
+                    .unwrap())
+            });
+
+        let service = interceptor.supergraph_service(mock_service.boxed());
+        let mut request = build_supergraph_request(
+            r#"
+            mutation Assess {
+              recordCreditChecked(input: { loanId: "loan-1", Score: 700 }) {
+                Score
+              }
+              recordAutomatedSummary(input: { loanId: "loan-1", Summary: "ok" }) {
+                Summary
+              }
+            }
+            "#,
+            json!({}),
+        );
+        request
+            .supergraph_request
+            .headers_mut()
+            .insert("x-causation-id", http::HeaderValue::from_static("cmd-1"));
+
+        let mut response = service.oneshot(request).await.unwrap();
+        while let Some(_) = response.response.body_mut().next().await {}
+
+        let correlation_id = response
+            .context
+            .get::<_, String>(CORRELATION_ID_CONTEXT_KEY)
+            .unwrap()
+            .expect("correlation id in context");
+        let calls = &sink.recorded()[0];
+        for call in calls {
+            assert_eq!(
+                Some(&json!(correlation_id)),
+                call.metadata.get(CORRELATION_ID_METADATA_KEY)
+            );
+        }
+        assert_eq!(
+            Some(&json!("cmd-1")),
+            calls[0].metadata.get(CAUSATION_ID_METADATA_KEY)
+        );
+        let first_event_id = calls[0].metadata[EVENT_ID_METADATA_KEY].clone();
+        assert!(Uuid::parse_str(first_event_id.as_str().unwrap()).is_ok());
+        assert_eq!(
+            Some(&first_event_id),
+            calls[1].metadata.get(CAUSATION_ID_METADATA_KEY)
+        );
+        assert_ne!(first_event_id, calls[1].metadata[EVENT_ID_METADATA_KEY]);
+    }
+
+    #[tokio::test]
+    async fn disabled_persistence_counts_mutations_without_writing_them() {
+        let logs = CapturedLogs::default();
+        let _subscriber = tracing::subscriber::set_default(logs.clone());
+
+        let recorded = run_mutation(
+            json!({ "enabled": false }),
+            CHECK_CREDIT,
+            credit_checked(),
+            |_| {},
+        )
+        .await;
+
+        assert!(recorded.is_empty());
+        assert!(logs.contains("histogram.kurrent_mutations_per_request=1"));
+        assert!(logs.contains("monotonic_counter.kurrent_mutations_not_persisted_total=1"));
+    }
+
+    const DUPLICATE_OPERATIONS: &str = r#"
+        mutation CheckCredit {
+          recordCreditChecked(input: { loanId: "loan-first", Score: 700 }) {
+            Score
+          }
+        }
+
+        mutation CheckCredit {
+          recordCreditChecked(input: { loanId: "loan-second", Score: 500 }) {
+            Score
+          }
+        }
+    "#;
+
+    #[test]
+    fn first_operation_wins_when_names_are_duplicated() {
+        let options = ExtractionOptions {
+            duplicate_operations: DuplicateOperations::FirstWins,
+            ..Default::default()
+        };
+
+        let calls = extract_mutations(DUPLICATE_OPERATIONS, &BytesMap::new(), &options);
+
+        assert_eq!(
+            vec!["CheckCredit".to_string()],
+            duplicate_operation_names(DUPLICATE_OPERATIONS)
+        );
+        assert_eq!(1, calls.len());
+        assert_eq!(Some("loan-first".to_string()), calls[0].loan_id);
+    }
+
+    #[tokio::test]
+    async fn rejects_duplicate_operation_names_by_default() {
+        let sink = StdArc::new(MockMutationSink::default());
+        let interceptor = MutationInterceptor::with_sink(sink.clone());
+
+        let mock_service = test::MockSupergraphService::new();
+        let service = interceptor.supergraph_service(mock_service.boxed());
+        let request = build_supergraph_request(DUPLICATE_OPERATIONS, json!({}));
+
+        let mut response = service.oneshot(request).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, response.response.status());
+
+        let body = response.response.body_mut().next().await.unwrap();
+        assert_eq!(
+            Some(&serde_json_bytes::Value::from("DUPLICATE_OPERATION_NAME")),
+            body.errors[0].extensions.get("code")
+        );
+        assert!(sink.recorded().is_empty());
+    }
+
+    #[test]
+    fn sampled_flag_reflects_the_active_span() {
+        use opentelemetry::trace::{SpanContext, SpanId, TraceFlags, TraceId, TraceState};
+
+        let span_context = |flags| {
+            SpanContext::new(
+                TraceId::from_u128(1),
+                SpanId::from_u64(1),
+                flags,
+                true,
+                TraceState::default(),
+            )
+        };
+
+        {
+            let _guard = opentelemetry::Context::current()
+                .with_remote_span_context(span_context(TraceFlags::SAMPLED))
+                .attach();
+            assert_eq!(Some(true), trace_sampled(&HeaderMap::new()));
+        }
+        {
+            let _guard = opentelemetry::Context::current()
+                .with_remote_span_context(span_context(TraceFlags::default()))
+                .attach();
+            assert_eq!(Some(false), trace_sampled(&HeaderMap::new()));
+        }
+    }
+
+    #[test]
+    fn sampled_flag_falls_back_to_the_traceparent_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(None, trace_sampled(&headers));
+
+        headers.insert(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(Some(true), trace_sampled(&headers));
+
+        headers.insert(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00"
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(Some(false), trace_sampled(&headers));
+    }
+
+    #[test]
+    fn traceparent_comes_from_the_header_or_is_generated_when_configured() {
+        let incoming = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let mut headers = HeaderMap::new();
+        headers.insert("traceparent", incoming.parse().unwrap());
+
+        assert_eq!(None, traceparent(&headers, TraceparentMode::Off));
+        assert_eq!(
+            Some(incoming.to_string()),
+            traceparent(&headers, TraceparentMode::Propagate)
+        );
+
+        headers.insert("traceparent", "not-a-traceparent".parse().unwrap());
+        assert_eq!(None, traceparent(&headers, TraceparentMode::Propagate));
+        let generated = traceparent(&headers, TraceparentMode::Generate).unwrap();
+        assert!(is_valid_traceparent(&generated), "{generated}");
+    }
+
+    #[test]
+    fn traceparent_prefers_the_active_span() {
+        use opentelemetry::trace::{SpanContext, SpanId, TraceFlags, TraceId, TraceState};
+
+        let _guard = opentelemetry::Context::current()
+            .with_remote_span_context(SpanContext::new(
+                TraceId::from_u128(1),
+                SpanId::from_u64(2),
+                TraceFlags::SAMPLED,
+                true,
+                TraceState::default(),
+            ))
+            .attach();
+
+        assert_eq!(
+            Some("00-00000000000000000000000000000001-0000000000000002-01".to_string()),
+            traceparent(&HeaderMap::new(), TraceparentMode::Propagate)
+        );
+    }
+
+    #[test]
+    fn tags_enum_literals_inside_lists_and_objects_when_enabled() {
+        let mutation = r#"
+            mutation Decide {
+              recordLoanManualDecision(input: { loanId: "loan-1", Decision: APPROVED, Reasons: [INCOME, "manual"] }) {
+                LoanRequestID
+              }
+            }
+        "#;
+        let tagged = ExtractionOptions {
+            enum_tagging: Some(Arc::new(EnumTypes::default())),
+            ..Default::default()
+        };
+
+        let calls = extract_mutations(mutation, &BytesMap::new(), &tagged);
+        let plain = extract_mutations(mutation, &BytesMap::new(), &ExtractionOptions::default());
+
+        assert_eq!(
+            json!({ "__enum": "APPROVED" }),
+            calls[0].arguments[0].value["Decision"]
+        );
+        assert_eq!(
+            json!([{ "__enum": "INCOME" }, "manual"]),
+            calls[0].arguments[0].value["Reasons"]
+        );
+        assert_eq!(json!("APPROVED"), plain[0].arguments[0].value["Decision"]);
+    }
+
+    #[test]
+    fn tags_enums_inside_variable_objects_using_the_schema() {
+        let sdl = r#"
+            enum Decision { APPROVED DECLINED }
+            input DecisionInput { loanId: String!, Decision: Decision!, History: [Decision!] }
+        "#;
+        let mutation = r#"
+            mutation Decide($input: DecisionInput!) {
+              recordLoanManualDecision(input: $input) {
+                LoanRequestID
+              }
+            }
+        "#;
+        let mut variables = BytesMap::new();
+        variables.insert(
+            ByteString::from("input"),
+            serde_json_bytes::to_value(json!({
+                "loanId": "loan-1",
+                "Decision": "DECLINED",
+                "History": ["APPROVED"]
+            }))
+            .unwrap(),
+        );
+        let options = ExtractionOptions {
+            enum_tagging: Some(Arc::new(EnumTypes::from_sdl(sdl))),
+            ..Default::default()
+        };
+
+        let calls = extract_mutations(mutation, &variables, &options);
+
+        assert_eq!(
+            json!({
+                "loanId": "loan-1",
+                "Decision": { "__enum": "DECLINED" },
+                "History": [{ "__enum": "APPROVED" }]
+            }),
+            calls[0].arguments[0].value
+        );
+        assert_eq!(Some("loan-1".to_string()), calls[0].loan_id);
+    }
+
+    #[test]
+    fn raw_query_and_variables_are_stored_within_the_byte_limit() {
+        let query = "mutation CheckCredit($input: CreditCheckedInput!) { recordCreditChecked(input: $input) { Score } }";
+        let mut variables = BytesMap::new();
+        variables.insert(
+            ByteString::from("input"),
+            serde_json_bytes::to_value(json!({ "loanId": "loan-1", "Score": 700 })).unwrap(),
+        );
+
+        let metadata = raw_query_metadata(query, &variables, 1024);
+
+        assert_eq!(Some(&json!(query)), metadata.get("raw_query"));
+        assert_eq!(
+            Some(&json!({ "input": { "loanId": "loan-1", "Score": 700 } })),
+            metadata.get("raw_variables")
+        );
+        assert!(!metadata.contains_key("raw_query_truncated"));
+    }
+
+    #[test]
+    fn oversized_raw_query_is_truncated_and_variables_skipped() {
+        let query = "mutation CheckCredit($input: CreditCheckedInput!) { recordCreditChecked(input: $input) { Score } }";
+        let mut variables = BytesMap::new();
+        variables.insert(
+            ByteString::from("input"),
+            serde_json_bytes::to_value(json!({ "loanId": "loan-1", "Score": 700 })).unwrap(),
+        );
+
+        let metadata = raw_query_metadata(query, &variables, 20);
+
+        assert_eq!(
+            Some(&json!("mutation CheckCredit")),
+            metadata.get("raw_query")
+        );
+        assert_eq!(Some(&json!(true)), metadata.get("raw_query_truncated"));
+        assert!(!metadata.contains_key("raw_variables"));
+    }
+
+    #[tokio::test]
+    async fn variables_are_stored_in_the_metadata_when_configured() {
+        let sink = StdArc::new(MockMutationSink::default());
+        let interceptor = MutationInterceptor::with_sink_and_config(
+            sink.clone(),
+            plugin_config(json!({ "store_variables": true })),
+        );
+
+        let mut mock_service = test::MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .returning(|req: supergraph::Request| {
+                let data = json!({ "recordCreditChecked": { "Score": 700 } });
+                Ok(supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .data(serde_json_bytes::to_value(data).unwrap())
+                    .build()
+                    .unwrap())
+            });
+
+        let service = interceptor.supergraph_service(mock_service.boxed());
+        let request = build_supergraph_request(
+            r#"mutation CheckCredit($score: Int!) {
+              recordCreditChecked(input: { loanId: "loan-1", Score: $score }) { Score }
+            }"#,
+            json!({ "score": 700 }),
+        );
+
+        let mut response = service.oneshot(request).await.unwrap();
+        while let Some(_) = response.response.body_mut().next().await {}
+
+        let recorded = sink.recorded();
+        assert_eq!(
+            Some(&json!({ "score": 700 })),
+            recorded[0][0].metadata.get("variables")
+        );
+    }
+
+    /// Sink whose appends take `delay`, recording when they complete.
+    #[derive(Default)]
+    struct SlowSink {
+        delay: Duration,
+        persisted: StdArc<std::sync::atomic::AtomicBool>,
+    }
+
+    impl MutationSink for SlowSink {
+        fn persist_mutations(&self, _calls: Vec<MutationCall>) {}
+
+        fn persist_and_wait(
+            &self,
+            _calls: Vec<MutationCall>,
+        ) -> futures::future::BoxFuture<'static, Result<(), KurrentError>> {
+            let delay = self.delay;
+            let persisted = self.persisted.clone();
+            Box::pin(async move {
+                tokio::time::sleep(delay).await;
+                persisted.store(true, std::sync::atomic::Ordering::SeqCst);
+                Ok(())
+            })
+        }
+    }
+
+    /// Whether the append had completed when the client received the response.
+    async fn persisted_when_response_arrives(sink: StdArc<SlowSink>, config: Value) -> bool {
+        let interceptor =
+            MutationInterceptor::with_sink_and_config(sink.clone(), plugin_config(config));
+
+        let mut mock_service = test::MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .returning(|req: supergraph::Request| {
+                let data = json!({ "recordCreditChecked": { "Score": 700 } });
+                Ok(supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .data(serde_json_bytes::to_value(data).unwrap())
+                    .build()
+                    .unwrap())
+            });
+
+        let service = interceptor.supergraph_service(mock_service.boxed());
+        let request = build_supergraph_request(
+            r#"mutation { recordCreditChecked(input: { loanId: "loan-1", Score: 700 }) { Score } }"#,
+            json!({}),
+        );
+
+        let mut response = service.oneshot(request).await.unwrap();
+        assert!(response.response.body_mut().next().await.is_some());
+        sink.persisted.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn persist_before_response_forwards_the_body_after_the_append() {
+        let sink = StdArc::new(SlowSink {
+            delay: Duration::from_millis(50),
+            ..Default::default()
+        });
+
+        let persisted =
+            persisted_when_response_arrives(sink, json!({ "persist_before_response": true })).await;
+
+        assert!(persisted);
+    }
+
+    #[tokio::test]
+    async fn persist_before_response_timeout_forwards_slow_appends() {
+        let sink = StdArc::new(SlowSink {
+            delay: Duration::from_millis(200),
+            ..Default::default()
+        });
+        let config = json!({
+            "persist_before_response": true,
+            "persist_before_response_timeout_ms": 10
+        });
+
+        let persisted = persisted_when_response_arrives(sink.clone(), config).await;
+
+        assert!(!persisted);
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert!(sink.persisted.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[test]
+    fn failing_to_stash_pending_mutations_does_not_panic() {
+        struct Unserializable;
+
+        impl serde::Serialize for Unserializable {
+            fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+                Err(serde::ser::Error::custom("cannot serialize"))
+            }
+        }
+
+        let context = Context::new();
+
+        assert!(!stash_pending_mutations(&context, Unserializable));
+        assert!(!context.contains_key("pending_mutations"));
+        assert!(stash_pending_mutations(
+            &context,
+            Vec::<MutationCall>::new()
+        ));
+    }
+
+    /// Records the credit score a mutation returned as event metadata.
+    #[derive(Debug)]
+    struct ScoreEnricher;
+
+    impl Enricher for ScoreEnricher {
+        fn enrich(&self, call: &mut MutationCall, response: &Value) {
+            if let Some(score) = response.get("Score") {
+                call.metadata.insert("score".to_string(), score.clone());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_enrichers_run_after_the_built_in_one() {
+        let sink = StdArc::new(MockMutationSink::default());
+        let interceptor = MutationInterceptor::with_sink_and_config(
+            sink.clone(),
+            plugin_config(
+                json!({ "id_from_response": { "recordCreditChecked": "LoanRequestID" } }),
+            ),
+        )
+        .with_enricher(StdArc::new(ScoreEnricher));
+
+        let mut mock_service = test::MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .returning(|req: supergraph::Request| {
+                let data = json!({
+                    "recordCreditChecked": { "LoanRequestID": "loan-7", "Score": 700 }
+                });
+                Ok(supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .data(serde_json_bytes::to_value(data).unwrap())
+                    .build()
+                    .unwrap())
+            });
+
+        let service = interceptor.supergraph_service(mock_service.boxed());
+        let request = build_supergraph_request(
+            r#"mutation { recordCreditChecked(input: { Score: 700 }) { LoanRequestID Score } }"#,
+            json!({}),
+        );
+        let mut response = service.oneshot(request).await.unwrap();
+        while let Some(_) = response.response.body_mut().next().await {}
+
+        let recorded = sink.recorded();
+        let call = &recorded[0][0];
+        assert_eq!(Some("loan-7".to_string()), call.loan_id);
+        assert_eq!(Some(&json!(700)), call.metadata.get("score"));
+    }
+
+    #[tokio::test]
+    async fn configured_response_headers_are_recorded_in_the_metadata() {
+        let sink = StdArc::new(MockMutationSink::default());
+        let interceptor = MutationInterceptor::with_sink_and_config(
+            sink.clone(),
+            plugin_config(json!({ "capture_response_headers": ["ETag", "x-missing"] })),
+        );
+
+        let mut mock_service = test::MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .returning(|req: supergraph::Request| {
+                let data = json!({ "recordCreditChecked": { "Score": 700 } });
+                let mut response = supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .data(serde_json_bytes::to_value(data).unwrap())
+                    .build()
+                    .unwrap();
+                response
+                    .response
+                    .headers_mut()
+                    .insert("etag", http::HeaderValue::from_static("\"v2\""));
+                Ok(response)
+            });
+
+        let service = interceptor.supergraph_service(mock_service.boxed());
+        let request = build_supergraph_request(
+            r#"mutation { recordCreditChecked(input: { loanId: "loan-1", Score: 700 }) { Score } }"#,
+            json!({}),
+        );
+        let mut response = service.oneshot(request).await.unwrap();
+        while let Some(_) = response.response.body_mut().next().await {}
+
+        let recorded = sink.recorded();
+        assert_eq!(
+            Some(&json!({ "etag": "\"v2\"" })),
+            recorded[0][0].metadata.get("response_headers")
+        );
+    }
+
+    #[test]
+    fn absent_response_headers_are_not_recorded() {
+        let names = vec!["etag".to_string()];
+
+        assert_eq!(None, response_headers_metadata(&HeaderMap::new(), &names));
+    }
+
+    #[tokio::test]
+    async fn memory_sink_records_mutations_without_kurrentdb() {
+        let init = PluginInit::fake_builder()
+            .config(plugin_config(json!({ "sink": "memory" })))
+            .supergraph_sdl(StdArc::new(
+                include_str!("../../supergraph-schema.graphql").to_string(),
+            ))
+            .build();
+        let interceptor = MutationInterceptor::new(init).await.unwrap();
+
+        let mut mock_service = test::MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .returning(|req: supergraph::Request| {
+                let data = json!({ "recordCreditChecked": { "Score": 700 } });
+                Ok(supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .data(serde_json_bytes::to_value(data).unwrap())
+                    .build()
+                    .unwrap())
+            });
+
+        let service = interceptor.supergraph_service(mock_service.boxed());
+        let request = build_supergraph_request(
+            r#"mutation { recordCreditChecked(input: { loanId: "loan-memory", Score: 700 }) { Score } }"#,
+            json!({}),
+        );
+        let mut response = service.oneshot(request).await.unwrap();
+        while let Some(_) = response.response.body_mut().next().await {}
+
+        let recorded = MemorySink::shared().take();
+        assert!(
+            recorded
+                .iter()
+                .any(|call| call.loan_id.as_deref() == Some("loan-memory"))
+        );
+    }
+
+    #[test]
+    fn router_values_convert_to_json_unchanged() {
+        let value = json!({
+            "loanId": "loan-1",
+            "Score": 700,
+            "Rate": 4.5,
+            "Approved": true,
+            "Notes": null,
+            "History": [{ "Score": 650 }, "manual"]
+        });
+
+        assert_eq!(
+            value,
+            bytes_to_json(&serde_json_bytes::to_value(value.clone()).unwrap())
+        );
+    }
+
+    #[test]
+    fn variables_above_max_event_bytes_are_not_stored() {
+        let mut variables = BytesMap::new();
+        variables.insert(
+            ByteString::from("score"),
+            serde_json_bytes::to_value(json!(700)).unwrap(),
+        );
+
+        assert_eq!(
+            Some(json!({ "score": 700 })),
+            variables_metadata(&variables, None)
+        );
+        assert_eq!(None, variables_metadata(&variables, Some(5)));
+        assert_eq!(None, variables_metadata(&BytesMap::new(), None));
+    }
+
+    const SUBSCRIPTION_AND_MUTATION: &str = r#"
+        subscription OnCreditChecked {
+          creditChecked {
+            Score
+          }
+        }
+
+        mutation CheckCredit {
+          recordCreditChecked(input: { loanId: "loan-1", Score: 700 }) {
+            Score
+          }
+        }
+    "#;
+
+    #[test]
+    fn subscriptions_never_produce_mutation_calls() {
+        let options = ExtractionOptions::default();
+
+        let subscription = extract_operation_mutations(
+            SUBSCRIPTION_AND_MUTATION,
+            Some("OnCreditChecked"),
+            &BytesMap::new(),
+            &options,
+        );
+        let mutation = extract_operation_mutations(
+            SUBSCRIPTION_AND_MUTATION,
+            Some("CheckCredit"),
+            &BytesMap::new(),
+            &options,
+        );
+        let whole_document =
+            extract_mutations(SUBSCRIPTION_AND_MUTATION, &BytesMap::new(), &options);
+
+        assert!(subscription.is_empty());
+        assert_eq!(1, mutation.len());
+        assert_eq!("recordCreditChecked", mutation[0].field_name);
+        assert_eq!(1, whole_document.len());
+    }
+
+    #[tokio::test]
+    async fn executing_the_subscription_of_a_mixed_document_does_not_persist() {
+        let sink = StdArc::new(MockMutationSink::default());
+        let interceptor = MutationInterceptor::with_sink(sink.clone());
+
+        let mut mock_service = test::MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .returning(|req: supergraph::Request| {
+                let data = json!({ "creditChecked": { "Score": 700 } });
+                Ok(supergraph::Response::fake_builder()
+                    .context(req.context)
+                    .data(serde_json_bytes::to_value(data).unwrap())
+                    .build()
+                    .unwrap())
+            });
+
+        let service = interceptor.supergraph_service(mock_service.boxed());
+
+        let request = supergraph::Request::fake_builder()
+            .query(SUBSCRIPTION_AND_MUTATION.to_string())
+            .operation_name("OnCreditChecked")
+            .build()
+            .unwrap();
+
+        let mut response = service.oneshot(request).await.unwrap();
+        while let Some(_) = response.response.body_mut().next().await {}
+
+        assert!(sink.recorded().is_empty());
+    }
+
+    #[test]
+    fn extracts_from_an_already_parsed_document() {
+        let document = Parser::new(SUBSCRIPTION_AND_MUTATION).parse().document();
+
+        let calls = extract_document_mutations(
+            &document,
+            Some("CheckCredit"),
+            &BytesMap::new(),
+            &ExtractionOptions::default(),
+        );
+
+        assert_eq!(1, calls.len());
+        assert_eq!(Some("loan-1".to_string()), calls[0].loan_id);
+    }
+
+    #[test]
+    fn introspection_fields_are_not_mutations() {
+        let mutation = r#"
+            mutation CheckCredit {
+              __typename
+              recordCreditChecked(input: { loanId: "loan-1" }) { Score }
+            }
+        "#;
+
+        let calls = extract_mutations(mutation, &BytesMap::new(), &ExtractionOptions::default());
+
+        assert_eq!(1, calls.len());
+        assert_eq!("recordCreditChecked", calls[0].field_name);
+    }
+
+    #[tokio::test]
+    async fn unnamed_mutation_fields_never_reach_the_sink() {
+        let recorded = run_mutation(
+            json!({}),
+            r#"mutation Broken { result: (input: { loanId: "loan-1" }) { id } }"#,
+            json!({ "data": { "": null } }),
+            |_| {},
+        )
+        .await;
+
+        assert!(recorded.is_empty());
+    }
+
+    #[test]
+    fn list_inputs_become_one_call_per_element() {
+        let mutation = r#"
+            mutation CreateLoans {
+              createLoans(input: [
+                { loanId: "loan-1", amount: 1000 }
+                { loanId: "loan-2", amount: 2000 }
+                { loanId: "loan-3", amount: 3000 }
+              ]) { id }
+            }
+        "#;
+        let config: KurrentConfig =
+            serde_json::from_value(json!({ "stream_naming": "category", "category": "loan" }))
+                .unwrap();
+
+        let calls = extract_mutations(mutation, &BytesMap::new(), &ExtractionOptions::default());
+
+        assert_eq!(3, calls.len());
+        let streams: Vec<String> = calls
+            .iter()
+            .map(|call| crate::plugins::kurrent_mapper::stream_name(call, &config))
+            .collect();
+        assert_eq!(vec!["loan-loan-1", "loan-loan-2", "loan-loan-3"], streams);
+        assert_eq!(Some(&json!(2000)), calls[1].argument_value("input.amount"));
+        assert_eq!(Some(&json!(1)), calls[1].metadata.get("input_index"));
+        assert_eq!(
+            vec![0, 1, 2],
+            calls.iter().map(|c| c.sequence).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn extraction_tolerates_a_partially_parsed_document() {
+        let ast = Parser::new(
+            r#"mutation CheckCredit {
+              recordCreditChecked(input: { loanId: "loan-1" }, note: ) { Score }
+            }"#,
+        )
+        .parse();
+        assert!(ast.errors().next().is_some());
+
+        let calls = extract_document_mutations(
+            &ast.document(),
+            None,
+            &BytesMap::new(),
+            &ExtractionOptions::default(),
+        );
+
+        assert_eq!(1, calls.len());
+        assert_eq!("recordCreditChecked", calls[0].field_name);
+        assert_eq!(Some("loan-1".to_string()), calls[0].loan_id);
+        assert!(calls[0].argument_value("note").is_none_or(Value::is_null));
+    }
+
+    #[tokio::test]
+    async fn identical_mutations_in_one_operation_persist_once_when_deduplicating() {
+        let mutation = r#"
+            mutation CheckCredit {
+              first: recordCreditChecked(input: { loanId: "loan-1", Score: 700 }) {
+                Score
+              }
+              second: recordCreditChecked(input: { loanId: "loan-1", Score: 700 }) {
+                Score
+              }
+              other: recordCreditChecked(input: { loanId: "loan-1", Score: 650 }) {
+                Score
+              }
+            }
+        "#;
+        let response = json!({
+            "data": {
+                "first": { "Score": 700 },
+                "second": { "Score": 700 },
+                "other": { "Score": 650 }
+            }
+        });
+
+        let recorded = run_mutation(
+            json!({ "dedup_within_batch": true }),
+            mutation,
+            response,
+            |_| {},
+        )
+        .await;
+
+        assert_eq!(2, recorded[0].len());
+        assert_eq!(Some("first".to_string()), recorded[0][0].alias);
+        assert_eq!(Some("other".to_string()), recorded[0][1].alias);
+    }
+
+    #[tokio::test]
+    async fn partial_success_policy_decides_what_of_a_partial_response_is_persisted() {
+        let mutation = r#"
+            mutation CheckCredit {
+              first: recordCreditChecked(input: { loanId: "loan-1", Score: 700 }) { Score }
+              second: recordCreditChecked(input: { loanId: "loan-2", Score: 650 }) { Score }
+            }
+        "#;
+        let response = json!({
+            "data": { "first": { "Score": 700 }, "second": null },
+            "errors": [{ "message": "credit bureau unavailable", "path": ["second"] }]
+        });
+        let run = |policy: &str| {
+            run_mutation(
+                json!({ "partial_success_policy": policy }),
+                mutation,
+                response.clone(),
+                |_| {},
+            )
+        };
+
+        let all = run("persist_all").await;
+        let without_errored = run("persist_without_errored_fields").await;
+        let skipped = run("skip").await;
+
+        assert_eq!(2, all[0].len());
+        assert_eq!(1, without_errored[0].len());
+        assert_eq!(Some("first".to_string()), without_errored[0][0].alias);
+        assert!(skipped.is_empty());
+    }
+
+    #[tokio::test]
+    async fn deferred_responses_are_persisted_once_with_the_merged_data() {
+        let sink = StdArc::new(MockMutationSink::default());
+        let interceptor = MutationInterceptor::with_sink(sink.clone());
+
+        let mut mock_service = test::MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .returning(|req: supergraph::Request| {
+                let chunks: Vec<graphql::Response> = vec![
+                    serde_json::from_value(json!({
+                        "data": { "recordCreditChecked": { "Score": 700 } },
+                        "hasNext": true
+                    }))
+                    .unwrap(),
+                    serde_json::from_value(json!({
+                        "incremental": [{
+                            "data": { "LoanRequestID": "loan-1" },
+                            "path": ["recordCreditChecked"]
+                        }],
+                        "hasNext": false
+                    }))
+                    .unwrap(),
+                ];
+                Ok(supergraph::Response {
+                    response: http::Response::new(Box::pin(futures::stream::iter(chunks))),
+                    context: req.context,
+                })
+            });
+
+        let service = interceptor.supergraph_service(mock_service.boxed());
+        let mutation = r#"
+            mutation CheckCredit {
+              recordCreditChecked(input: { loanId: "loan-1", Score: 700 }) {
+                Score
+                ... @defer { LoanRequestID }
+              }
+            }
+        "#;
+        let request = build_supergraph_request(mutation, json!({}));
+
+        let mut response = service.oneshot(request).await.unwrap();
+        let mut chunks = 0;
+        while let Some(_) = response.response.body_mut().next().await {
+            chunks += 1;
+        }
+
+        let recorded = sink.recorded();
+        assert_eq!(2, chunks);
+        assert_eq!(1, recorded.len());
+        assert_eq!(
+            Some(&json!({ "Score": 700, "LoanRequestID": "loan-1" })),
+            recorded[0][0].argument_value("responseData")
+        );
+    }
+
+    #[tokio::test]
+    async fn responses_without_data_are_persisted_only_when_configured() {
+        let persisted = run_mutation(
+            json!({ "persist_without_data": true }),
+            CHECK_CREDIT,
+            json!({}),
+            |_| {},
+        )
+        .await;
+        let skipped = run_mutation(json!({}), CHECK_CREDIT, json!({}), |_| {}).await;
+
+        assert_eq!(1, persisted.len());
+        assert_eq!("recordCreditChecked", persisted[0][0].field_name);
+        assert!(
+            persisted[0][0]
+                .arguments
+                .iter()
+                .all(|arg| arg.name != "responseData")
+        );
+        assert!(skipped.is_empty());
+    }
+
+    #[test]
+    fn malformed_operations_report_parse_errors() {
+        let malformed = r#"
+            mutation CheckCredit {
+              recordCreditChecked(input: { loanId: "loan-1", Score: 700 } {
+                Score
+              }
+            }
+        "#;
+        let valid = r#"mutation CheckCredit { recordCreditChecked(input: { loanId: "loan-1" }) { Score } }"#;
+
+        let errors = describe_parse_errors(&Parser::new(malformed).parse());
+
+        assert!(!errors.is_empty());
+        assert!(errors[0].contains("at offset"));
+        assert!(describe_parse_errors(&Parser::new(valid).parse()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn selected_response_extensions_are_captured() {
+        let response = json!({
+            "data": { "recordCreditChecked": { "Score": 700 } },
+            "extensions": { "serverVersion": 42, "tracing": { "duration": 12 } }
+        });
+
+        let selected = run_mutation(
+            json!({ "capture_extensions": ["serverVersion"] }),
+            CHECK_CREDIT,
+            response.clone(),
+            |_| {},
+        )
+        .await;
+        let all = run_mutation(
+            json!({ "capture_extensions": ["*"] }),
+            CHECK_CREDIT,
+            response.clone(),
+            |_| {},
+        )
+        .await;
+        let none = run_mutation(json!({}), CHECK_CREDIT, response, |_| {}).await;
+
+        assert_eq!(
+            json!({ "serverVersion": 42 }),
+            Value::Object(selected[0][0].extensions.clone())
+        );
+        assert_eq!(
+            json!({ "serverVersion": 42, "tracing": { "duration": 12 } }),
+            Value::Object(all[0][0].extensions.clone())
+        );
+        assert!(none[0][0].extensions.is_empty());
+    }
+
+    #[test]
+    fn absent_extensions_leave_calls_unchanged() {
+        let mut calls = vec![MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            ..Default::default()
+        }];
+
+        capture_extensions(&mut calls, &BytesMap::new(), &["*".to_string()]);
+
+        assert!(calls[0].extensions.is_empty());
+    }
+
+    /// Request setup sending `tenant` in the `x-tenant-id` header.
+    fn tenant_header(tenant: &'static str) -> impl FnOnce(&mut supergraph::Request) {
+        move |request| {
+            request
+                .supergraph_request
+                .headers_mut()
+                .insert("x-tenant-id", http::HeaderValue::from_static(tenant));
+        }
+    }
+
+    #[test]
+    fn idempotency_key_comes_from_the_argument_else_the_header() {
+        let capture = IdempotencyKeyCapture {
+            header: Some("idempotency-key".to_string()),
+            argument: Some("input.requestId".to_string()),
+        };
+        let call = |input: Value| MutationCall {
+            field_name: "recordCreditChecked".to_string(),
+            arguments: vec![MutationArg {
+                name: "input".to_string(),
+                value: input,
+            }],
+            ..Default::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "idempotency-key",
+            http::HeaderValue::from_static("from-header"),
+        );
+
+        assert_eq!(
+            Some("from-argument".to_string()),
+            capture.resolve(&call(json!({ "requestId": "from-argument" })), &headers)
+        );
+        assert_eq!(
+            Some("from-header".to_string()),
+            capture.resolve(&call(json!({})), &headers)
+        );
+        assert_eq!(None, capture.resolve(&call(json!({})), &HeaderMap::new()));
+    }
+
+    #[tokio::test]
+    async fn tenant_is_taken_from_the_configured_header() {
+        let config = json!({ "tenant_header": "x-tenant-id" });
+
+        let acme = run_mutation(
+            config.clone(),
+            CHECK_CREDIT,
+            credit_checked(),
+            tenant_header("acme"),
+        )
+        .await;
+        let globex = run_mutation(
+            config.clone(),
+            CHECK_CREDIT,
+            credit_checked(),
+            tenant_header("globex"),
+        )
+        .await;
+
+        assert_eq!(Some("acme".to_string()), acme[0][0].tenant);
+        assert_eq!(Some("globex".to_string()), globex[0][0].tenant);
+    }
+
+    #[tokio::test]
+    async fn missing_tenant_uses_the_default_or_skips_persistence() {
+        let defaulted = run_mutation(
+            json!({ "tenant_header": "x-tenant-id", "default_tenant": "shared" }),
+            CHECK_CREDIT,
+            credit_checked(),
+            |_| {},
+        )
+        .await;
+        let skipped = run_mutation(
+            json!({ "tenant_header": "x-tenant-id" }),
+            CHECK_CREDIT,
+            credit_checked(),
+            |_| {},
+        )
+        .await;
+
+        assert_eq!(Some("shared".to_string()), defaulted[0][0].tenant);
+        assert!(skipped.is_empty());
+    }
+
+    #[test]
+    fn large_integers_can_be_persisted_as_strings() {
+        let mutation = r#"
+            mutation Fund($amount: Long) {
+              recordLoanFunded(input: { loanId: "loan-1", cents: 9007199254740993, small: 42 }, amount: $amount) {
+                loanId
+              }
+            }
+        "#;
+        let mut vars = BytesMap::new();
+        vars.insert(
+            ByteString::from("amount"),
+            serde_json_bytes::to_value(json!(18446744073709551615u64)).unwrap(),
+        );
+        let options = ExtractionOptions {
+            large_integers: LargeIntegers::String,
+            ..Default::default()
+        };
+
+        let as_strings = extract_mutations(mutation, &vars, &options);
+        let as_numbers = extract_mutations(mutation, &vars, &ExtractionOptions::default());
+
+        assert_eq!(
+            json!({ "loanId": "loan-1", "cents": "9007199254740993", "small": 42 }),
+            as_strings[0].arguments[0].value
+        );
+        assert_eq!(
+            json!("18446744073709551615"),
+            as_strings[0].arguments[1].value
+        );
+        assert_eq!(
+            json!(9007199254740993u64),
+            as_numbers[0].arguments[0].value["cents"]
+        );
+        assert_eq!(
+            json!(18446744073709551615u64),
+            as_numbers[0].arguments[1].value
+        );
+    }
+
+    #[test]
+    fn null_arguments_are_skipped_when_configured() {
+        let mutation = r#"
+            mutation Note($note: String) {
+              recordLoanNote(input: { loanId: "loan-1" }, note: $note, reviewer: null) {
+                loanId
+              }
+            }
+        "#;
+        let options = ExtractionOptions {
+            skip_null_args: true,
+            ..Default::default()
+        };
+
+        let skipped = extract_mutations(mutation, &BytesMap::new(), &options);
+        let kept = extract_mutations(mutation, &BytesMap::new(), &ExtractionOptions::default());
+
+        assert_eq!(vec!["input"], argument_names(&skipped[0]));
+        assert_eq!(vec!["input", "note", "reviewer"], argument_names(&kept[0]));
+    }
+
+    #[test]
+    fn object_fields_from_absent_variables_are_omitted_but_explicit_nulls_kept() {
+        let mutation = r#"
+            mutation Check($score: Int, $timestamp: String, $nationalId: String = "987654321") {
+              recordCreditChecked(input: {
+                loanId: "loan-1"
+                Score: $score
+                CreditCheckedTimestamp: $timestamp
+                NationalID: $nationalId
+              }) { Score }
+            }
+        "#;
+        let mut variables = BytesMap::new();
+        variables.insert(ByteString::from("timestamp"), BytesValue::Null);
+
+        let calls = extract_mutations(mutation, &variables, &ExtractionOptions::default());
+
+        assert_eq!(
+            Some(&json!({
+                "loanId": "loan-1",
+                "CreditCheckedTimestamp": null,
+                "NationalID": "987654321"
+            })),
+            calls[0].argument_value("input")
+        );
+    }
+
+    #[test]
+    fn schema_defaults_fill_absent_input_fields() {
+        let sdl = r#"
+            input CreditCheckedInput { loanId: ID!, Score: Int = 0, Bureau: String = "equifax" }
+            type Mutation { recordCreditChecked(input: CreditCheckedInput!): ID! }
+        "#;
+        let options = ExtractionOptions {
+            input_defaults: Some(Arc::new(InputDefaults::from_sdl(sdl, false))),
+            ..Default::default()
+        };
+        let mutation = r#"
+            mutation Check($score: Int) {
+              recordCreditChecked(input: { loanId: "loan-1", Score: $score, Bureau: null }) { Score }
+            }
+        "#;
+
+        let calls = extract_mutations(mutation, &BytesMap::new(), &options);
+
+        assert_eq!(
+            Some(&json!({ "loanId": "loan-1", "Score": 0, "Bureau": null })),
+            calls[0].argument_value("input")
+        );
+    }
+
+    #[test]
+    fn aliased_calls_are_looked_up_by_alias_and_named_by_field() {
+        let mutation = r#"
+            mutation {
+              approval: recordLoanManuallyApproved(input: { loanId: "loan-1" }) {
                 LoanRequestID
-                CreditScoreSummary
               }
             }
         "#;
+        let calls = extract_mutations(mutation, &BytesMap::new(), &ExtractionOptions::default());
+        let data = serde_json_bytes::to_value(json!({
+            "approval": { "LoanRequestID": "loan-1" }
+        }))
+        .unwrap();
 
-        let request = build_supergraph_request(mutation, json!({}));
+        let enriched = enrich_mutations_with_response(
+            calls,
+            &data,
+            &BytesMap::new(),
+            &EnrichmentOptions::default(),
+        );
 
-        let mut response = service.oneshot(request).await.unwrap();
-        assert!(response.response.status().is_success());
+        assert_eq!(
+            Some(&json!({ "LoanRequestID": "loan-1" })),
+            enriched[0].argument_value("responseData")
+        );
+        assert_eq!(
+            "graphql-mutation-recordLoanManuallyApproved",
+            stream_name(&enriched[0], &KurrentConfig::default())
+        );
+        let by_alias: KurrentConfig =
+            serde_json::from_value(json!({ "name_source": "alias" })).unwrap();
+        assert_eq!(
+            "graphql-mutation-approval",
+            stream_name(&enriched[0], &by_alias)
+        );
+        assert_eq!("GraphQL.approval", event_type(&enriched[0], &by_alias));
+    }
 
-        // Consume the response stream to trigger the mutation persistence
-        while let Some(_) = response.response.body_mut().next().await {}
+    #[test]
+    fn calls_missing_a_required_argument_are_dropped() {
+        let query = r#"
+            mutation RecordCreditChecked($score: Int) {
+              recordCreditChecked(input: { loanId: "loan-1", Score: $score }) { loanId }
+            }
+        "#;
+        let options = ExtractionOptions {
+            require_args: HashMap::from([(
+                "recordCreditChecked".to_string(),
+                vec!["input.Score".to_string()],
+            )]),
+            ..Default::default()
+        };
+        let mut variables = BytesMap::new();
+        variables.insert(
+            ByteString::from("score"),
+            serde_json_bytes::Value::from(720),
+        );
 
-        let recorded = sink.recorded();
-        assert_eq!(1, recorded.len());
-        let calls = &recorded[0];
-        assert_eq!(1, calls.len());
-        let call = &calls[0];
-        assert_eq!("recordAutomatedSummary", call.field_name);
-        let input = call
-            .arguments
-            .iter()
-            .find(|arg| arg.name == "input")
-            .expect("input argument");
+        let supplied = extract_mutations(query, &variables, &options);
+        let omitted = extract_mutations(query, &BytesMap::new(), &options);
+
+        assert_eq!(1, supplied.len());
+        assert!(omitted.is_empty());
+    }
+
+    #[test]
+    fn queries_are_captured_only_when_enabled() {
+        let query = r#"
+            query LoanStatus {
+              loan(id: "loan-1") { status }
+              creditReport(loanId: "loan-1") { score }
+            }
+        "#;
+        let enabled = ExtractionOptions {
+            operation_types: vec![OperationType::Mutation, OperationType::Query],
+            ..Default::default()
+        };
+        let listed = ExtractionOptions {
+            query_fields: Some(FieldPatterns::compile(&["credit*"]).unwrap()),
+            ..enabled.clone()
+        };
+
+        let default = extract_mutations(query, &BytesMap::new(), &ExtractionOptions::default());
+        let all = extract_mutations(query, &BytesMap::new(), &enabled);
+        let filtered = extract_mutations(query, &BytesMap::new(), &listed);
+        let shorthand = extract_mutations(
+            r#"{ loan(id: "loan-1") { status } }"#,
+            &BytesMap::new(),
+            &enabled,
+        );
+
+        assert!(default.is_empty());
+        assert_eq!(2, all.len());
+        assert!(
+            all.iter()
+                .all(|call| call.operation_type == OperationType::Query)
+        );
         assert_eq!(
-            json!("credit score summary"),
-            input.value["CreditScoreSummary"]
+            vec!["creditReport"],
+            filtered
+                .iter()
+                .map(|call| call.field_name.as_str())
+                .collect::<Vec<_>>()
         );
-        // Verify response data was added
-        let response_data = call.arguments.iter().find(|arg| arg.name == "responseData");
-        assert!(response_data.is_some(), "Response data should be present");
+        assert_eq!(1, shorthand.len());
+    }
+
+    #[test]
+    fn mutations_are_not_captured_when_only_queries_are_enabled() {
+        let options = ExtractionOptions {
+            operation_types: vec![OperationType::Query],
+            ..Default::default()
+        };
+
+        let mutation = r#"mutation { recordLoanNote(input: { loanId: "loan-1" }) { loanId } }"#;
+
+        let calls = extract_mutations(mutation, &BytesMap::new(), &options);
+
+        assert!(calls.is_empty());
+    }
+
+    fn argument_names(call: &MutationCall) -> Vec<&str> {
+        call.arguments.iter().map(|arg| arg.name.as_str()).collect()
     }
 
     #[tokio::test]
-    async fn extracts_loan_id_from_record_loan_requested_response() {
-        let sink = StdArc::new(MockMutationSink::default());
-        let interceptor = MutationInterceptor::with_sink(sink.clone());
+    async fn id_patterns_match_several_scalar_returning_mutations() {
+        let config = json!({ "id_from_scalar_response": ["create*", "record*Requested"] });
 
-        let mut mock_service = test::MockSupergraphService::new();
-        mock_service
-            .expect_call()
-            .returning(|req: supergraph::Request| {
-                // Return a UUID as the loanId
-                let data = json!({
-                    "recordLoanRequested": "550e8400-e29b-41d4-a716-446655440000"
-                });
-                Ok(supergraph::Response::fake_builder()
-                    .context(req.context)
-                    .data(serde_json_bytes::to_value(data).unwrap())
-                    .build()
-                    .unwrap())
-            });
-        mock_service.expect_clone().return_once(|| {
-            let mut inner = test::MockSupergraphService::new();
-            inner.expect_call().returning(|req: supergraph::Request| {
-                let data = json!({
-                    "recordLoanRequested": "550e8400-e29b-41d4-a716-446655440000"
-                });
-                Ok(supergraph::Response::fake_builder()
-                    .context(req.context)
-                    .data(serde_json_bytes::to_value(data).unwrap())
-                    .build()
-                    .unwrap())
-            });
-            inner
-        });
+        let order = run_mutation(
+            config.clone(),
+            r#"mutation { createOrder(input: { sku: "A-1" }) }"#,
+            json!({ "data": { "createOrder": "new-id-1" } }),
+            |_| {},
+        )
+        .await;
+        let check = run_mutation(
+            config.clone(),
+            r#"mutation { recordCreditCheckRequested(input: { Score: 700 }) }"#,
+            json!({ "data": { "recordCreditCheckRequested": "new-id-1" } }),
+            |_| {},
+        )
+        .await;
+        let unmatched = run_mutation(
+            config,
+            r#"mutation { recordLoanNote(input: { note: "hi" }) }"#,
+            json!({ "data": { "recordLoanNote": "new-id-1" } }),
+            |_| {},
+        )
+        .await;
 
-        let service = interceptor.supergraph_service(mock_service.boxed());
+        assert_eq!(Some("new-id-1".to_string()), order[0][0].loan_id);
+        assert_eq!(Some("new-id-1".to_string()), check[0][0].loan_id);
+        assert_eq!(None, unmatched[0][0].loan_id);
+    }
 
+    #[tokio::test]
+    async fn id_is_read_from_a_nested_response_path() {
+        let config = json!({
+            "id_from_response": { "createLoan": "loan.id", "updateLoan": "loan.id" }
+        });
         let mutation = r#"
-            mutation RecordLoan {
-              recordLoanRequested(
-                input: {
-                  Amount: 50000.0
-                  NationalID: "123456789"
-                  Name: "John Doe"
-                  Gender: "Male"
-                  Age: 35
-                  MaritalStatus: "Married"
-                  Dependents: 2
-                  EducationLevel: "Bachelor"
-                  EmployerName: "Tech Corp"
-                  JobTitle: "Engineer"
-                  JobSeniority: 5.0
-                  Income: 85000.0
-                  Address: {
-                    Street: "123 Main St"
-                    City: "San Francisco"
-                    Region: "CA"
-                    Country: "USA"
-                    PostalCode: "94102"
-                  }
-                  LoanRequestedTimestamp: "2024-09-29T00:00:00Z"
-                }
-              )
+            mutation Loans {
+              createLoan(input: { amount: 1000 }) {
+                loan { id }
+              }
+              updateLoan(input: { amount: 2000 }) {
+                loan { id }
+              }
             }
         "#;
+        let response = json!({
+            "data": {
+                "createLoan": { "loan": { "id": "loan-789" } },
+                "updateLoan": { "loan": null }
+            }
+        });
 
-        let request = build_supergraph_request(mutation, json!({}));
+        let recorded = run_mutation(config, mutation, response, |_| {}).await;
 
-        let mut response = service.oneshot(request).await.unwrap();
-        assert!(response.response.status().is_success());
+        assert_eq!(Some("loan-789".to_string()), recorded[0][0].loan_id);
+        assert_eq!(None, recorded[0][1].loan_id);
+    }
 
-        // Consume the response stream to trigger the mutation persistence
-        while let Some(_) = response.response.body_mut().next().await {}
+    #[test]
+    fn selected_fields_record_aliases_and_schema_names() {
+        let mutation = r#"
+            mutation CreateOrder {
+              createOrder(input: { sku: "A-1" }) {
+                newOrder: order { id }
+                success
+              }
+            }
+        "#;
 
-        let recorded = sink.recorded();
-        assert_eq!(1, recorded.len());
-        let calls = &recorded[0];
-        assert_eq!(1, calls.len());
-        let call = &calls[0];
-        assert_eq!("recordLoanRequested", call.field_name);
+        let calls = extract_mutations(mutation, &BytesMap::new(), &ExtractionOptions::default());
 
-        // Verify loanId was extracted from response and set at top level
-        assert!(
-            call.loan_id.is_some(),
-            "loanId should be extracted from response"
-        );
         assert_eq!(
-            "550e8400-e29b-41d4-a716-446655440000",
-            call.loan_id.as_ref().unwrap()
+            vec![
+                SelectedField::new("newOrder", "order"),
+                SelectedField::new("success", "success"),
+            ],
+            calls[0].selected_fields
         );
     }
 
-    #[tokio::test]
-    async fn extracts_loan_id_from_input_arguments() {
-        let sink = StdArc::new(MockMutationSink::default());
-        let interceptor = MutationInterceptor::with_sink(sink.clone());
+    #[test]
+    fn plugin_reports_its_registered_name() {
+        let interceptor = MutationInterceptor::with_sink_and_config(
+            StdArc::new(MockMutationSink::default()),
+            plugin_config(json!({})),
+        );
 
-        let mut mock_service = test::MockSupergraphService::new();
-        mock_service
-            .expect_call()
-            .returning(|req: supergraph::Request| {
-                let data = json!({
-                    "recordCreditChecked": {
-                        "LoanRequestID": "test-loan-456",
-                        "NationalID": "123456789",
-                        "Score": 750,
-                        "CreditCheckedTimestamp": "2024-09-29T00:00:00Z"
-                    }
-                });
-                Ok(supergraph::Response::fake_builder()
-                    .context(req.context)
-                    .data(serde_json_bytes::to_value(data).unwrap())
-                    .build()
-                    .unwrap())
-            });
-        mock_service.expect_clone().return_once(|| {
-            let mut inner = test::MockSupergraphService::new();
-            inner.expect_call().returning(|req: supergraph::Request| {
-                let data = json!({
-                    "recordCreditChecked": {
-                        "LoanRequestID": "test-loan-456",
-                        "NationalID": "123456789",
-                        "Score": 750,
-                        "CreditCheckedTimestamp": "2024-09-29T00:00:00Z"
-                    }
-                });
-                Ok(supergraph::Response::fake_builder()
-                    .context(req.context)
-                    .data(serde_json_bytes::to_value(data).unwrap())
-                    .build()
-                    .unwrap())
-            });
-            inner
-        });
+        assert_eq!("starstuff.mutation_plugin", interceptor.name());
+    }
 
-        let service = interceptor.supergraph_service(mock_service.boxed());
+    #[tokio::test]
+    async fn configured_subgraph_mutations_are_persisted_with_their_subgraph() {
+        let config = json!({ "capture_subgraphs": ["credit"] });
 
         let mutation = r#"
-            mutation CheckCredit {
-              recordCreditChecked(
-                input: {
-                  loanId: "test-loan-456"
-                  NationalID: "123456789"
-                  Score: 750
-                  CreditCheckedTimestamp: "2024-09-29T00:00:00Z"
-                }
-              ) {
-                LoanRequestID
-                Score
-              }
+            mutation CheckCredit__credit__0 {
+              recordCreditChecked(input: { loanId: "loan-1", Score: 700 }) { Score }
             }
         "#;
+        let with_correlation_id = |request: &mut subgraph::Request| {
+            request
+                .context
+                .insert(CORRELATION_ID_CONTEXT_KEY, "corr-1".to_string())
+                .unwrap();
+        };
 
-        let request = build_supergraph_request(mutation, json!({}));
+        let captured = run_subgraph_mutation(
+            config.clone(),
+            "credit",
+            mutation,
+            credit_checked(),
+            with_correlation_id,
+        )
+        .await;
+        let ignored = run_subgraph_mutation(
+            config,
+            "loans",
+            mutation,
+            credit_checked(),
+            with_correlation_id,
+        )
+        .await;
 
-        let mut response = service.oneshot(request).await.unwrap();
-        assert!(response.response.status().is_success());
+        assert_eq!(1, captured.len());
+        let call = &captured[0][0];
+        assert_eq!(Some("credit".to_string()), call.subgraph);
+        assert_eq!(Some("loan-1".to_string()), call.loan_id);
+        assert_eq!(
+            Some(&json!("corr-1")),
+            call.metadata.get(CORRELATION_ID_METADATA_KEY)
+        );
+        assert!(ignored.is_empty());
+    }
 
-        // Consume the response stream to trigger the mutation persistence
-        while let Some(_) = response.response.body_mut().next().await {}
+    #[test]
+    fn response_data_can_be_left_out_while_still_resolving_ids() {
+        let call = MutationCall {
+            field_name: "createLoan".to_string(),
+            ..Default::default()
+        };
+        let data =
+            serde_json_bytes::to_value(json!({ "createLoan": { "loan": { "id": "loan-789" } } }))
+                .unwrap();
+        let options = EnrichmentOptions {
+            enrichers: vec![StdArc::new(ResponseIdEnricher {
+                id_from_response: HashMap::from([(
+                    "createLoan".to_string(),
+                    "loan.id".to_string(),
+                )]),
+                ..Default::default()
+            })],
+            omit_response_data: true,
+            ..Default::default()
+        };
 
-        let recorded = sink.recorded();
-        assert_eq!(1, recorded.len());
-        let calls = &recorded[0];
-        assert_eq!(1, calls.len());
-        let call = &calls[0];
-        assert_eq!("recordCreditChecked", call.field_name);
+        let calls = enrich_mutations_with_response(vec![call], &data, &BytesMap::new(), &options);
 
-        // Verify loanId was extracted from input arguments and set at top level
+        assert_eq!(Some("loan-789".to_string()), calls[0].loan_id);
         assert!(
-            call.loan_id.is_some(),
-            "loanId should be extracted from input arguments"
+            calls[0]
+                .arguments
+                .iter()
+                .all(|arg| arg.name != "responseData")
         );
-        assert_eq!("test-loan-456", call.loan_id.as_ref().unwrap());
     }
 
     #[tokio::test]
-    async fn ignores_non_mutation_operations() {
-        let sink = StdArc::new(MockMutationSink::default());
-        let interceptor = MutationInterceptor::with_sink(sink.clone());
-
-        let mut mock_service = test::MockSupergraphService::new();
-        mock_service
-            .expect_call()
-            .returning(|req: supergraph::Request| {
-                Ok(supergraph::Response::fake_builder()
-                    .context(req.context)
-                    .build()
-                    .unwrap())
-            });
-        mock_service.expect_clone().return_once(|| {
-            let mut inner = test::MockSupergraphService::new();
-            inner.expect_call().returning(|req: supergraph::Request| {
-                Ok(supergraph::Response::fake_builder()
-                    .context(req.context)
-                    .build()
-                    .unwrap())
-            });
-            inner
-        });
+    async fn a_zero_sample_rate_persists_nothing() {
+        let recorded = run_mutation(
+            json!({ "sample_rate": 0.0, "sample_by_correlation_id": true }),
+            CHECK_CREDIT,
+            credit_checked(),
+            |_| {},
+        )
+        .await;
 
-        let service = interceptor.supergraph_service(mock_service.boxed());
+        assert!(recorded.is_empty());
+    }
 
-        let query = "query { __typename }";
-        let request = build_supergraph_request(query, json!({}));
+    #[tokio::test]
+    async fn requests_with_the_skip_header_are_not_persisted() {
+        let recorded = run_mutation(
+            json!({ "skip_header": "x-kurrent-skip" }),
+            CHECK_CREDIT,
+            credit_checked(),
+            |request| {
+                request
+                    .supergraph_request
+                    .headers_mut()
+                    .insert("x-kurrent-skip", http::HeaderValue::from_static("TRUE"));
+            },
+        )
+        .await;
 
-        let response = service.oneshot(request).await.unwrap();
-        assert!(response.response.status().is_success());
-        assert!(sink.recorded().is_empty());
+        assert!(recorded.is_empty());
     }
 }
@@ -0,0 +1,195 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::plugins::kurrent_mapper::MutationArg;
+
+/// Normalized form a custom scalar argument is persisted in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ScalarCoercion {
+    /// An RFC 3339 timestamp in UTC with millisecond precision,
+    /// e.g. `2025-01-02T03:04:05.000Z`. Accepts RFC 3339 strings with any offset
+    /// and epoch milliseconds.
+    Iso8601,
+    /// Milliseconds since the Unix epoch, as a number. Accepts numbers, numeric
+    /// strings and RFC 3339 strings.
+    EpochMillis,
+    /// A lowercase hyphenated UUID. Accepts any form `Uuid::parse_str` does
+    /// (simple, braced, URN, either case).
+    Uuid,
+}
+
+impl ScalarCoercion {
+    /// The normalized value, or `None` if `value` is not a valid instance.
+    pub fn coerce(self, value: &Value) -> Option<Value> {
+        match self {
+            ScalarCoercion::Iso8601 => timestamp(value)
+                .map(|at| Value::from(at.to_rfc3339_opts(SecondsFormat::Millis, true))),
+            ScalarCoercion::EpochMillis => match value {
+                Value::Number(millis) => millis.as_i64().map(Value::from),
+                Value::String(text) => match text.parse::<i64>() {
+                    Ok(millis) => Some(Value::from(millis)),
+                    Err(_) => timestamp(value).map(|at| Value::from(at.timestamp_millis())),
+                },
+                _ => None,
+            },
+            ScalarCoercion::Uuid => value
+                .as_str()
+                .and_then(|text| Uuid::parse_str(text).ok())
+                .map(|uuid| Value::from(uuid.hyphenated().to_string())),
+        }
+    }
+}
+
+fn timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(text) => DateTime::parse_from_rfc3339(text)
+            .ok()
+            .map(|at| at.with_timezone(&Utc)),
+        Value::Number(millis) => DateTime::from_timestamp_millis(millis.as_i64()?),
+        _ => None,
+    }
+}
+
+/// What happens to an argument value its coercion rejects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum InvalidScalarPolicy {
+    /// Persist the value as it was sent.
+    #[default]
+    PassThrough,
+    /// Leave the value out of the persisted arguments.
+    Drop,
+}
+
+/// Coercions keyed by dotted argument path, e.g. `input.CreditCheckedTimestamp`,
+/// applied to every call that has the argument.
+#[derive(Debug, Clone, Default)]
+pub struct ScalarCoercions {
+    pub coercions: HashMap<String, ScalarCoercion>,
+    pub on_invalid: InvalidScalarPolicy,
+}
+
+impl ScalarCoercions {
+    pub fn is_empty(&self) -> bool {
+        self.coercions.is_empty()
+    }
+
+    pub fn apply(&self, field_name: &str, arguments: &mut Vec<MutationArg>) {
+        for (path, coercion) in &self.coercions {
+            let Some(value) = argument_value_mut(arguments, path).filter(|value| !value.is_null())
+            else {
+                continue;
+            };
+            if let Some(coerced) = coercion.coerce(value) {
+                *value = coerced;
+                continue;
+            }
+
+            tracing::warn!(mutation = %field_name, argument = %path, coercion = ?coercion, value = %value, policy = ?self.on_invalid, "Argument value is not a valid instance of its scalar coercion");
+            if self.on_invalid == InvalidScalarPolicy::Drop {
+                remove_argument_value(arguments, path);
+            }
+        }
+    }
+}
+
+fn argument_value_mut<'a>(arguments: &'a mut [MutationArg], path: &str) -> Option<&'a mut Value> {
+    let mut segments = path.split('.');
+    let name = segments.next()?;
+    let value = &mut arguments.iter_mut().find(|arg| arg.name == name)?.value;
+    segments.try_fold(value, |current, segment| current.get_mut(segment))
+}
+
+fn remove_argument_value(arguments: &mut Vec<MutationArg>, path: &str) {
+    match path.rsplit_once('.') {
+        Some((parent, key)) => {
+            if let Some(Value::Object(object)) = argument_value_mut(arguments, parent) {
+                object.remove(key);
+            }
+        }
+        None => arguments.retain(|arg| arg.name != path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn coercions(policy: InvalidScalarPolicy) -> ScalarCoercions {
+        ScalarCoercions {
+            coercions: HashMap::from([
+                (
+                    "input.CreditCheckedTimestamp".to_string(),
+                    ScalarCoercion::Iso8601,
+                ),
+                ("requestId".to_string(), ScalarCoercion::Uuid),
+            ]),
+            on_invalid: policy,
+        }
+    }
+
+    fn arguments(timestamp: Value) -> Vec<MutationArg> {
+        vec![
+            MutationArg {
+                name: "input".to_string(),
+                value: json!({ "loanId": "loan-1", "CreditCheckedTimestamp": timestamp }),
+            },
+            MutationArg {
+                name: "requestId".to_string(),
+                value: json!("{6F9619FF-8B86-D011-B42D-00C04FC964FF}"),
+            },
+        ]
+    }
+
+    #[test]
+    fn date_times_are_normalized_to_utc_milliseconds() {
+        let coercions = coercions(InvalidScalarPolicy::PassThrough);
+
+        for sent in [
+            json!("2025-01-02T04:04:05+01:00"),
+            json!("2025-01-02T03:04:05Z"),
+            json!(1_735_787_045_000u64),
+        ] {
+            let mut args = arguments(sent);
+            coercions.apply("recordCreditChecked", &mut args);
+
+            assert_eq!(
+                json!("2025-01-02T03:04:05.000Z"),
+                args[0].value["CreditCheckedTimestamp"]
+            );
+            assert_eq!(json!("6f9619ff-8b86-d011-b42d-00c04fc964ff"), args[1].value);
+        }
+    }
+
+    #[test]
+    fn invalid_values_pass_through_or_are_dropped() {
+        let mut passed = arguments(json!("yesterday"));
+        coercions(InvalidScalarPolicy::PassThrough).apply("recordCreditChecked", &mut passed);
+        let mut dropped = arguments(json!("yesterday"));
+        coercions(InvalidScalarPolicy::Drop).apply("recordCreditChecked", &mut dropped);
+
+        assert_eq!(
+            json!("yesterday"),
+            passed[0].value["CreditCheckedTimestamp"]
+        );
+        assert_eq!(json!({ "loanId": "loan-1" }), dropped[0].value);
+    }
+
+    #[test]
+    fn epoch_millis_accepts_numbers_and_timestamps() {
+        let coercion = ScalarCoercion::EpochMillis;
+
+        assert_eq!(
+            Some(json!(1_735_787_045_000i64)),
+            coercion.coerce(&json!("2025-01-02T03:04:05Z"))
+        );
+        assert_eq!(Some(json!(42)), coercion.coerce(&json!("42")));
+        assert_eq!(None, coercion.coerce(&json!(true)));
+    }
+}
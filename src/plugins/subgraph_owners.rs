@@ -0,0 +1,189 @@
+use apollo_parser::{
+    Parser,
+    cst::{self, CstNode, Definition},
+};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::plugins::kurrent_mapper::OperationType;
+
+/// The subgraph resolving each root field, read from the supergraph's
+/// `@join__field`/`@join__type` directives, the same ones the query planner uses.
+/// Fields several subgraphs can resolve (`@shareable` root fields) have no single
+/// owner at this layer; `capture_subgraphs` records what each fetch did instead.
+#[derive(Debug, Default)]
+pub struct SubgraphOwners {
+    owners: HashMap<(OperationType, String), String>,
+}
+
+impl SubgraphOwners {
+    pub fn from_sdl(sdl: &str) -> Self {
+        let ast = Parser::new(sdl).parse();
+        let doc = ast.document();
+
+        let mut graph_names = HashMap::new();
+        let mut roots = HashMap::from([
+            ("Mutation".to_string(), OperationType::Mutation),
+            ("Query".to_string(), OperationType::Query),
+        ]);
+        for def in doc.definitions() {
+            match def {
+                Definition::EnumTypeDefinition(graphs)
+                    if graphs
+                        .name()
+                        .is_some_and(|name| name.text().as_str() == "join__Graph") =>
+                {
+                    for value in graphs
+                        .enum_values_definition()
+                        .into_iter()
+                        .flat_map(|values| values.enum_value_definitions())
+                    {
+                        let Some(enum_value) = value.enum_value() else {
+                            continue;
+                        };
+                        let name = directives(value.directives(), "join__graph")
+                            .find_map(|graph| argument(&graph, "name"));
+                        if let Some(name) = name {
+                            graph_names.insert(enum_value.syntax().text().to_string(), name);
+                        }
+                    }
+                }
+                Definition::SchemaDefinition(schema) => {
+                    roots.clear();
+                    for root in schema.root_operation_type_definitions() {
+                        let operation_type = match root.operation_type() {
+                            Some(op) if op.mutation_token().is_some() => OperationType::Mutation,
+                            Some(op) if op.query_token().is_some() => OperationType::Query,
+                            _ => continue,
+                        };
+                        if let Some(name) = root.named_type().and_then(|named| named.name()) {
+                            roots.insert(name.text().to_string(), operation_type);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut owners = Self::default();
+        for def in doc.definitions() {
+            let Definition::ObjectTypeDefinition(object) = def else {
+                continue;
+            };
+            let Some(&operation_type) = object
+                .name()
+                .and_then(|name| roots.get(name.text().as_str()))
+            else {
+                continue;
+            };
+            let type_graphs = graphs(object.directives(), "join__type");
+
+            for field in object
+                .fields_definition()
+                .into_iter()
+                .flat_map(|fields| fields.field_definitions())
+            {
+                let Some(field_name) = field.name() else {
+                    continue;
+                };
+                let field_graphs = graphs(field.directives(), "join__field");
+                let candidates = if field_graphs.is_empty() {
+                    &type_graphs
+                } else {
+                    &field_graphs
+                };
+                let [graph] = candidates.iter().collect::<Vec<_>>()[..] else {
+                    continue;
+                };
+                if let Some(name) = graph_names.get(graph) {
+                    owners.owners.insert(
+                        (operation_type, field_name.text().to_string()),
+                        name.clone(),
+                    );
+                }
+            }
+        }
+
+        owners
+    }
+
+    pub fn owner(&self, operation_type: OperationType, field_name: &str) -> Option<&str> {
+        self.owners
+            .get(&(operation_type, field_name.to_string()))
+            .map(String::as_str)
+    }
+}
+
+fn directives(
+    directives: Option<cst::Directives>,
+    name: &'static str,
+) -> impl Iterator<Item = cst::Directive> {
+    directives
+        .into_iter()
+        .flat_map(|directives| directives.directives())
+        .filter(move |directive| {
+            directive
+                .name()
+                .is_some_and(|directive_name| directive_name.text().as_str() == name)
+        })
+}
+
+/// The `graph` arguments of the `name` directives.
+fn graphs(list: Option<cst::Directives>, name: &'static str) -> BTreeSet<String> {
+    directives(list, name)
+        .filter_map(|directive| argument(&directive, "graph"))
+        .collect()
+}
+
+/// A directive argument given as an enum value or a string.
+fn argument(directive: &cst::Directive, name: &str) -> Option<String> {
+    let value = directive
+        .arguments()?
+        .arguments()
+        .find(|argument| argument.name().is_some_and(|n| n.text().as_str() == name))?
+        .value()?;
+    let text = value.syntax().text().to_string();
+    match value {
+        cst::Value::EnumValue(_) => Some(text),
+        cst::Value::StringValue(_) => serde_json::from_str(&text).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_fields_are_owned_by_their_joined_subgraph() {
+        let owners = SubgraphOwners::from_sdl(include_str!("../../supergraph-schema.graphql"));
+
+        assert_eq!(
+            Some("loan-origination"),
+            owners.owner(OperationType::Mutation, "recordCreditChecked")
+        );
+        assert_eq!(
+            Some("loan-decisions"),
+            owners.owner(OperationType::Mutation, "recordLoanManuallyApproved")
+        );
+        assert_eq!(None, owners.owner(OperationType::Mutation, "unknownField"));
+    }
+
+    #[test]
+    fn shared_root_fields_have_no_single_owner() {
+        let owners = SubgraphOwners::from_sdl(
+            r#"
+            enum join__Graph {
+              A @join__graph(name: "a", url: "http://a")
+              B @join__graph(name: "b", url: "http://b")
+            }
+            type Mutation @join__type(graph: A) {
+              onlyA: ID
+              shared: ID @join__field(graph: A) @join__field(graph: B)
+            }
+            "#,
+        );
+
+        assert_eq!(Some("a"), owners.owner(OperationType::Mutation, "onlyA"));
+        assert_eq!(None, owners.owner(OperationType::Mutation, "shared"));
+    }
+}
@@ -0,0 +1,162 @@
+//! Runs a mutation through the router with the plugin enabled and reads the event
+//! back from a real KurrentDB, started with testcontainers.
+//!
+//! Needs Docker, so it is ignored by default. Run with
+//! `cargo test --test kurrentdb -- --ignored`.
+
+use apollo_router::{
+    TestHarness,
+    services::{subgraph, supergraph},
+};
+use kurrentdb::{Client, ReadStreamOptions, StreamPosition};
+use serde_json::json;
+use starstuff::plugins::kurrent_mapper::decode_call;
+use testcontainers::{
+    ContainerAsync, GenericImage, ImageExt,
+    core::{IntoContainerPort, WaitFor},
+    runners::AsyncRunner,
+};
+use tower::{BoxError, ServiceExt};
+
+const KURRENTDB_IMAGE: &str = "docker.cloudsmith.io/eventstore/kurrent-latest/kurrentdb";
+const KURRENTDB_TAG: &str = "25.0.0";
+const KURRENTDB_PORT: u16 = 2113;
+
+async fn start_kurrentdb() -> Result<(ContainerAsync<GenericImage>, String), BoxError> {
+    let container = GenericImage::new(KURRENTDB_IMAGE, KURRENTDB_TAG)
+        .with_exposed_port(KURRENTDB_PORT.tcp())
+        .with_wait_for(WaitFor::message_on_stdout("IS LEADER"))
+        .with_env_var("KURRENTDB_INSECURE", "true")
+        .with_env_var("KURRENTDB_RUN_PROJECTIONS", "All")
+        .start()
+        .await?;
+    let port = container.get_host_port_ipv4(KURRENTDB_PORT).await?;
+    let connection_string = format!("kurrentdb://localhost:{port}?tls=false");
+    Ok((container, connection_string))
+}
+
+/// Answers every subgraph fetch with the `recordCreditChecked` result.
+fn credit_checked_subgraph(_: &str, _: subgraph::BoxService) -> subgraph::BoxService {
+    tower::service_fn(|request: subgraph::Request| async move {
+        let data = json!({
+            "recordCreditChecked": {
+                "LoanRequestID": "loan-it-1",
+                "Score": 720
+            }
+        });
+        Ok::<_, BoxError>(
+            subgraph::Response::fake_builder()
+                .context(request.context)
+                .data(serde_json_bytes::to_value(data)?)
+                .build(),
+        )
+    })
+    .boxed()
+}
+
+/// Runs the `CheckCredit` mutation through a router configured with
+/// `plugin_config`, waiting for its events to be persisted.
+async fn check_credit(plugin_config: serde_json::Value) -> Result<(), BoxError> {
+    let router = TestHarness::builder()
+        .configuration_json(json!({
+            "plugins": { "starstuff.mutation_plugin": plugin_config }
+        }))?
+        .schema(include_str!("../supergraph-schema.graphql"))
+        .subgraph_hook(credit_checked_subgraph)
+        .build_supergraph()
+        .await?;
+
+    let request = supergraph::Request::fake_builder()
+        .query(
+            r#"
+            mutation CheckCredit {
+              recordCreditChecked(
+                input: {
+                  loanId: "loan-it-1"
+                  NationalID: "987654321"
+                  Score: 720
+                  CreditCheckedTimestamp: "2024-01-01T00:00:00Z"
+                }
+              ) {
+                LoanRequestID
+                Score
+              }
+            }
+            "#,
+        )
+        .build()?;
+    let mut response = router.oneshot(request).await?;
+    let body = response.next_response().await.expect("a response");
+    assert!(body.errors.is_empty(), "{:?}", body.errors);
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "starts a KurrentDB container; needs Docker"]
+async fn mutations_are_appended_to_kurrentdb() -> Result<(), BoxError> {
+    let (_container, connection_string) = start_kurrentdb().await?;
+
+    check_credit(json!({
+        "connection_string": connection_string,
+        "persist_before_response": true
+    }))
+    .await?;
+
+    let client = Client::new(connection_string.parse()?)?;
+    let options = ReadStreamOptions::default()
+        .position(StreamPosition::Start)
+        .forwards();
+    let mut events = client
+        .read_stream("graphql-mutation-recordCreditChecked", &options)
+        .await?;
+    let resolved = events.next().await?.expect("one event in the stream");
+    let recorded = resolved.get_original_event();
+
+    assert_eq!("GraphQL.CheckCredit", recorded.event_type);
+    assert_eq!("graphql-mutation-recordCreditChecked", recorded.stream_id);
+
+    let call = decode_call(&recorded.data, &recorded.custom_metadata)?;
+    assert_eq!("recordCreditChecked", call.field_name);
+    assert_eq!(Some("CheckCredit".to_string()), call.operation_name);
+    assert_eq!(Some("loan-it-1".to_string()), call.loan_id);
+    assert_eq!(
+        Some(&json!("987654321")),
+        call.argument_value("input.NationalID")
+    );
+    assert_eq!(Some(&json!(720)), call.argument_value("input.Score"));
+    assert!(events.next().await?.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "starts a KurrentDB container; needs Docker"]
+async fn delete_mutations_tombstone_their_stream() -> Result<(), BoxError> {
+    let (_container, connection_string) = start_kurrentdb().await?;
+
+    check_credit(json!({
+        "connection_string": connection_string,
+        "persist_before_response": true,
+        "stream_overrides": { "recordCreditChecked": "credit-check-{loan_id}" },
+        "delete_mutations": {
+            "recordCreditChecked": { "aggregate": "CreditCheck", "stream_deletion": "hard" }
+        },
+        "allow_hard_delete": true
+    }))
+    .await?;
+
+    let client = Client::new(connection_string.parse()?)?;
+    let result = match client
+        .read_stream("credit-check-loan-it-1", &ReadStreamOptions::default())
+        .await
+    {
+        Ok(mut events) => events.next().await.map(|_| ()),
+        Err(error) => Err(error),
+    };
+
+    assert!(
+        matches!(result, Err(kurrentdb::Error::ResourceDeleted)),
+        "{result:?}"
+    );
+    Ok(())
+}